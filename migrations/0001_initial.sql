@@ -30,6 +30,16 @@ CREATE TABLE IF NOT EXISTS message_labels (
     FOREIGN KEY (label_id) REFERENCES labels(id) ON DELETE CASCADE
 );
 
+-- Address book built from synced From/To/Cc headers
+CREATE TABLE IF NOT EXISTS contacts (
+    email TEXT PRIMARY KEY COLLATE NOCASE,
+    name TEXT,
+    times_seen INTEGER NOT NULL DEFAULT 0,
+    times_sent INTEGER NOT NULL DEFAULT 0,
+    last_seen INTEGER NOT NULL DEFAULT 0,
+    last_sent INTEGER NOT NULL DEFAULT 0
+);
+
 -- Performance Indexes
 CREATE INDEX IF NOT EXISTS idx_messages_internal_date ON messages(internal_date DESC);
 CREATE INDEX IF NOT EXISTS idx_messages_thread_id ON messages(thread_id);
@@ -0,0 +1,2 @@
+-- Keep Cc so replies can pick the alias a message was copied to
+ALTER TABLE messages ADD COLUMN cc_address TEXT;
@@ -0,0 +1,14 @@
+-- Messages that have not been accepted by Gmail yet, kept so a failed send
+-- can be retried or reopened instead of lost
+CREATE TABLE IF NOT EXISTS outbox (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    from_address TEXT,
+    to_address TEXT NOT NULL,
+    cc_address TEXT NOT NULL DEFAULT '',
+    bcc_address TEXT NOT NULL DEFAULT '',
+    subject TEXT NOT NULL DEFAULT '',
+    body TEXT NOT NULL DEFAULT '',
+    markdown INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at INTEGER NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Compositions still open when gtui was closed are kept as drafts: they can
+-- be reopened but are never sent without the user finishing them
+ALTER TABLE outbox ADD COLUMN draft INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,50 @@
+use crate::models::{Message, SendAs};
+use crate::undo::UndoableAction;
+
+/// Results of work the main loop handed to a background task. The UI
+/// applies changes optimistically and reconciles when one of these arrives.
+#[derive(Debug)]
+pub enum ActionResult {
+    /// Gmail finished applying a delete or archive that is already shown
+    /// locally. On failure the change is rolled back.
+    Applied {
+        action: UndoableAction,
+        result: Result<(), String>,
+    },
+    /// An older page of a label was fetched from Gmail into the cache;
+    /// `Ok` holds how many messages were new
+    OlderPage {
+        label_id: String,
+        result: Result<usize, String>,
+    },
+    /// Bodies of a conversation synced with headers only were fetched
+    Bodies {
+        thread_id: String,
+        result: Result<(), String>,
+    },
+    /// Gmail refused a read/unread change that is already shown locally;
+    /// `is_read` is the state to restore
+    ReadStateFailed {
+        id: String,
+        is_read: bool,
+        error: String,
+    },
+    /// Gmail refused a label change that is already shown locally. `row` is
+    /// the conversation as it was listed before the change, at `index`.
+    LabelFailed {
+        add: bool,
+        label_id: String,
+        message_ids: Vec<String>,
+        row: Box<Message>,
+        index: usize,
+        error: String,
+    },
+    /// Gmail accepted a message from the outbox
+    Sent { outbox_id: i64 },
+    /// Sending a message failed; it stays in the outbox to retry or edit
+    SendFailed { outbox_id: i64, error: String },
+    /// A background call failed; nothing to roll back, just tell the user
+    Failed(String),
+    /// Send-as aliases fetched after authentication
+    SendAs(Vec<SendAs>),
+}
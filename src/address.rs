@@ -0,0 +1,392 @@
+/// A single `Name <addr>` or bare `addr` entry from an address header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mailbox {
+    pub name: Option<String>,
+    pub email: String,
+}
+
+impl Mailbox {
+    /// Format for display and for the compose fields
+    pub fn to_display_string(&self) -> String {
+        match &self.name {
+            Some(name) if needs_quoting(name) => {
+                format!("\"{}\" <{}>", name.replace('"', "\\\""), self.email)
+            }
+            Some(name) => format!("{} <{}>", name, self.email),
+            None => self.email.clone(),
+        }
+    }
+}
+
+fn needs_quoting(name: &str) -> bool {
+    name.chars()
+        .any(|c| matches!(c, ',' | ';' | '<' | '>' | '@' | '"' | ':' | '(' | ')'))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AddressError {
+    #[error("unterminated quoted string")]
+    UnterminatedQuote,
+    #[error("missing closing '>' in \"{0}\"")]
+    UnterminatedAngle(String),
+    #[error("unexpected text after '>' in \"{0}\"")]
+    TrailingText(String),
+    #[error("display name \"{0}\" must be quoted")]
+    UnquotedSpecials(String),
+    #[error("\"{0}\" is not a valid email address")]
+    InvalidAddress(String),
+}
+
+/// Split a header value such as `"Doe, Jane" <jane@x.com>, bob@y.com` into
+/// mailboxes, skipping entries that don't parse. Used for headers we receive,
+/// which are often not strictly RFC 5322 compliant.
+pub fn parse_address_list(value: &str) -> Vec<Mailbox> {
+    split_address_list(value)
+        .unwrap_or_else(|_| vec![value.to_string()])
+        .iter()
+        .filter_map(|entry| {
+            parse_mailbox(entry)
+                .ok()
+                .or_else(|| parse_mailbox_lenient(entry))
+        })
+        .collect()
+}
+
+/// Parse an RFC 5322 address list as typed by the user, rejecting anything
+/// that is not a valid mailbox. Empty entries (such as a trailing comma) are
+/// ignored, and `;` is accepted as a separator like `,`.
+pub fn parse_address_list_strict(value: &str) -> Result<Vec<Mailbox>, AddressError> {
+    split_address_list(value)?
+        .iter()
+        .map(|entry| parse_mailbox(entry))
+        .collect()
+}
+
+/// Split on separators that are outside quotes, comments and angle brackets
+fn split_address_list(value: &str) -> Result<Vec<String>, AddressError> {
+    let mut entries = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut in_angle = false;
+    let mut comment_depth = 0usize;
+    let mut escaped = false;
+
+    for c in value.chars() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if in_quotes || comment_depth > 0 => {
+                current.push(c);
+                escaped = true;
+            }
+            '"' if comment_depth == 0 => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            '(' if !in_quotes => {
+                comment_depth += 1;
+                current.push(c);
+            }
+            ')' if !in_quotes && comment_depth > 0 => {
+                comment_depth -= 1;
+                current.push(c);
+            }
+            '<' if !in_quotes && comment_depth == 0 => {
+                in_angle = true;
+                current.push(c);
+            }
+            '>' if !in_quotes && comment_depth == 0 => {
+                in_angle = false;
+                current.push(c);
+            }
+            ',' | ';' if !in_quotes && !in_angle && comment_depth == 0 => {
+                entries.push(std::mem::take(&mut current));
+            }
+            _ => current.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(AddressError::UnterminatedQuote);
+    }
+    if in_angle {
+        return Err(AddressError::UnterminatedAngle(current.trim().to_string()));
+    }
+    entries.push(current);
+
+    Ok(entries
+        .into_iter()
+        .map(|e| e.trim().to_string())
+        .filter(|e| !e.is_empty())
+        .collect())
+}
+
+/// Parse `display-name <addr-spec>` or a bare `addr-spec`
+fn parse_mailbox(entry: &str) -> Result<Mailbox, AddressError> {
+    let entry = strip_comments(entry);
+    let entry = entry.trim();
+
+    let (name, email) = match find_unquoted(entry, '<') {
+        Some(start) => {
+            let Some(end) = entry[start..].find('>').map(|i| start + i) else {
+                return Err(AddressError::UnterminatedAngle(entry.to_string()));
+            };
+            if !entry[end + 1..].trim().is_empty() {
+                return Err(AddressError::TrailingText(entry.to_string()));
+            }
+            let name = parse_display_name(entry[..start].trim())?;
+            (name, entry[start + 1..end].trim())
+        }
+        None => (None, entry),
+    };
+
+    if !is_valid_addr_spec(email) {
+        return Err(AddressError::InvalidAddress(email.to_string()));
+    }
+    Ok(Mailbox {
+        name,
+        email: email.to_string(),
+    })
+}
+
+/// Best-effort fallback for received headers that don't parse strictly
+fn parse_mailbox_lenient(entry: &str) -> Option<Mailbox> {
+    let entry = entry.trim();
+    let (name, email) = match (entry.rfind('<'), entry.rfind('>')) {
+        (Some(start), Some(end)) if start < end => {
+            let name = unquote(entry[..start].trim());
+            (
+                (!name.is_empty()).then_some(name),
+                entry[start + 1..end].trim().to_string(),
+            )
+        }
+        _ => (None, entry.to_string()),
+    };
+
+    if !email.contains('@') {
+        return None;
+    }
+    Some(Mailbox { name, email })
+}
+
+fn parse_display_name(phrase: &str) -> Result<Option<String>, AddressError> {
+    if phrase.is_empty() {
+        return Ok(None);
+    }
+    if phrase.starts_with('"') && phrase.ends_with('"') && phrase.len() >= 2 {
+        let name = unquote(phrase);
+        return Ok((!name.is_empty()).then_some(name));
+    }
+    // An unquoted phrase is a run of atoms; `.` is tolerated (obs-phrase)
+    if phrase
+        .chars()
+        .any(|c| !(is_atext(c) || c == ' ' || c == '\t' || c == '.'))
+    {
+        return Err(AddressError::UnquotedSpecials(phrase.to_string()));
+    }
+    Ok(Some(
+        phrase.split_whitespace().collect::<Vec<_>>().join(" "),
+    ))
+}
+
+/// `local-part@domain` where the local part is a dot-atom or quoted string and
+/// the domain is a dotted host name or an address literal
+fn is_valid_addr_spec(addr: &str) -> bool {
+    let Some(at) = addr.rfind('@') else {
+        return false;
+    };
+    let (local, domain) = (&addr[..at], &addr[at + 1..]);
+
+    let local_ok = if local.len() >= 2 && local.starts_with('"') && local.ends_with('"') {
+        let inner = &local[1..local.len() - 1];
+        !inner.chars().any(|c| c == '\r' || c == '\n') && !inner.ends_with('\\')
+    } else {
+        is_dot_atom(local)
+    };
+
+    let domain_ok = if domain.starts_with('[') && domain.ends_with(']') {
+        domain.len() > 2
+            && domain[1..domain.len() - 1]
+                .chars()
+                .all(|c| c.is_ascii_graphic() && c != '[' && c != ']' && c != '\\')
+    } else {
+        let labels: Vec<&str> = domain.split('.').collect();
+        labels.len() >= 2
+            && labels.iter().all(|label| {
+                !label.is_empty()
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+            })
+    };
+
+    local_ok && domain_ok
+}
+
+fn is_dot_atom(s: &str) -> bool {
+    !s.is_empty()
+        && s.split('.')
+            .all(|atom| !atom.is_empty() && atom.chars().all(is_atext))
+}
+
+/// RFC 5322 `atext`, extended with non-ASCII characters as in RFC 6532
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c) || !c.is_ascii()
+}
+
+fn find_unquoted(s: &str, needle: char) -> Option<usize> {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' && in_quotes {
+            escaped = true;
+        } else if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == needle && !in_quotes {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Remove `(comments)` outside quoted strings
+fn strip_comments(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_quotes = false;
+    let mut depth = 0usize;
+    let mut escaped = false;
+    for c in s.chars() {
+        if escaped {
+            escaped = false;
+            if depth == 0 {
+                out.push(c);
+            }
+            continue;
+        }
+        match c {
+            '\\' if in_quotes || depth > 0 => {
+                escaped = true;
+                if depth == 0 {
+                    out.push(c);
+                }
+            }
+            '"' if depth == 0 => {
+                in_quotes = !in_quotes;
+                out.push(c);
+            }
+            '(' if !in_quotes => depth += 1,
+            ')' if !in_quotes && depth > 0 => depth -= 1,
+            _ if depth == 0 => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+fn unquote(name: &str) -> String {
+    let inner = name
+        .strip_prefix('"')
+        .and_then(|n| n.strip_suffix('"'))
+        .unwrap_or(name);
+    inner.replace("\\\"", "\"").replace("\\\\", "\\")
+}
+
+/// Byte offset in `before_cursor` where the address being typed starts, and
+/// the partially typed text itself
+pub fn completion_token(before_cursor: &str) -> (usize, &str) {
+    let start = before_cursor.rfind([',', ';']).map(|i| i + 1).unwrap_or(0);
+    let token = &before_cursor[start..];
+    let trimmed = token.trim_start();
+    (start + token.len() - trimmed.len(), trimmed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address_list_names_and_bare() {
+        let parsed = parse_address_list("\"Doe, Jane\" <jane@x.com>, bob@y.com;Carol <c@z.org>");
+        assert_eq!(
+            parsed,
+            vec![
+                Mailbox {
+                    name: Some("Doe, Jane".to_string()),
+                    email: "jane@x.com".to_string()
+                },
+                Mailbox {
+                    name: None,
+                    email: "bob@y.com".to_string()
+                },
+                Mailbox {
+                    name: Some("Carol".to_string()),
+                    email: "c@z.org".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display_string_quotes_when_needed() {
+        let mailbox = Mailbox {
+            name: Some("Doe, Jane".to_string()),
+            email: "jane@x.com".to_string(),
+        };
+        assert_eq!(mailbox.to_display_string(), "\"Doe, Jane\" <jane@x.com>");
+        assert_eq!(
+            parse_address_list(&mailbox.to_display_string()),
+            vec![mailbox]
+        );
+    }
+
+    #[test]
+    fn test_strict_accepts_valid_lists() {
+        let parsed = parse_address_list_strict(
+            "Jane Q. Doe <jane@x.com>, \"Smith, Bob\" <bob@mail.y.co.uk>, (work) c@z.org,",
+        )
+        .unwrap();
+        let emails: Vec<&str> = parsed.iter().map(|m| m.email.as_str()).collect();
+        assert_eq!(emails, vec!["jane@x.com", "bob@mail.y.co.uk", "c@z.org"]);
+        assert_eq!(parsed[0].name.as_deref(), Some("Jane Q. Doe"));
+        assert_eq!(parsed[1].name.as_deref(), Some("Smith, Bob"));
+        assert_eq!(parsed[2].name, None);
+    }
+
+    #[test]
+    fn test_strict_rejects_malformed_addresses() {
+        for bad in [
+            "jane@",
+            "jane.x.com",
+            "jane@x",
+            "jane..doe@x.com",
+            "jane@-x.com",
+            "Jane <jane@x.com",
+            "Jane <jane@x.com> extra",
+            "Doe, Jane <jane@x.com>",
+            "\"Jane <jane@x.com>",
+        ] {
+            assert!(
+                parse_address_list_strict(bad).is_err(),
+                "accepted {:?}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_strict_accepts_quoted_local_and_literal_domain() {
+        assert!(parse_address_list_strict("\"john doe\"@x.com").is_ok());
+        assert!(parse_address_list_strict("root@[192.168.0.1]").is_ok());
+    }
+
+    #[test]
+    fn test_completion_token() {
+        assert_eq!(completion_token("al"), (0, "al"));
+        assert_eq!(completion_token("a@x.com, bo"), (9, "bo"));
+        assert_eq!(completion_token("a@x.com, "), (9, ""));
+    }
+}
@@ -24,18 +24,27 @@ pub const SCOPES: &[&str] = &[
     "https://www.googleapis.com/auth/gmail.settings.basic",
 ];
 
+/// Extra scopes requested when People API contact enrichment is enabled
+pub const PEOPLE_SCOPES: &[&str] = &[
+    "https://www.googleapis.com/auth/contacts.readonly",
+    "https://www.googleapis.com/auth/contacts.other.readonly",
+];
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct TokenData {
     tokens: Vec<TokenInfo>,
 }
 
-pub struct RingStorage;
+/// The keychain entry holding one account's token. The default account
+/// keeps the original entry name so existing sign-ins carry over.
+pub struct RingStorage {
+    key: String,
+}
 
 #[async_trait]
 impl TokenStorage for RingStorage {
     async fn set(&self, _scopes: &[&str], token: TokenInfo) -> Result<()> {
-        let entry =
-            Entry::new(APP_NAME, TOKEN_KEY).map_err(|e| anyhow::anyhow!("Keyring error: {}", e))?;
+        let entry = self.entry()?;
 
         let mut data = self.get_all().await.unwrap_or_default();
         data.tokens.clear();
@@ -59,9 +68,20 @@ impl TokenStorage for RingStorage {
 }
 
 impl RingStorage {
+    pub fn new(account: Option<&str>) -> Self {
+        let key = match account {
+            Some(name) => format!("{}:{}", TOKEN_KEY, name),
+            None => TOKEN_KEY.to_string(),
+        };
+        Self { key }
+    }
+
+    fn entry(&self) -> Result<Entry> {
+        Entry::new(APP_NAME, &self.key).map_err(|e| anyhow::anyhow!("Keyring error: {}", e))
+    }
+
     async fn get_all(&self) -> Result<TokenData> {
-        let entry =
-            Entry::new(APP_NAME, TOKEN_KEY).map_err(|e| anyhow::anyhow!("Keyring error: {}", e))?;
+        let entry = self.entry()?;
 
         match entry.get_password() {
             Ok(serialized) => {
@@ -73,8 +93,7 @@ impl RingStorage {
     }
 
     pub async fn clear_token(&self) -> Result<()> {
-        let entry =
-            Entry::new(APP_NAME, TOKEN_KEY).map_err(|e| anyhow::anyhow!("Keyring error: {}", e))?;
+        let entry = self.entry()?;
         match entry.delete_password() {
             Ok(_) => Ok(()),
             Err(keyring::Error::NoEntry) => Ok(()),
@@ -108,13 +127,15 @@ pub struct Authenticator;
 
 impl Authenticator {
     pub async fn load_secret<P: AsRef<Path>>(path: P) -> Result<ApplicationSecret> {
+        let path = path.as_ref();
         read_application_secret(path)
             .await
-            .context("Failed to read application secret")
+            .with_context(|| format!("Failed to read OAuth credentials from {}", path.display()))
     }
 
     pub async fn authenticate(
         secret: ApplicationSecret,
+        storage: RingStorage,
         delegate: TuiDelegate,
     ) -> Result<
         oauth2::authenticator::Authenticator<
@@ -123,7 +144,7 @@ impl Authenticator {
     > {
         let auth =
             InstalledFlowAuthenticator::builder(secret, InstalledFlowReturnMethod::HTTPRedirect)
-                .with_storage(Box::new(RingStorage))
+                .with_storage(Box::new(storage))
                 .flow_delegate(Box::new(delegate))
                 .build()
                 .await
@@ -2,23 +2,44 @@ use sqlx::Row;
 use sqlx::sqlite::SqlitePoolOptions;
 use std::env;
 
+// Only the path lookup is needed here, not the rest of gtui
+#[allow(dead_code)]
+#[path = "../paths.rs"]
+mod paths;
+
+/// Flags shared with gtui that take a value
+const PATH_FLAGS: &[&str] = &["--config", "--data-dir", "--account"];
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <search_query>", args[0]);
+    let mut positional = Vec::new();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if PATH_FLAGS.contains(&arg.as_str()) {
+            iter.next();
+        } else {
+            positional.push(arg);
+        }
+    }
+    let Some(query) = positional.first() else {
+        eprintln!(
+            "Usage: {} [--data-dir <dir>] [--account <name>] <search_query>",
+            args[0]
+        );
         eprintln!("Search query matches against Sender or Subject.");
         std::process::exit(1);
-    }
-
-    let query = &args[1];
+    };
     let search_term = format!("%{}%", query);
 
-    let database_url = "sqlite://gtui.db";
+    // The same cache gtui uses, opened read-only so a wrong path fails
+    // instead of creating an empty database
+    let paths = paths::Paths::from_env(&args)?;
+    let database_url = format!("sqlite:{}?mode=ro", paths.database().display());
     let pool = SqlitePoolOptions::new()
-        .connect(database_url)
+        .connect(&database_url)
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to connect to database: {}", e))?;
+        .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", paths.database().display(), e))?;
 
     let row = sqlx::query(
         "SELECT id, from_address, subject, internal_date, body_plain, body_html 
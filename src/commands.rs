@@ -0,0 +1,698 @@
+use crate::config::{Keybindings, split_key};
+use crate::fuzzy;
+use crate::paths;
+use chrono::NaiveDate;
+
+/// Everything the user can do while browsing, whether from a key or from the
+/// command palette
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    PrevPanel,
+    NextPanel,
+    GrowPanel,
+    ShrinkPanel,
+    ToggleZoom,
+    ToggleLabels,
+    MoveDown,
+    MoveUp,
+    MarkRead,
+    Reply,
+    Forward,
+    NewMessage,
+    Delete,
+    Archive,
+    Undo,
+    ViewInPager,
+    CommandPalette,
+    Help,
+    ToggleConsole,
+    Suspend,
+    GoToInbox,
+    PageUp,
+    PageDown,
+    /// Select the newest conversation from on or before this day
+    JumpToDate(NaiveDate),
+    /// First row of the focused panel
+    GoTop,
+    /// Last row of the focused panel
+    GoBottom,
+    OpenInBrowser,
+    SyncNow,
+    /// Search the local cache; an empty query goes back to the label
+    Search(String),
+    /// Add or remove a label, by name, on the selected conversation
+    Label {
+        add: bool,
+        name: String,
+    },
+    /// Send every message in the outbox again
+    RetryUnsent,
+    /// Reopen the newest unsent message in the composition panel
+    EditUnsent,
+    /// Restart gtui signed in to another account, by name
+    SwitchAccount(String),
+    // Composing
+    SendMessage,
+    ExternalEditor,
+    ToggleCcBcc,
+    CycleFrom,
+    ToggleMarkdown,
+    TogglePreview,
+}
+
+impl Action {
+    /// Whether a count prefix (`5j`) runs the action that many times
+    pub fn repeats(&self) -> bool {
+        matches!(
+            self,
+            Action::MoveDown
+                | Action::MoveUp
+                | Action::PageDown
+                | Action::PageUp
+                | Action::GrowPanel
+                | Action::ShrinkPanel
+                | Action::Delete
+                | Action::Archive
+                | Action::Undo
+        )
+    }
+}
+
+/// Reads one action's bindings out of `Keybindings`
+pub type KeysFor = fn(&Keybindings) -> &[String];
+
+/// A palette entry. `keys` reads the current binding so the palette always
+/// shows what is configured.
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// Shown in the palette, e.g. `label add|remove <name>`
+    pub usage: &'static str,
+    pub description: &'static str,
+    /// Heading the help overlay lists this under
+    pub group: &'static str,
+    /// The `[keybindings]` field `keys` reads, which also names the command
+    /// in `[keybindings.details]`; `None` for commands only run by name
+    pub binding: Option<&'static str>,
+    pub keys: KeysFor,
+    parse: fn(&str) -> Result<Action, String>,
+}
+
+impl CommandSpec {
+    pub fn takes_args(&self) -> bool {
+        self.usage.contains('<')
+    }
+
+    /// What a key bound to this command does; `None` for commands that need
+    /// arguments
+    pub fn action(&self) -> Option<Action> {
+        (self.parse)("").ok()
+    }
+}
+
+pub const NAVIGATION: &str = "Panels & Navigation";
+pub const CONVERSATION: &str = "Conversation";
+pub const GENERAL: &str = "General";
+pub const COMPOSE: &str = "Composing";
+
+/// A key that acts while composing. Only the configurable ones are listed;
+/// Esc, Tab and Enter are fixed.
+pub struct ComposeKey {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub keys: KeysFor,
+    pub action: Action,
+}
+
+pub const COMPOSE_KEYS: &[ComposeKey] = &[
+    ComposeKey {
+        name: "send_message",
+        description: "Send the message",
+        keys: |k| &k.send_message,
+        action: Action::SendMessage,
+    },
+    ComposeKey {
+        name: "external_editor",
+        description: "Edit the draft in $EDITOR",
+        keys: |k| &k.external_editor,
+        action: Action::ExternalEditor,
+    },
+    ComposeKey {
+        name: "toggle_cc_bcc",
+        description: "Show or hide Cc and Bcc",
+        keys: |k| &k.toggle_cc_bcc,
+        action: Action::ToggleCcBcc,
+    },
+    ComposeKey {
+        name: "cycle_from",
+        description: "Change the From alias",
+        keys: |k| &k.cycle_from,
+        action: Action::CycleFrom,
+    },
+    ComposeKey {
+        name: "toggle_markdown",
+        description: "Send the body as Markdown",
+        keys: |k| &k.toggle_markdown,
+        action: Action::ToggleMarkdown,
+    },
+    ComposeKey {
+        name: "toggle_preview",
+        description: "Show the Markdown preview",
+        keys: |k| &k.toggle_preview,
+        action: Action::TogglePreview,
+    },
+];
+
+fn no_args(args: &str, action: Action) -> Result<Action, String> {
+    if args.is_empty() {
+        Ok(action)
+    } else {
+        Err(format!("Unexpected arguments: {}", args))
+    }
+}
+
+fn unbound(_: &Keybindings) -> &[String] {
+    &[]
+}
+
+/// Every command, in the order keys are matched and the palette lists them
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "prev-panel",
+        usage: "prev-panel",
+        description: "Focus the panel to the left",
+        group: NAVIGATION,
+        binding: Some("prev_panel"),
+        keys: |k| &k.prev_panel,
+        parse: |a| no_args(a, Action::PrevPanel),
+    },
+    CommandSpec {
+        name: "next-panel",
+        usage: "next-panel",
+        description: "Focus the panel to the right",
+        group: NAVIGATION,
+        binding: Some("next_panel"),
+        keys: |k| &k.next_panel,
+        parse: |a| no_args(a, Action::NextPanel),
+    },
+    CommandSpec {
+        name: "grow-panel",
+        usage: "grow-panel",
+        description: "Make the focused panel wider",
+        group: NAVIGATION,
+        binding: Some("grow_panel"),
+        keys: |k| &k.grow_panel,
+        parse: |a| no_args(a, Action::GrowPanel),
+    },
+    CommandSpec {
+        name: "shrink-panel",
+        usage: "shrink-panel",
+        description: "Make the focused panel narrower",
+        group: NAVIGATION,
+        binding: Some("shrink_panel"),
+        keys: |k| &k.shrink_panel,
+        parse: |a| no_args(a, Action::ShrinkPanel),
+    },
+    CommandSpec {
+        name: "zoom",
+        usage: "zoom",
+        description: "Show only the focused panel",
+        group: NAVIGATION,
+        binding: Some("toggle_zoom"),
+        keys: |k| &k.toggle_zoom,
+        parse: |a| no_args(a, Action::ToggleZoom),
+    },
+    CommandSpec {
+        name: "toggle-labels",
+        usage: "toggle-labels",
+        description: "Show or hide the Labels panel",
+        group: NAVIGATION,
+        binding: Some("toggle_labels"),
+        keys: |k| &k.toggle_labels,
+        parse: |a| no_args(a, Action::ToggleLabels),
+    },
+    CommandSpec {
+        name: "down",
+        usage: "down",
+        description: "Move down in the focused panel",
+        group: NAVIGATION,
+        binding: Some("move_down"),
+        keys: |k| &k.move_down,
+        parse: |a| no_args(a, Action::MoveDown),
+    },
+    CommandSpec {
+        name: "up",
+        usage: "up",
+        description: "Move up in the focused panel",
+        group: NAVIGATION,
+        binding: Some("move_up"),
+        keys: |k| &k.move_up,
+        parse: |a| no_args(a, Action::MoveUp),
+    },
+    CommandSpec {
+        name: "top",
+        usage: "top",
+        description: "Jump to the top of the focused panel",
+        group: NAVIGATION,
+        binding: Some("go_top"),
+        keys: |k| &k.go_top,
+        parse: |a| no_args(a, Action::GoTop),
+    },
+    CommandSpec {
+        name: "bottom",
+        usage: "bottom",
+        description: "Jump to the bottom of the focused panel",
+        group: NAVIGATION,
+        binding: Some("go_bottom"),
+        keys: |k| &k.go_bottom,
+        parse: |a| no_args(a, Action::GoBottom),
+    },
+    CommandSpec {
+        name: "page-down",
+        usage: "page-down",
+        description: "Move down one screen",
+        group: NAVIGATION,
+        binding: Some("page_down"),
+        keys: |k| &k.page_down,
+        parse: |a| no_args(a, Action::PageDown),
+    },
+    CommandSpec {
+        name: "page-up",
+        usage: "page-up",
+        description: "Move up one screen",
+        group: NAVIGATION,
+        binding: Some("page_up"),
+        keys: |k| &k.page_up,
+        parse: |a| no_args(a, Action::PageUp),
+    },
+    CommandSpec {
+        name: "date",
+        usage: "date <yyyy-mm-dd>",
+        description: "Jump to mail from a date, fetching older mail if needed",
+        group: NAVIGATION,
+        binding: None,
+        keys: unbound,
+        parse: |a| {
+            NaiveDate::parse_from_str(a, "%Y-%m-%d")
+                .map(Action::JumpToDate)
+                .map_err(|_| "Usage: date <yyyy-mm-dd>".to_string())
+        },
+    },
+    CommandSpec {
+        name: "inbox",
+        usage: "inbox",
+        description: "Go to the Inbox",
+        group: NAVIGATION,
+        binding: Some("go_inbox"),
+        keys: |k| &k.go_inbox,
+        parse: |a| no_args(a, Action::GoToInbox),
+    },
+    CommandSpec {
+        name: "toggle-read",
+        usage: "toggle-read",
+        description: "Mark the conversation read or unread",
+        group: CONVERSATION,
+        binding: Some("mark_read"),
+        keys: |k| &k.mark_read,
+        parse: |a| no_args(a, Action::MarkRead),
+    },
+    CommandSpec {
+        name: "reply",
+        usage: "reply",
+        description: "Reply to the conversation",
+        group: CONVERSATION,
+        binding: Some("reply"),
+        keys: |k| &k.reply,
+        parse: |a| no_args(a, Action::Reply),
+    },
+    CommandSpec {
+        name: "forward",
+        usage: "forward",
+        description: "Forward the conversation",
+        group: CONVERSATION,
+        binding: Some("forward"),
+        keys: |k| &k.forward,
+        parse: |a| no_args(a, Action::Forward),
+    },
+    CommandSpec {
+        name: "compose",
+        usage: "compose",
+        description: "Write a new message",
+        group: GENERAL,
+        binding: Some("new_message"),
+        keys: |k| &k.new_message,
+        parse: |a| no_args(a, Action::NewMessage),
+    },
+    CommandSpec {
+        name: "delete",
+        usage: "delete",
+        description: "Move the conversation to Trash",
+        group: CONVERSATION,
+        binding: Some("delete"),
+        keys: |k| &k.delete,
+        parse: |a| no_args(a, Action::Delete),
+    },
+    CommandSpec {
+        name: "archive",
+        usage: "archive",
+        description: "Archive the conversation",
+        group: CONVERSATION,
+        binding: Some("archive"),
+        keys: |k| &k.archive,
+        parse: |a| no_args(a, Action::Archive),
+    },
+    CommandSpec {
+        name: "undo",
+        usage: "undo",
+        description: "Undo the last delete or archive",
+        group: CONVERSATION,
+        binding: Some("undo"),
+        keys: |k| &k.undo,
+        parse: |a| no_args(a, Action::Undo),
+    },
+    CommandSpec {
+        name: "pager",
+        usage: "pager",
+        description: "Read the conversation in $PAGER",
+        group: CONVERSATION,
+        binding: Some("view_in_pager"),
+        keys: |k| &k.view_in_pager,
+        parse: |a| no_args(a, Action::ViewInPager),
+    },
+    CommandSpec {
+        name: "palette",
+        usage: "palette",
+        description: "Open this command palette",
+        group: GENERAL,
+        binding: Some("command_palette"),
+        keys: |k| &k.command_palette,
+        parse: |a| no_args(a, Action::CommandPalette),
+    },
+    CommandSpec {
+        name: "open-in-browser",
+        usage: "open-in-browser",
+        description: "Open the conversation in Gmail on the web",
+        group: CONVERSATION,
+        binding: None,
+        keys: unbound,
+        parse: |a| no_args(a, Action::OpenInBrowser),
+    },
+    CommandSpec {
+        name: "sync",
+        usage: "sync",
+        description: "Sync with Gmail now",
+        group: GENERAL,
+        binding: Some("sync_now"),
+        keys: |k| &k.sync_now,
+        parse: |a| no_args(a, Action::SyncNow),
+    },
+    CommandSpec {
+        name: "search",
+        usage: "search <query>",
+        description: "Search cached mail (from:, to:, subject:)",
+        group: GENERAL,
+        binding: None,
+        keys: unbound,
+        parse: |a| Ok(Action::Search(a.to_string())),
+    },
+    CommandSpec {
+        name: "label",
+        usage: "label add|remove <name>",
+        description: "Add or remove a label on the conversation",
+        group: CONVERSATION,
+        binding: None,
+        keys: unbound,
+        parse: |a| {
+            let (op, name) = a.split_once(char::is_whitespace).unwrap_or((a, ""));
+            let name = name.trim().to_string();
+            match op {
+                _ if name.is_empty() => Err("Usage: label add|remove <name>".to_string()),
+                "add" => Ok(Action::Label { add: true, name }),
+                "remove" | "rm" => Ok(Action::Label { add: false, name }),
+                _ => Err("Usage: label add|remove <name>".to_string()),
+            }
+        },
+    },
+    CommandSpec {
+        name: "outbox",
+        usage: "outbox retry|edit",
+        description: "Retry unsent messages, or reopen the newest",
+        group: GENERAL,
+        binding: None,
+        keys: unbound,
+        parse: |a| match a.trim() {
+            "retry" => Ok(Action::RetryUnsent),
+            "edit" => Ok(Action::EditUnsent),
+            _ => Err("Usage: outbox retry|edit".to_string()),
+        },
+    },
+    CommandSpec {
+        name: "account",
+        usage: "account <name>",
+        description: "Switch to another Gmail account (default is the first)",
+        group: GENERAL,
+        binding: None,
+        keys: unbound,
+        parse: |a| match a.trim() {
+            name if paths::is_valid_account(name) => Ok(Action::SwitchAccount(name.to_string())),
+            "" => Err("Usage: account <name>".to_string()),
+            name => Err(format!(
+                "Invalid account name {:?}: use letters, digits and . _ @ + -",
+                name
+            )),
+        },
+    },
+    CommandSpec {
+        name: "help",
+        usage: "help",
+        description: "Show every keybinding",
+        group: GENERAL,
+        binding: Some("help"),
+        keys: |k| &k.help,
+        parse: |a| no_args(a, Action::Help),
+    },
+    CommandSpec {
+        name: "console",
+        usage: "console",
+        description: "Show or hide the log console",
+        group: GENERAL,
+        binding: Some("toggle_console"),
+        keys: |k| &k.toggle_console,
+        parse: |a| no_args(a, Action::ToggleConsole),
+    },
+    CommandSpec {
+        name: "suspend",
+        usage: "suspend",
+        description: "Suspend gtui to the shell; resume with fg",
+        group: GENERAL,
+        binding: Some("suspend"),
+        keys: |k| &k.suspend,
+        parse: |a| no_args(a, Action::Suspend),
+    },
+    CommandSpec {
+        name: "quit",
+        usage: "quit",
+        description: "Quit gtui",
+        group: GENERAL,
+        binding: Some("quit"),
+        keys: |k| &k.quit,
+        parse: |a| no_args(a, Action::Quit),
+    },
+];
+
+/// A binding as shown to the user, e.g. `ctrl-s` becomes `Ctrl+S`
+pub fn display_key(binding: &str) -> String {
+    let (prefix, base) = split_key(binding);
+    let mut shown: Vec<String> = prefix
+        .split('-')
+        .filter(|m| !m.is_empty())
+        .map(|m| {
+            let mut chars = m.chars();
+            chars
+                .next()
+                .map(|c| c.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        })
+        .collect();
+    shown.push(match base {
+        " " => "Space".to_string(),
+        b if !shown.is_empty() && b.chars().count() == 1 => b.to_uppercase(),
+        b => b.to_string(),
+    });
+    shown.join("+")
+}
+
+/// Every binding for one action, e.g. `Backspace/D`
+pub fn display_keys(bindings: &[String]) -> String {
+    bindings
+        .iter()
+        .map(|b| display_key(b))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Help overlay contents: (heading, [(keys, description)]), built from the
+/// loaded keybindings so rebinding a key updates the help with it
+pub fn help_sections(
+    keybindings: &Keybindings,
+) -> Vec<(&'static str, Vec<(String, &'static str)>)> {
+    let mut sections: Vec<(&'static str, Vec<(String, &'static str)>)> =
+        [NAVIGATION, CONVERSATION, GENERAL]
+            .into_iter()
+            .map(|group| {
+                let rows = COMMANDS
+                    .iter()
+                    .filter(|c| c.group == group)
+                    .map(|c| {
+                        let keys = (c.keys)(keybindings);
+                        let keys = if keys.is_empty() {
+                            format!(":{}", c.usage)
+                        } else {
+                            display_keys(keys)
+                        };
+                        (keys, c.description)
+                    })
+                    .collect();
+                (group, rows)
+            })
+            .collect();
+
+    let mut compose: Vec<(String, &'static str)> = COMPOSE_KEYS
+        .iter()
+        .map(|c| (display_keys((c.keys)(keybindings)), c.description))
+        .collect();
+    compose.push(("Tab/BackTab".to_string(), "Move between fields"));
+    compose.push(("Esc".to_string(), "Discard the draft"));
+    sections.push((COMPOSE, compose));
+    sections
+}
+
+/// Parse a command line such as `label add Work`. A leading `:` is
+/// optional and command names may be abbreviated to any unique prefix.
+pub fn parse(input: &str) -> Result<Action, String> {
+    let input = input.trim().trim_start_matches(':');
+    let (name, args) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    let spec = find(name)?;
+    (spec.parse)(args.trim())
+}
+
+fn find(name: &str) -> Result<&'static CommandSpec, String> {
+    if let Some(spec) = COMMANDS.iter().find(|c| c.name == name) {
+        return Ok(spec);
+    }
+    let mut candidates = COMMANDS.iter().filter(|c| c.name.starts_with(name));
+    match (candidates.next(), candidates.next()) {
+        (Some(spec), None) if !name.is_empty() => Ok(spec),
+        (Some(_), Some(_)) if !name.is_empty() => Err(format!("Ambiguous command: {}", name)),
+        _ => Err(format!("Unknown command: {}", name)),
+    }
+}
+
+/// Commands matching `query`, best first. Names count for more than
+/// descriptions.
+pub fn search(query: &str) -> Vec<&'static CommandSpec> {
+    let mut matches: Vec<(i64, &CommandSpec)> = COMMANDS
+        .iter()
+        .filter_map(|c| {
+            let by_name = fuzzy::score(query, c.name).map(|s| s + 100);
+            let by_description = fuzzy::score(query, c.description);
+            by_name.max(by_description).map(|s| (s, c))
+        })
+        .collect();
+    // Stable sort keeps table order among equal scores
+    matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    matches.into_iter().map(|(_, c)| c).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_with_arguments() {
+        assert_eq!(
+            parse(":label add Work Stuff"),
+            Ok(Action::Label {
+                add: true,
+                name: "Work Stuff".to_string()
+            })
+        );
+        assert_eq!(
+            parse("search from:bob"),
+            Ok(Action::Search("from:bob".to_string()))
+        );
+        assert_eq!(
+            parse("date 2024-03-01"),
+            Ok(Action::JumpToDate(
+                NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+            ))
+        );
+        assert!(parse("date yesterday").is_err());
+        assert!(parse("label frobnicate Work").is_err());
+        assert_eq!(parse("outbox retry"), Ok(Action::RetryUnsent));
+        assert!(parse("outbox").is_err());
+        assert!(parse("archive now").is_err());
+        assert_eq!(
+            parse("account work@example.com"),
+            Ok(Action::SwitchAccount("work@example.com".to_string()))
+        );
+        assert!(parse("account ../other").is_err());
+    }
+
+    #[test]
+    fn test_parse_accepts_unique_prefixes() {
+        assert_eq!(parse("arch"), Ok(Action::Archive));
+        assert_eq!(parse("s"), Err("Ambiguous command: s".to_string()));
+        assert_eq!(parse("bogus"), Err("Unknown command: bogus".to_string()));
+    }
+
+    #[test]
+    fn test_display_key() {
+        assert_eq!(display_key("ctrl-s"), "Ctrl+S");
+        assert_eq!(display_key(" "), "Space");
+        assert_eq!(display_key("L"), "L");
+        assert_eq!(display_key("BackTab"), "BackTab");
+        assert_eq!(display_key("ctrl--"), "Ctrl+-");
+        assert_eq!(display_key("gg"), "gg");
+    }
+
+    #[test]
+    fn test_help_follows_rebound_keys() {
+        let keybindings = Keybindings {
+            send_message: vec!["ctrl-Enter".to_string(), "F5".to_string()],
+            ..Default::default()
+        };
+        let sections = help_sections(&keybindings);
+        let (_, compose) = sections.iter().find(|(g, _)| *g == COMPOSE).unwrap();
+        assert_eq!(
+            compose[0],
+            ("Ctrl+Enter/F5".to_string(), "Send the message")
+        );
+        let (_, general) = sections.iter().find(|(g, _)| *g == GENERAL).unwrap();
+        assert!(general.contains(&(
+            ":search <query>".to_string(),
+            "Search cached mail (from:, to:, subject:)"
+        )));
+    }
+
+    #[test]
+    fn test_binding_names_match_keybinding_fields() {
+        let defaults = Keybindings::default();
+        let table = toml::Value::try_from(&defaults).unwrap();
+        for command in COMMANDS {
+            let Some(binding) = command.binding else {
+                assert!((command.keys)(&defaults).is_empty(), "{}", command.name);
+                continue;
+            };
+            let field: Vec<String> = table[binding].clone().try_into().unwrap();
+            assert_eq!(field, (command.keys)(&defaults), "{}", command.name);
+        }
+    }
+
+    #[test]
+    fn test_search_ranks_names_first() {
+        let found = search("arch");
+        assert_eq!(found[0].name, "archive");
+        assert!(search("trash").iter().any(|c| c.name == "delete"));
+    }
+}
@@ -1,11 +1,191 @@
+use anyhow::Context;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
+    #[serde(default)]
     pub keybindings: Keybindings,
     #[serde(default)]
     pub signatures: Signatures,
+    #[serde(default)]
+    pub contacts: ContactsConfig,
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    #[serde(default)]
+    pub layout: LayoutConfig,
+    #[serde(default)]
+    pub list: ListConfig,
+    #[serde(default)]
+    pub mouse: MouseConfig,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+}
+
+/// The log file in the data directory. `RUST_LOG` takes precedence.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// `error`, `warn`, `info`, `debug` or `trace`, or a full filter such as
+    /// `gtui=debug,hyper=info`
+    pub level: String,
+    /// Daily log files kept before the oldest is deleted
+    pub max_files: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            max_files: 7,
+        }
+    }
+}
+
+/// What background sync fetches and how often. Labels are named by id
+/// (`INBOX`, `Label_12`) or by name, case-insensitively.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Seconds between sync passes
+    pub interval_secs: u64,
+    /// Most recent messages kept per label, unless `depth` names the label
+    pub default_depth: u32,
+    /// Per-label overrides, e.g. `{ INBOX = 2000 }`
+    pub depth: HashMap<String, u32>,
+    /// Only sync these labels; empty means all of them
+    pub include: Vec<String>,
+    /// Never sync these labels, unless one is being viewed
+    pub exclude: Vec<String>,
+    /// Store only headers and snippet during sync and fetch a message's body
+    /// when its conversation is opened. Search then only covers bodies that
+    /// have been fetched; turn off to keep every body cached for offline
+    /// reading and full-text search.
+    pub body_on_demand: bool,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 30,
+            default_depth: 100,
+            depth: HashMap::from([("INBOX".to_string(), 2000)]),
+            include: Vec::new(),
+            exclude: vec!["SPAM".to_string(), "TRASH".to_string()],
+            body_on_demand: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MouseConfig {
+    /// Capture the mouse for clicks and scrolling. Turn off to keep the
+    /// terminal's own text selection.
+    pub enabled: bool,
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// How each conversation is drawn in the message list
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListConfig {
+    /// One line per conversation instead of three
+    pub compact: bool,
+    /// Custom row template; overrides `compact`. Lines are separated by `\n`.
+    pub template: Option<String>,
+    /// "2h ago" and "Yesterday" instead of full timestamps
+    pub relative_dates: bool,
+}
+
+impl Default for ListConfig {
+    fn default() -> Self {
+        Self {
+            compact: false,
+            template: None,
+            relative_dates: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Orientation {
+    /// Side by side, unless the terminal is narrower than `stack_below`
+    #[default]
+    Auto,
+    Horizontal,
+    Vertical,
+}
+
+/// Panel sizes are percentages of the screen; Details takes the rest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    pub labels_width: u16,
+    pub list_width: u16,
+    pub orientation: Orientation,
+    pub stack_below: u16,
+    pub show_labels: bool,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            labels_width: 10,
+            list_width: 30,
+            orientation: Orientation::Auto,
+            stack_below: 100,
+            show_labels: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemePreset {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+}
+
+/// A built-in preset plus optional per-element colors. Colors are names
+/// (`"blue"`, `"light-red"`), 256-color indexes (`"208"`) or hex (`"#ff8800"`).
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub preset: ThemePreset,
+    pub border: Option<String>,
+    pub border_focused: Option<String>,
+    pub selection: Option<String>,
+    pub unread: Option<String>,
+    pub read: Option<String>,
+    pub status: Option<String>,
+    pub dim: Option<String>,
+    pub accent: Option<String>,
+    pub compose_border: Option<String>,
+    pub compose_focused: Option<String>,
+    pub label_chip: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ContactsConfig {
+    /// Also import saved and "other" contacts from the Google People API.
+    /// Needs the extra contacts scopes, so run with `--reset-token` once after
+    /// enabling it.
+    #[serde(default)]
+    pub people_api: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -14,7 +194,11 @@ pub struct Signatures {
     pub reply: Option<String>,
 }
 
+/// Missing actions fall back to their default keys, so older settings files
+/// keep working as new actions are added. Each entry is one key (`j`,
+/// `ctrl-s`, `PageDown`, `F5`) or a sequence (`gg`, `ctrl-w l`).
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Keybindings {
     pub next_panel: Vec<String>,
     pub prev_panel: Vec<String>,
@@ -29,82 +213,215 @@ pub struct Keybindings {
     pub send_message: Vec<String>,
     pub quit: Vec<String>,
     pub undo: Vec<String>,
+    pub external_editor: Vec<String>,
+    pub view_in_pager: Vec<String>,
+    pub cycle_from: Vec<String>,
+    pub toggle_markdown: Vec<String>,
+    pub toggle_preview: Vec<String>,
+    pub grow_panel: Vec<String>,
+    pub shrink_panel: Vec<String>,
+    pub toggle_zoom: Vec<String>,
+    pub toggle_labels: Vec<String>,
+    pub command_palette: Vec<String>,
+    pub help: Vec<String>,
+    pub toggle_console: Vec<String>,
+    pub suspend: Vec<String>,
+    pub toggle_cc_bcc: Vec<String>,
+    pub sync_now: Vec<String>,
+    pub page_up: Vec<String>,
+    pub page_down: Vec<String>,
+    pub go_top: Vec<String>,
+    pub go_bottom: Vec<String>,
+    pub go_inbox: Vec<String>,
+    /// Overrides used while the Details panel is focused, keyed by the same
+    /// names as the fields above, e.g. `move_down = ["j", "ctrl-d"]`
+    pub details: HashMap<String, Vec<String>>,
 }
 
-impl Default for Config {
+impl Default for Keybindings {
     fn default() -> Self {
         Self {
-            keybindings: Keybindings {
-                next_panel: vec!["l".to_string(), "Right".to_string(), "Tab".to_string()],
-                prev_panel: vec!["h".to_string(), "Left".to_string(), "BackTab".to_string()],
-                move_up: vec!["k".to_string(), "Up".to_string()],
-                move_down: vec!["j".to_string(), "Down".to_string()],
-                mark_read: vec![" ".to_string()],
-                new_message: vec!["n".to_string()],
-                reply: vec!["r".to_string()],
-                forward: vec!["f".to_string()],
-                delete: vec!["Backspace".to_string(), "d".to_string()],
-                archive: vec!["a".to_string()],
-                send_message: vec!["ctrl-s".to_string()],
-                quit: vec!["q".to_string()],
-                undo: vec!["u".to_string()],
-            },
-            signatures: Signatures::default(),
+            next_panel: vec!["l".to_string(), "Right".to_string(), "Tab".to_string()],
+            prev_panel: vec!["h".to_string(), "Left".to_string(), "BackTab".to_string()],
+            move_up: vec!["k".to_string(), "Up".to_string()],
+            move_down: vec!["j".to_string(), "Down".to_string()],
+            mark_read: vec![" ".to_string()],
+            new_message: vec!["n".to_string()],
+            reply: vec!["r".to_string()],
+            forward: vec!["f".to_string()],
+            delete: vec!["Backspace".to_string(), "d".to_string()],
+            archive: vec!["a".to_string()],
+            send_message: vec!["ctrl-s".to_string()],
+            quit: vec!["q".to_string()],
+            undo: vec!["u".to_string()],
+            external_editor: vec!["ctrl-e".to_string()],
+            view_in_pager: vec!["v".to_string()],
+            cycle_from: vec!["ctrl-f".to_string()],
+            toggle_markdown: vec!["ctrl-t".to_string()],
+            toggle_preview: vec!["ctrl-p".to_string()],
+            grow_panel: vec!["]".to_string()],
+            shrink_panel: vec!["[".to_string()],
+            toggle_zoom: vec!["z".to_string()],
+            toggle_labels: vec!["L".to_string()],
+            command_palette: vec![":".to_string()],
+            help: vec!["?".to_string()],
+            toggle_console: vec!["`".to_string()],
+            suspend: vec!["ctrl-z".to_string()],
+            toggle_cc_bcc: vec!["ctrl-b".to_string()],
+            sync_now: vec!["ctrl-r".to_string()],
+            page_up: vec!["PageUp".to_string()],
+            page_down: vec!["PageDown".to_string()],
+            go_top: vec!["gg".to_string(), "Home".to_string()],
+            go_bottom: vec!["G".to_string(), "End".to_string()],
+            go_inbox: vec!["gi".to_string()],
+            details: HashMap::new(),
         }
     }
 }
 
-pub fn parse_key_string(key_str: &str) -> (KeyCode, KeyModifiers) {
-    let mut parts: Vec<&str> = key_str.split('-').collect();
-    let mut modifiers = KeyModifiers::empty();
-
-    // We process from the end to find the base key, then consume prefixes
-    let base_key_str = parts.pop().unwrap_or("");
+/// Parse one key such as `j`, `ctrl-s`, `PageDown` or `F5`. Names of
+/// special keys are case-insensitive; single characters are not, so `L` means
+/// shift-l. A trailing `-` is the minus key itself, as in `-` or `ctrl--`.
+/// Returns `None` for unknown modifiers or key names.
+pub fn parse_key(key_str: &str) -> Option<(KeyCode, KeyModifiers)> {
+    let (prefix, base) = split_key(key_str);
 
-    for part in parts {
+    let mut modifiers = KeyModifiers::empty();
+    for part in prefix.split('-').filter(|p| !p.is_empty()) {
         match part.to_lowercase().as_str() {
             "ctrl" => modifiers.insert(KeyModifiers::CONTROL),
             "alt" => modifiers.insert(KeyModifiers::ALT),
             "shift" => modifiers.insert(KeyModifiers::SHIFT),
             "cmd" | "command" | "super" => modifiers.insert(KeyModifiers::SUPER),
             "meta" => modifiers.insert(KeyModifiers::META),
-            _ => {}
+            _ => return None,
         }
     }
 
-    let code = match base_key_str {
-        "Backspace" => KeyCode::Backspace,
-        "Enter" => KeyCode::Enter,
-        "Left" => KeyCode::Left,
-        "Right" => KeyCode::Right,
-        "Up" => KeyCode::Up,
-        "Down" => KeyCode::Down,
-        "Tab" => KeyCode::Tab,
-        "BackTab" => KeyCode::BackTab,
-        "Esc" => KeyCode::Esc,
-        " " => KeyCode::Char(' '),
-        s if s.len() == 1 => KeyCode::Char(s.chars().next().unwrap()),
-        _ => KeyCode::Null,
+    let mut chars = base.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match base.to_lowercase().as_str() {
+            "backspace" => KeyCode::Backspace,
+            "enter" => KeyCode::Enter,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "esc" => KeyCode::Esc,
+            "space" => KeyCode::Char(' '),
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n @ 1..=24) => KeyCode::F(n),
+                _ => return None,
+            },
+        },
     };
 
-    (code, modifiers)
+    Some((code, modifiers))
 }
 
+/// Split `ctrl-alt-x` into its modifiers, `ctrl-alt`, and the key, `x`
+pub fn split_key(key_str: &str) -> (&str, &str) {
+    if key_str == "-" {
+        ("", "-")
+    } else if let Some(prefix) = key_str.strip_suffix("--") {
+        (prefix, "-")
+    } else {
+        match key_str.rsplit_once('-') {
+            Some((prefix, base)) if !base.is_empty() => (prefix, base),
+            _ => ("", key_str),
+        }
+    }
+}
+
+/// Whether `event` is exactly `code` with `modifiers`. Shift is implied by
+/// the character itself (`L`, `?`), so it only has to match when a binding
+/// asks for it.
+pub fn key_matches(event: KeyEvent, code: KeyCode, modifiers: KeyModifiers) -> bool {
+    let mut pressed = event.modifiers;
+    if matches!(event.code, KeyCode::Char(_) | KeyCode::BackTab)
+        && !modifiers.contains(KeyModifiers::SHIFT)
+    {
+        pressed.remove(KeyModifiers::SHIFT);
+    }
+    event.code == code && pressed == modifiers
+}
+
+/// Whether `event` is one of the single-key `bindings`
 pub fn matches_key(event: KeyEvent, bindings: &[String]) -> bool {
-    bindings.iter().any(|b| {
-        let (code, modifiers) = parse_key_string(b);
-        event.code == code && event.modifiers.contains(modifiers)
-    })
+    bindings
+        .iter()
+        .filter_map(|b| parse_key(b))
+        .any(|(code, modifiers)| key_matches(event, code, modifiers))
 }
 
 impl Config {
-    pub fn load() -> Self {
-        use std::fs;
-        if let Ok(content) = fs::read_to_string("settings.toml") {
-            if let Ok(config) = toml::from_str(&content) {
-                return config;
+    /// Read settings from `path`. A missing file means the defaults; one
+    /// that can't be read or parsed is an error, so it can be reported
+    /// rather than silently replaced.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", path.display()));
             }
-        }
-        Self::default()
+        };
+        // The full error draws the offending line with a caret; one line fits
+        // a notification better
+        toml::from_str(&content).map_err(|e| {
+            let line = e
+                .span()
+                .map(|span| content[..span.start].matches('\n').count() + 1);
+            match line {
+                Some(line) => anyhow::anyhow!(
+                    "{} line {}: {}",
+                    path.display(),
+                    line,
+                    e.message().trim_end()
+                ),
+                None => anyhow::anyhow!("{}: {}", path.display(), e.message().trim_end()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_reports_invalid_settings() {
+        let path = std::env::temp_dir().join(format!("gtui-test-settings-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert!(Config::load(&path).is_ok());
+
+        std::fs::write(&path, "[keybindings]\nquit = \"q\"\n").unwrap();
+        let err = Config::load(&path).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("line 2: invalid type: string \"q\", expected a sequence")
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sync_defaults_match_sample_settings() {
+        let sample: Config = toml::from_str(include_str!("../settings.toml")).unwrap();
+        let defaults = SyncConfig::default();
+        assert_eq!(sample.sync.interval_secs, defaults.interval_secs);
+        assert_eq!(sample.sync.default_depth, defaults.default_depth);
+        assert_eq!(sample.sync.depth, defaults.depth);
+        assert_eq!(sample.sync.include, defaults.include);
+        assert_eq!(sample.sync.exclude, defaults.exclude);
+        assert_eq!(sample.sync.body_on_demand, defaults.body_on_demand);
     }
 }
@@ -0,0 +1,112 @@
+use crate::address::Mailbox;
+use crate::fuzzy;
+use crate::models::Contact;
+
+/// Maximum number of completions shown under an address field
+pub const MAX_COMPLETIONS: usize = 6;
+
+impl Contact {
+    /// Frecency: people we mail count far more than people who mail us, and
+    /// both decay with the age of the last exchange
+    pub fn rank(&self, now_ms: i64) -> f64 {
+        let decay = |last: i64| {
+            let age_days = (now_ms - last).max(0) as f64 / 86_400_000.0;
+            1.0 / (1.0 + age_days / 30.0)
+        };
+        self.times_sent as f64 * 4.0 * decay(self.last_sent)
+            + self.times_seen as f64 * decay(self.last_seen)
+    }
+
+    pub fn mailbox(&self) -> Mailbox {
+        Mailbox {
+            name: self.name.clone(),
+            email: self.email.clone(),
+        }
+    }
+}
+
+/// Contacts matching the partially typed `query`, best first. Addresses
+/// already present in the field are skipped.
+pub fn complete<'c>(
+    contacts: &'c [Contact],
+    query: &str,
+    exclude: &[Mailbox],
+    now_ms: i64,
+) -> Vec<&'c Contact> {
+    if query.trim().is_empty() {
+        return Vec::new();
+    }
+
+    let mut matches: Vec<(i64, f64, &Contact)> = contacts
+        .iter()
+        .filter(|c| {
+            !exclude
+                .iter()
+                .any(|m| m.email.eq_ignore_ascii_case(&c.email))
+        })
+        .filter_map(|c| {
+            let haystack = match &c.name {
+                Some(name) => format!("{} {}", name, c.email),
+                None => c.email.clone(),
+            };
+            fuzzy::score(query, &haystack).map(|s| (s, c.rank(now_ms), c))
+        })
+        .collect();
+
+    matches.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then(b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal))
+    });
+    matches
+        .into_iter()
+        .take(MAX_COMPLETIONS)
+        .map(|(_, _, c)| c)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(email: &str, name: Option<&str>, sent: i64, seen: i64) -> Contact {
+        Contact {
+            email: email.to_string(),
+            name: name.map(str::to_string),
+            times_seen: seen,
+            times_sent: sent,
+            last_seen: 0,
+            last_sent: 0,
+        }
+    }
+
+    #[test]
+    fn test_complete_matches_name_or_email() {
+        let contacts = vec![
+            contact("jane@x.com", Some("Jane Doe"), 0, 1),
+            contact("bob@y.com", None, 0, 1),
+        ];
+        let found = complete(&contacts, "doe", &[], 0);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].email, "jane@x.com");
+    }
+
+    #[test]
+    fn test_complete_ranks_frequent_recipients_first() {
+        let contacts = vec![
+            contact("ann@a.com", None, 0, 50),
+            contact("ann@b.com", None, 20, 0),
+        ];
+        let found = complete(&contacts, "ann", &[], 0);
+        assert_eq!(found[0].email, "ann@b.com");
+    }
+
+    #[test]
+    fn test_complete_skips_addresses_already_entered() {
+        let contacts = vec![contact("jane@x.com", Some("Jane"), 1, 1)];
+        let exclude = vec![Mailbox {
+            name: None,
+            email: "JANE@x.com".to_string(),
+        }];
+        assert!(complete(&contacts, "jane", &exclude, 0).is_empty());
+    }
+}
@@ -1,26 +1,150 @@
+use crate::address::{Mailbox, parse_address_list};
 use crate::models;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use inflections::case::to_title_case;
 use sqlx::{Row, sqlite::SqlitePool};
+use std::time::Duration;
 
+/// One row per conversation, read back by `thread_summary`
+const THREAD_SUMMARY_COLUMNS: &str = "m.id, m.thread_id, m.snippet, m.from_address, m.to_address, m.subject, MAX(m.internal_date) as latest_date, m.body_plain, m.body_html, m.is_read,
+     EXISTS (
+         SELECT 1 FROM messages m2
+         JOIN message_labels ml2 ON m2.id = ml2.message_id
+         WHERE m2.thread_id = m.thread_id AND ml2.label_id = 'SENT'
+     ) as has_sent_reply,
+     (SELECT COUNT(*) FROM messages m3 WHERE m3.thread_id = m.thread_id) as message_count,
+     (SELECT COUNT(*) FROM messages m3 WHERE m3.thread_id = m.thread_id AND m3.is_read = 0) as unread_count,
+     (SELECT GROUP_CONCAT(DISTINCT ml4.label_id) FROM messages m4
+         JOIN message_labels ml4 ON m4.id = ml4.message_id
+         WHERE m4.thread_id = m.thread_id) as thread_labels,
+     m.cc_address";
+
+/// Schema changes in order; a database at version N has had the first N
+/// applied. Released migrations must never change: add a new one instead.
+/// The first is all `IF NOT EXISTS`, so databases created before versioning
+/// simply start from it.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/0001_initial.sql"),
+    include_str!("../migrations/0002_message_cc.sql"),
+    include_str!("../migrations/0003_outbox.sql"),
+    include_str!("../migrations/0004_outbox_draft.sql"),
+];
+
+fn thread_summary(row: &sqlx::sqlite::SqliteRow) -> models::Message {
+    models::Message {
+        id: row.get(0),
+        thread_id: row.get(1),
+        snippet: row.get(2),
+        from_address: row.get(3),
+        to_address: row.get(4),
+        cc_address: row.get(14),
+        subject: row.get(5),
+        internal_date: row.get(6),
+        body_plain: row.get(7),
+        body_html: row.get(8),
+        is_read: row.get(9),
+        has_sent_reply: row.get(10),
+        message_count: row.get(11),
+        unread_count: row.get(12),
+        thread_label_ids: row
+            .get::<Option<String>, _>(13)
+            .map(|ids| ids.split(',').map(str::to_string).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Turn a search query into a WHERE clause and its bind values. Every word
+/// must match; each is a prefix match so `inv` finds "invoice".
+fn search_filter(query: &str) -> (String, Vec<String>) {
+    let mut fts = Vec::new();
+    let mut clauses = Vec::new();
+    let mut binds = Vec::new();
+    for word in query.split_whitespace() {
+        let (column, term) = match word.split_once(':') {
+            Some(("from", term)) => (Some("from_address"), term),
+            Some(("subject", term)) => (Some("subject"), term),
+            Some(("to", term)) => {
+                // To isn't in the full-text index
+                clauses.push("m.to_address LIKE ?".to_string());
+                binds.push(format!("%{}%", term));
+                continue;
+            }
+            _ => (None, word),
+        };
+        if term.is_empty() {
+            continue;
+        }
+        let quoted = format!("\"{}\"*", term.replace('"', "\"\""));
+        fts.push(match column {
+            Some(column) => format!("{} : {}", column, quoted),
+            None => quoted,
+        });
+    }
+    if !fts.is_empty() {
+        clauses.insert(
+            0,
+            "m.rowid IN (SELECT rowid FROM messages_fts WHERE messages_fts MATCH ?)".to_string(),
+        );
+        binds.insert(0, fts.join(" "));
+    }
+    if clauses.is_empty() {
+        clauses.push("1 = 1".to_string());
+    }
+    (clauses.join(" AND "), binds)
+}
+
+/// Connections kept open at once. SQLite allows one writer at a time, so more
+/// would only queue on the lock.
+const MAX_CONNECTIONS: u32 = 4;
+/// How long a write waits for another connection's write before failing with
+/// "database is locked"
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The local cache. Clones share one connection pool; open it once and hand
+/// clones to background tasks.
+#[derive(Clone)]
 pub struct Database {
     pool: SqlitePool,
 }
 
 impl Database {
     pub async fn new(database_url: &str) -> Result<Self> {
-        use sqlx::sqlite::SqliteConnectOptions;
+        use sqlx::sqlite::{
+            SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous,
+        };
         use std::str::FromStr;
 
-        let options = SqliteConnectOptions::from_str(database_url)?.create_if_missing(true);
+        // WAL lets the UI read while sync writes
+        let options = SqliteConnectOptions::from_str(database_url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(BUSY_TIMEOUT);
 
-        let pool = SqlitePool::connect_with(options).await?;
+        let pool = SqlitePoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .connect_with(options)
+            .await?;
         Ok(Self { pool })
     }
 
+    /// A fresh in-memory database; one connection, since each in-memory
+    /// connection is its own database
+    #[cfg(test)]
+    pub async fn open_in_memory() -> Self {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let db = Self { pool };
+        db.run_migrations().await.unwrap();
+        db
+    }
+
     pub async fn get_messages_by_thread(&self, thread_id: &str) -> Result<Vec<models::Message>> {
         let rows = sqlx::query(
-            "SELECT id, thread_id, snippet, from_address, to_address, subject, internal_date, body_plain, body_html, is_read 
+            "SELECT id, thread_id, snippet, from_address, to_address, subject, internal_date, body_plain, body_html, is_read, cc_address
              FROM messages 
              WHERE thread_id = ?
              ORDER BY internal_date DESC"
@@ -37,27 +161,57 @@ impl Database {
                 snippet: row.get(2),
                 from_address: row.get(3),
                 to_address: row.get(4),
+                cc_address: row.get(10),
                 subject: row.get(5),
                 internal_date: row.get(6),
                 body_plain: row.get(7),
                 body_html: row.get(8),
                 is_read: row.get(9),
                 has_sent_reply: false, // Not applicable for individual thread messages
+                ..Default::default()
             })
             .collect();
 
         Ok(messages)
     }
 
-
-
+    /// Bring the schema up to date in one transaction, so a failed upgrade
+    /// leaves the database as it was
     pub async fn run_migrations(&self) -> Result<()> {
-        let schema = include_str!("../schema.sql");
-        sqlx::query(schema).execute(&self.pool).await?;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)")
+            .execute(&mut *tx)
+            .await?;
+        let version: i64 =
+            sqlx::query_scalar("SELECT COALESCE(MAX(version), 0) FROM schema_version")
+                .fetch_one(&mut *tx)
+                .await?;
+        if version as usize > MIGRATIONS.len() {
+            anyhow::bail!(
+                "The database is at schema version {}, newer than this build of gtui understands ({})",
+                version,
+                MIGRATIONS.len()
+            );
+        }
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            sqlx::query(migration)
+                .execute(&mut *tx)
+                .await
+                .with_context(|| format!("Failed to apply schema migration {}", index + 1))?;
+        }
+        sqlx::query("DELETE FROM schema_version")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO schema_version (version) VALUES (?)")
+            .bind(MIGRATIONS.len() as i64)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
         Ok(())
     }
 
     pub async fn upsert_labels(&self, labels: &[models::Label]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
         for label in labels {
             sqlx::query(
                 "INSERT INTO labels (id, name, type, color_foreground, color_background) 
@@ -70,9 +224,10 @@ impl Database {
             .bind(&label.label_type)
             .bind(&label.color_foreground)
             .bind(&label.color_background)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
         }
+        tx.commit().await?;
         Ok(())
     }
 
@@ -81,10 +236,11 @@ impl Database {
         messages: &[models::Message],
         label_id: &str,
     ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
         for msg in messages {
             sqlx::query(
-                "INSERT INTO messages (id, thread_id, snippet, from_address, to_address, subject, internal_date, body_plain, body_html, is_read) 
-                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?) 
+                "INSERT INTO messages (id, thread_id, snippet, from_address, to_address, subject, internal_date, body_plain, body_html, is_read, cc_address) 
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) 
                  ON CONFLICT(id) DO UPDATE SET snippet=excluded.snippet, is_read=excluded.is_read, 
                  body_plain=excluded.body_plain, body_html=excluded.body_html"
             )
@@ -94,11 +250,12 @@ impl Database {
             .bind(&msg.from_address)
             .bind(&msg.to_address)
             .bind(&msg.subject)
-            .bind(&msg.internal_date)
+            .bind(msg.internal_date)
             .bind(&msg.body_plain)
             .bind(&msg.body_html)
             .bind(msg.is_read)
-            .execute(&self.pool)
+            .bind(&msg.cc_address)
+            .execute(&mut *tx)
             .await?;
 
             sqlx::query(
@@ -106,12 +263,91 @@ impl Database {
             )
             .bind(&msg.id)
             .bind(label_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Fill in the body of a message synced with headers only
+    pub async fn update_message_body(&self, msg: &models::Message) -> Result<()> {
+        sqlx::query("UPDATE messages SET body_plain = ?, body_html = ? WHERE id = ?")
+            .bind(&msg.body_plain)
+            .bind(&msg.body_html)
+            .bind(&msg.id)
             .execute(&self.pool)
             .await?;
+        Ok(())
+    }
+
+    /// Add the From/To/Cc addresses of freshly fetched messages to the address
+    /// book. Recipients of messages carrying the SENT label count as people we
+    /// mailed, which weighs much more in completion ranking.
+    pub async fn record_contacts(&self, messages: &[models::Message]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for msg in messages {
+            let sent = msg.has_sent_reply;
+            let mut mailboxes: Vec<(Mailbox, bool)> = Vec::new();
+            for (header, is_recipient) in [
+                (&msg.from_address, false),
+                (&msg.to_address, true),
+                (&msg.cc_address, true),
+            ] {
+                for mailbox in parse_address_list(header.as_deref().unwrap_or("")) {
+                    mailboxes.push((mailbox, sent && is_recipient));
+                }
+            }
+
+            for (mailbox, mailed) in mailboxes {
+                sqlx::query(
+                    "INSERT INTO contacts (email, name, times_seen, times_sent, last_seen, last_sent)
+                     VALUES (?, ?, 1, ?, ?, ?)
+                     ON CONFLICT(email) DO UPDATE SET name=COALESCE(excluded.name, contacts.name),
+                     times_seen=contacts.times_seen + 1, times_sent=contacts.times_sent + excluded.times_sent,
+                     last_seen=MAX(contacts.last_seen, excluded.last_seen),
+                     last_sent=MAX(contacts.last_sent, excluded.last_sent)",
+                )
+                .bind(&mailbox.email)
+                .bind(&mailbox.name)
+                .bind(if mailed { 1 } else { 0 })
+                .bind(msg.internal_date)
+                .bind(if mailed { msg.internal_date } else { 0 })
+                .execute(&mut *tx)
+                .await?;
+            }
         }
+        tx.commit().await?;
         Ok(())
     }
 
+    /// Add contacts from the People API; their names take precedence over the
+    /// display names seen in headers
+    pub async fn upsert_people_contacts(&self, mailboxes: &[Mailbox]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for mailbox in mailboxes {
+            sqlx::query(
+                "INSERT INTO contacts (email, name) VALUES (?, ?)
+                 ON CONFLICT(email) DO UPDATE SET name=COALESCE(excluded.name, contacts.name)",
+            )
+            .bind(&mailbox.email)
+            .bind(&mailbox.name)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_contacts(&self) -> Result<Vec<models::Contact>> {
+        let contacts = sqlx::query_as::<_, models::Contact>(
+            "SELECT email, name, times_seen, times_sent, last_seen, last_sent FROM contacts",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(contacts)
+    }
+
     pub async fn get_labels(&self) -> Result<Vec<models::Label>> {
         let rows = sqlx::query(
             "SELECT id, name, type as label_type, color_foreground, color_background FROM labels ORDER BY name ASC"
@@ -151,44 +387,50 @@ impl Database {
         limit: i64,
         offset: i64,
     ) -> Result<Vec<models::Message>> {
-        let rows = sqlx::query(
-            "SELECT m.id, m.thread_id, m.snippet, m.from_address, m.to_address, m.subject, MAX(m.internal_date) as latest_date, m.body_plain, m.body_html, m.is_read,
-             EXISTS (
-                 SELECT 1 FROM messages m2
-                 JOIN message_labels ml2 ON m2.id = ml2.message_id
-                 WHERE m2.thread_id = m.thread_id AND ml2.label_id = 'SENT'
-             ) as has_sent_reply
+        let sql = format!(
+            "SELECT {THREAD_SUMMARY_COLUMNS}
              FROM messages m
              JOIN message_labels ml ON m.id = ml.message_id
              WHERE ml.label_id = ?
              GROUP BY m.thread_id
              ORDER BY latest_date DESC
              LIMIT ? OFFSET ?"
-        )
-        .bind(label_id)
-        .bind(limit)
-        .bind(offset)
-        .fetch_all(&self.pool)
-        .await?;
+        );
+        let rows = sqlx::query(&sql)
+            .bind(label_id)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await?;
 
-        let messages = rows
-            .into_iter()
-            .map(|row| models::Message {
-                id: row.get(0),
-                thread_id: row.get(1),
-                snippet: row.get(2),
-                from_address: row.get(3),
-                to_address: row.get(4),
-                subject: row.get(5),
-                internal_date: row.get(6),
-                body_plain: row.get(7),
-                body_html: row.get(8),
-                is_read: row.get(9),
-                has_sent_reply: row.get(10),
-            })
-            .collect();
+        Ok(rows.iter().map(thread_summary).collect())
+    }
 
-        Ok(messages)
+    /// Conversations with a message matching `query`, newest first. Plain
+    /// words use the full-text index; `from:`, `subject:` and `to:` narrow a
+    /// word to one header.
+    pub async fn search_messages(
+        &self,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<models::Message>> {
+        let (filter, binds) = search_filter(query);
+        let sql = format!(
+            "SELECT {THREAD_SUMMARY_COLUMNS}
+             FROM messages m
+             WHERE {filter}
+             GROUP BY m.thread_id
+             ORDER BY latest_date DESC
+             LIMIT ? OFFSET ?"
+        );
+        let mut q = sqlx::query(&sql);
+        for bind in binds {
+            q = q.bind(bind);
+        }
+        let rows = q.bind(limit).bind(offset).fetch_all(&self.pool).await?;
+
+        Ok(rows.iter().map(thread_summary).collect())
     }
 
     pub async fn get_messages_with_dates_by_label(
@@ -242,33 +484,379 @@ impl Database {
         }
     }
 
-    pub async fn delete_message(&self, id: &str) -> Result<()> {
-        sqlx::query("DELETE FROM message_labels WHERE message_id = ?")
-            .bind(id)
-            .execute(&self.pool)
-            .await?;
-        sqlx::query("DELETE FROM messages WHERE id = ?")
-            .bind(id)
-            .execute(&self.pool)
-            .await?;
+    /// Delete messages and their labels, all or nothing, e.g. every message
+    /// of a thread
+    pub async fn delete_messages(&self, ids: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for id in ids {
+            sqlx::query("DELETE FROM message_labels WHERE message_id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM messages WHERE id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
 
     pub async fn remove_label_from_message(&self, message_id: &str, label_id: &str) -> Result<()> {
-        sqlx::query("DELETE FROM message_labels WHERE message_id = ? AND label_id = ?")
-            .bind(message_id)
+        self.remove_label_from_messages(&[message_id.to_string()], label_id)
+            .await
+    }
+
+    /// Remove a label from every message in `ids`, all or nothing
+    pub async fn remove_label_from_messages(&self, ids: &[String], label_id: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for id in ids {
+            sqlx::query("DELETE FROM message_labels WHERE message_id = ? AND label_id = ?")
+                .bind(id)
+                .bind(label_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Add a label to every message in `ids`, all or nothing
+    pub async fn add_label_to_messages(&self, ids: &[String], label_id: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for id in ids {
+            sqlx::query(
+                "INSERT OR IGNORE INTO message_labels (message_id, label_id) VALUES (?, ?)",
+            )
+            .bind(id)
             .bind(label_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Save a message to the outbox before sending it, or as a draft,
+    /// replacing the entry it was reopened from if `id` is set. Returns the
+    /// entry's id.
+    pub async fn queue_outbox(&self, msg: &models::OutboxMessage) -> Result<i64> {
+        let result = sqlx::query(
+            "INSERT INTO outbox (id, from_address, to_address, cc_address, bcc_address, subject, body, markdown, last_error, created_at, draft)
+             VALUES (NULLIF(?, 0), ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(id) DO UPDATE SET from_address=excluded.from_address,
+             to_address=excluded.to_address, cc_address=excluded.cc_address,
+             bcc_address=excluded.bcc_address, subject=excluded.subject, body=excluded.body,
+             markdown=excluded.markdown, last_error=excluded.last_error, draft=excluded.draft",
+        )
+        .bind(msg.id)
+        .bind(&msg.from_address)
+        .bind(&msg.to_address)
+        .bind(&msg.cc_address)
+        .bind(&msg.bcc_address)
+        .bind(&msg.subject)
+        .bind(&msg.body)
+        .bind(msg.markdown)
+        .bind(&msg.last_error)
+        .bind(msg.created_at)
+        .bind(msg.draft)
+        .execute(&self.pool)
+        .await
+        .context("Failed to save the message to the outbox")?;
+        Ok(if msg.id == 0 {
+            result.last_insert_rowid()
+        } else {
+            msg.id
+        })
+    }
+
+    /// Unsent messages, oldest first
+    pub async fn get_outbox(&self) -> Result<Vec<models::OutboxMessage>> {
+        let messages = sqlx::query_as::<_, models::OutboxMessage>(
+            "SELECT id, from_address, to_address, cc_address, bcc_address, subject, body, markdown, last_error, created_at, draft
+             FROM outbox ORDER BY created_at ASC, id ASC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(messages)
+    }
+
+    pub async fn set_outbox_error(&self, id: i64, error: &str) -> Result<()> {
+        sqlx::query("UPDATE outbox SET last_error = ? WHERE id = ?")
+            .bind(error)
+            .bind(id)
             .execute(&self.pool)
             .await?;
         Ok(())
     }
 
-    pub async fn add_label_to_message(&self, message_id: &str, label_id: &str) -> Result<()> {
-        sqlx::query("INSERT OR IGNORE INTO message_labels (message_id, label_id) VALUES (?, ?)")
-            .bind(message_id)
-            .bind(label_id)
+    /// Drop a message from the outbox once Gmail has accepted it
+    pub async fn delete_outbox(&self, id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM outbox WHERE id = ?")
+            .bind(id)
             .execute(&self.pool)
             .await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_filter_builds_fts_and_header_terms() {
+        let (filter, binds) = search_filter("from:bob invoice to:alice@example.com");
+        assert_eq!(
+            filter,
+            "m.rowid IN (SELECT rowid FROM messages_fts WHERE messages_fts MATCH ?) AND m.to_address LIKE ?"
+        );
+        assert_eq!(
+            binds,
+            vec![
+                "from_address : \"bob\"* \"invoice\"*".to_string(),
+                "%alice@example.com%".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search_filter_escapes_quotes() {
+        let (_, binds) = search_filter("say\"hi");
+        assert_eq!(binds, vec!["\"say\"\"hi\"*".to_string()]);
+    }
+
+    async fn test_db() -> Database {
+        Database::open_in_memory().await
+    }
+
+    fn message(id: &str, thread_id: &str) -> models::Message {
+        models::Message {
+            id: id.to_string(),
+            thread_id: thread_id.to_string(),
+            subject: Some("Hello".to_string()),
+            internal_date: 1,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_thread_operations_cover_every_message() {
+        let db = test_db().await;
+        let labels: Vec<models::Label> = ["INBOX", "Label_1"]
+            .iter()
+            .map(|id| models::Label {
+                id: id.to_string(),
+                name: id.to_string(),
+                label_type: "user".to_string(),
+                color_foreground: None,
+                color_background: None,
+                display_name: id.to_string(),
+            })
+            .collect();
+        db.upsert_labels(&labels).await.unwrap();
+        let thread = [message("a", "t"), message("b", "t")];
+        db.upsert_messages(&thread, "INBOX").await.unwrap();
+        let ids = vec!["a".to_string(), "b".to_string()];
+
+        db.add_label_to_messages(&ids, "Label_1").await.unwrap();
+        db.remove_label_from_messages(&ids, "INBOX").await.unwrap();
+        assert!(
+            db.get_messages_by_label("INBOX", 10, 0)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            db.get_messages_by_label("Label_1", 10, 0)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        db.delete_messages(&ids).await.unwrap();
+        assert!(db.get_messages_by_thread("t").await.unwrap().is_empty());
+        assert!(
+            db.get_messages_by_label("Label_1", 10, 0)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_batch_writes_nothing() {
+        let db = test_db().await;
+        // The label doesn't exist, so the second statement fails its foreign key
+        let result = db.upsert_messages(&[message("a", "t")], "Missing").await;
+        assert!(result.is_err());
+        assert!(!db.message_exists("a").await.unwrap());
+    }
+
+    /// The schema databases were created with before migrations were
+    /// versioned: no `schema_version`, no contacts
+    const BASELINE_SCHEMA: &str = r#"
+        -- Labels table
+        CREATE TABLE IF NOT EXISTS labels (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            type TEXT NOT NULL,
+            color_foreground TEXT,
+            color_background TEXT
+        );
+
+        -- Messages table
+        CREATE TABLE IF NOT EXISTS messages (
+            id TEXT PRIMARY KEY,
+            thread_id TEXT NOT NULL,
+            snippet TEXT,
+            from_address TEXT,
+            to_address TEXT,
+            subject TEXT,
+            internal_date INTEGER NOT NULL,
+            body_plain TEXT,
+            body_html TEXT,
+            is_read INTEGER DEFAULT 0
+        );
+
+        -- Junction table for messages and labels
+        CREATE TABLE IF NOT EXISTS message_labels (
+            message_id TEXT NOT NULL,
+            label_id TEXT NOT NULL,
+            PRIMARY KEY (message_id, label_id),
+            FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE,
+            FOREIGN KEY (label_id) REFERENCES labels(id) ON DELETE CASCADE
+        );
+
+        -- Performance Indexes
+        CREATE INDEX IF NOT EXISTS idx_messages_internal_date ON messages(internal_date DESC);
+        CREATE INDEX IF NOT EXISTS idx_messages_thread_id ON messages(thread_id);
+        CREATE INDEX IF NOT EXISTS idx_message_labels_label_id ON message_labels(label_id);
+
+        -- FTS5 table for search (External Content Table)
+        CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+            subject,
+            from_address,
+            snippet,
+            body_plain,
+            content='messages',
+            content_rowid='rowid'
+        );
+
+        -- Triggers to keep FTS in sync
+        CREATE TRIGGER IF NOT EXISTS messages_ai AFTER INSERT ON messages BEGIN
+          INSERT INTO messages_fts(rowid, subject, from_address, snippet, body_plain)
+          VALUES (new.rowid, new.subject, new.from_address, new.snippet, new.body_plain);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS messages_ad AFTER DELETE ON messages BEGIN
+          INSERT INTO messages_fts(messages_fts, rowid, subject, from_address, snippet, body_plain)
+          VALUES('delete', old.rowid, old.subject, old.from_address, old.snippet, old.body_plain);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS messages_au AFTER UPDATE ON messages BEGIN
+          INSERT INTO messages_fts(messages_fts, rowid, subject, from_address, snippet, body_plain)
+          VALUES('delete', old.rowid, old.subject, old.from_address, old.snippet, old.body_plain);
+          INSERT INTO messages_fts(rowid, subject, from_address, snippet, body_plain)
+          VALUES (new.rowid, new.subject, new.from_address, new.snippet, new.body_plain);
+        END;
+"#;
+
+    /// A database set up by `schema`, holding one message in the INBOX
+    async fn legacy_db(schema: &str) -> Database {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query(schema).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO labels (id, name, type) VALUES ('INBOX', 'INBOX', 'system');
+             INSERT INTO messages (id, thread_id, subject, internal_date) VALUES ('a', 't', 'Hi', 1);
+             INSERT INTO message_labels (message_id, label_id) VALUES ('a', 'INBOX');",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        Database { pool }
+    }
+
+    /// Check `db` is at head and every migrated table is usable
+    async fn assert_at_head(db: &Database) {
+        let version: i64 = sqlx::query_scalar("SELECT version FROM schema_version")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len() as i64);
+        let inbox = db.get_messages_by_label("INBOX", 10, 0).await.unwrap();
+        assert_eq!(inbox.len(), 1);
+        assert_eq!(inbox[0].cc_address, None);
+
+        let mut message = message("b", "t");
+        message.cc_address = Some("carol@example.com".to_string());
+        db.upsert_messages(&[message], "INBOX").await.unwrap();
+        let thread = db.get_messages_by_thread("t").await.unwrap();
+        let b = thread.iter().find(|m| m.id == "b").unwrap();
+        assert_eq!(b.cc_address.as_deref(), Some("carol@example.com"));
+        // The baseline had no contacts table; 0001 creates it
+        sqlx::query("SELECT email FROM contacts")
+            .fetch_all(&db.pool)
+            .await
+            .unwrap();
+        assert!(db.get_outbox().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_migrates_unversioned_database_to_head() {
+        let db = legacy_db(BASELINE_SCHEMA).await;
+        db.run_migrations().await.unwrap();
+        assert_at_head(&db).await;
+
+        // Running again at head changes nothing
+        db.run_migrations().await.unwrap();
+        assert_at_head(&db).await;
+    }
+
+    #[tokio::test]
+    async fn test_migrates_version_one_database_to_head() {
+        // Only 0001 has been applied, so the upgrade starts at 0002
+        let schema = format!(
+            "{}
+             CREATE TABLE schema_version (version INTEGER NOT NULL);
+             INSERT INTO schema_version (version) VALUES (1);",
+            MIGRATIONS[0]
+        );
+        let db = legacy_db(&schema).await;
+        db.run_migrations().await.unwrap();
+        assert_at_head(&db).await;
+    }
+
+    #[tokio::test]
+    async fn test_outbox_keeps_unsent_messages_until_deleted() {
+        let db = test_db().await;
+        let mut msg = models::OutboxMessage {
+            to_address: "bob@example.com".to_string(),
+            subject: "Draft".to_string(),
+            created_at: 1,
+            ..Default::default()
+        };
+        msg.id = db.queue_outbox(&msg).await.unwrap();
+        db.set_outbox_error(msg.id, "Failed to send message")
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_outbox().await.unwrap()[0].last_error.as_deref(),
+            Some("Failed to send message")
+        );
+
+        // Queuing a reopened message replaces it and clears the error
+        msg.subject = "Edited".to_string();
+        msg.draft = true;
+        assert_eq!(db.queue_outbox(&msg).await.unwrap(), msg.id);
+        assert_eq!(db.get_outbox().await.unwrap(), vec![msg.clone()]);
+
+        db.delete_outbox(msg.id).await.unwrap();
+        assert!(db.get_outbox().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+use anyhow::{Context, Result, bail};
+use crossterm::{
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode},
+};
+use ratatui::Terminal;
+use ratatui::backend::CrosstermBackend;
+use std::io::{Stdout, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The editable parts of a message, as written to and read back from the
+/// temporary file handed to `$VISUAL`/`$EDITOR`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Draft {
+    pub to: String,
+    pub cc: String,
+    pub bcc: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Draft {
+    /// Serialize as header lines, a blank line, then the body
+    pub fn to_file_contents(&self) -> String {
+        format!(
+            "To: {}\nCc: {}\nBcc: {}\nSubject: {}\n\n{}",
+            self.to, self.cc, self.bcc, self.subject, self.body
+        )
+    }
+
+    /// Parse a file written by `to_file_contents` (and then edited by the user).
+    /// Headers end at the first blank line; indented lines continue the previous
+    /// header and unknown headers are ignored.
+    pub fn from_file_contents(contents: &str) -> Self {
+        let normalized = contents.replace("\r\n", "\n");
+        let mut draft = Draft::default();
+        let mut lines = normalized.split('\n');
+        let mut current: Option<&mut String> = None;
+
+        for line in lines.by_ref() {
+            if line.trim().is_empty() {
+                break;
+            }
+
+            if line.starts_with([' ', '\t']) {
+                if let Some(value) = current.as_deref_mut() {
+                    if !value.is_empty() {
+                        value.push(' ');
+                    }
+                    value.push_str(line.trim());
+                }
+                continue;
+            }
+
+            let Some((name, value)) = line.split_once(':') else {
+                current = None;
+                continue;
+            };
+            current = match name.trim().to_ascii_lowercase().as_str() {
+                "to" => Some(&mut draft.to),
+                "cc" => Some(&mut draft.cc),
+                "bcc" => Some(&mut draft.bcc),
+                "subject" => Some(&mut draft.subject),
+                _ => None,
+            };
+            if let Some(field) = current.as_deref_mut() {
+                *field = value.trim().to_string();
+            }
+        }
+
+        let body: Vec<&str> = lines.collect();
+        let mut body = body.join("\n");
+        // Editors conventionally terminate the file with a newline
+        if body.ends_with('\n') {
+            body.pop();
+        }
+        draft.body = body;
+        draft
+    }
+}
+
+/// Leave the alternate screen and raw mode, run `f`, then restore the TUI.
+/// The terminal is always restored, even if `f` fails. Mouse capture is only
+/// turned back on when `mouse_capture` is set.
+pub fn with_suspended_terminal<T>(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    mouse_capture: bool,
+    f: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        crossterm::terminal::LeaveAlternateScreen,
+        crossterm::event::DisableMouseCapture,
+        crossterm::cursor::Show
+    )?;
+
+    let result = f();
+
+    enable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        crossterm::terminal::EnterAlternateScreen
+    )?;
+    if mouse_capture {
+        execute!(terminal.backend_mut(), crossterm::event::EnableMouseCapture)?;
+    }
+    terminal.clear()?;
+
+    result
+}
+
+/// Open `draft` in the user's editor and parse the result once it exits
+pub fn edit_draft(draft: &Draft) -> Result<Draft> {
+    let path = std::env::temp_dir().join(format!(
+        "gtui-draft-{}-{}.eml",
+        std::process::id(),
+        chrono::Utc::now().timestamp_millis()
+    ));
+    write_private(&path, &draft.to_file_contents()).context("Failed to write draft file")?;
+
+    let result = run_editor(&path).and_then(|_| {
+        std::fs::read_to_string(&path)
+            .map(|contents| Draft::from_file_contents(&contents))
+            .context("Failed to read draft file")
+    });
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+/// Create `path`, readable only by the current user, and write `contents`.
+/// Fails rather than reuse a file someone else may have planted there.
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+/// Show `text` in the user's pager
+pub fn view_in_pager(text: &str) -> Result<()> {
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| "less".to_string());
+    let mut child = command_from_env_string(&pager)?
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to launch pager `{}`", pager))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager may exit before reading everything; that's not an error
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait().context("Failed to wait for pager")?;
+    Ok(())
+}
+
+fn run_editor(path: &Path) -> Result<()> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+
+    let status = command_from_env_string(&editor)?
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to launch editor `{}`", editor))?;
+    if !status.success() {
+        bail!("Editor exited with {}", status);
+    }
+    Ok(())
+}
+
+/// Build a command from an environment value such as `code --wait`
+fn command_from_env_string(value: &str) -> Result<Command> {
+    let mut parts = value.split_whitespace();
+    let Some(program) = parts.next() else {
+        bail!("Empty command");
+    };
+    let mut command = Command::new(program);
+    command.args(parts);
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draft_round_trip() {
+        let draft = Draft {
+            to: "alice@example.com, Bob <bob@example.com>".to_string(),
+            cc: "carol@example.com".to_string(),
+            bcc: String::new(),
+            subject: "Re: Lunch".to_string(),
+            body: "Sounds good.\n\nTo: not a header".to_string(),
+        };
+        assert_eq!(Draft::from_file_contents(&draft.to_file_contents()), draft);
+    }
+
+    #[test]
+    fn test_draft_strips_editor_trailing_newline() {
+        let parsed = Draft::from_file_contents("To: a@example.com\nSubject: Hi\n\nBody\n");
+        assert_eq!(parsed.to, "a@example.com");
+        assert_eq!(parsed.subject, "Hi");
+        assert_eq!(parsed.body, "Body");
+    }
+
+    #[test]
+    fn test_draft_header_continuation_and_case() {
+        let parsed = Draft::from_file_contents(
+            "to: a@example.com,\n  b@example.com\nX-Ignored: yes\nSUBJECT:  Hello \n\nBody",
+        );
+        assert_eq!(parsed.to, "a@example.com, b@example.com");
+        assert_eq!(parsed.subject, "Hello");
+        assert_eq!(parsed.body, "Body");
+    }
+
+    #[test]
+    fn test_draft_without_headers_is_all_body_after_blank_line() {
+        let parsed = Draft::from_file_contents("\nJust a body");
+        assert_eq!(
+            parsed,
+            Draft {
+                body: "Just a body".to_string(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_draft_file_is_private_and_never_reused() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("gtui-test-draft-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        write_private(&path, "To: a@example.com").unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(write_private(&path, "replaced").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
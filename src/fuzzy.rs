@@ -0,0 +1,61 @@
+/// Score how well `query` matches `candidate` as a case-insensitive
+/// subsequence. Returns `None` when not every query character is found in
+/// order. Consecutive matches and matches at word starts score higher, and an
+/// empty query matches everything with a score of zero.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let mut score = 0;
+    let mut qi = 0;
+    let mut prev_matched = false;
+    let mut prev_char: Option<char> = None;
+
+    for c in candidate.chars() {
+        if qi == query.len() {
+            break;
+        }
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        if lower == query[qi] {
+            score += 1;
+            if prev_matched {
+                score += 15;
+            }
+            if prev_char.is_none_or(|p| !p.is_alphanumeric()) {
+                score += 10;
+            }
+            qi += 1;
+            prev_matched = true;
+        } else {
+            prev_matched = false;
+        }
+        prev_char = Some(c);
+    }
+
+    (qi == query.len()).then_some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_requires_subsequence() {
+        assert!(score("abc", "a big cat").is_some());
+        assert!(score("cba", "a big cat").is_none());
+    }
+
+    #[test]
+    fn test_score_prefers_prefix_and_consecutive() {
+        let prefix = score("bob", "bob@example.com").unwrap();
+        let scattered = score("bob", "big old barn").unwrap();
+        assert!(prefix > scattered);
+    }
+
+    #[test]
+    fn test_score_is_case_insensitive() {
+        assert_eq!(score("JA", "jane"), score("ja", "Jane"));
+    }
+}
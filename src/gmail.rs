@@ -1,48 +1,86 @@
+use crate::address::Mailbox;
+use crate::auth;
+use crate::markdown;
 use crate::models;
+use crate::outgoing::OutgoingMessage;
+use crate::quota::{Quota, units};
 use anyhow::{Context, Result};
 use base64::{Engine as _, engine::general_purpose};
 use google_gmail1::Gmail;
 use hyper::client::HttpConnector;
 use hyper_rustls::HttpsConnector;
 use inflections::case::to_title_case;
+use serde::Deserialize;
 
 #[derive(Clone)]
 pub struct GmailClient {
     hub: Gmail<HttpsConnector<HttpConnector>>,
-    debug_logging: bool,
+    /// Shared by every clone, so sync and user actions draw on one budget
+    quota: Quota,
 }
 
 impl GmailClient {
-    pub fn new(hub: Gmail<HttpsConnector<HttpConnector>>, debug_logging: bool) -> Self {
-        Self { hub, debug_logging }
+    pub fn new(hub: Gmail<HttpsConnector<HttpConnector>>) -> Self {
+        Self {
+            hub,
+            quota: Quota::default(),
+        }
+    }
+
+    /// When a rate-limited request will next be retried, for the status line
+    pub fn retrying_until(&self) -> Option<std::time::Instant> {
+        self.quota.retrying_until()
     }
 
-    pub async fn get_signature(&self) -> Result<Option<String>> {
+    /// List the verified addresses this account can send as, with their
+    /// signatures converted to plain text. The primary address comes first.
+    #[tracing::instrument(level = "debug", skip(self), err(Display))]
+    pub async fn list_send_as(&self) -> Result<Vec<models::SendAs>> {
+        let mut retry = self.quota.acquire(units::SEND_AS_LIST).await;
         let (_, aliases) = self
             .hub
             .users()
             .settings_send_as_list("me")
+            .delegate(&mut retry)
             .doit()
             .await
             .context("Failed to list send-as aliases")?;
 
-        if let Some(alias_list) = aliases.send_as {
-            // Find the primary alias
-            if let Some(primary) = alias_list
-                .into_iter()
-                .find(|a| a.is_primary.unwrap_or(false))
-            {
-                return Ok(primary.signature.map(|s| convert_html_to_plain_text(&s)));
-            }
-        }
-        Ok(None)
+        let mut aliases: Vec<_> = aliases
+            .send_as
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|a| {
+                a.is_primary.unwrap_or(false)
+                    || a.verification_status.as_deref() == Some("accepted")
+            })
+            .collect();
+        aliases.sort_by_key(|a| !a.is_primary.unwrap_or(false));
+
+        Ok(aliases
+            .into_iter()
+            .filter_map(|a| {
+                Some(models::SendAs {
+                    email: a.send_as_email?,
+                    display_name: a.display_name.filter(|n| !n.is_empty()),
+                    signature: a
+                        .signature
+                        .map(|s| convert_html_to_plain_text(&s))
+                        .filter(|s| !s.is_empty()),
+                    is_default: a.is_default.unwrap_or(false),
+                })
+            })
+            .collect())
     }
 
+    #[tracing::instrument(level = "debug", skip(self), err(Display))]
     pub async fn list_labels(&self) -> Result<Vec<models::Label>> {
+        let mut retry = self.quota.acquire(units::LABELS_LIST).await;
         let (_, label_list) = self
             .hub
             .users()
             .labels_list("me")
+            .delegate(&mut retry)
             .doit()
             .await
             .context("Failed to list labels")?;
@@ -64,6 +102,7 @@ impl GmailClient {
         Ok(labels)
     }
 
+    #[tracing::instrument(level = "debug", skip(self), err(Display))]
     pub async fn list_messages(
         &self,
         label_ids: Vec<String>,
@@ -84,7 +123,12 @@ impl GmailClient {
             req = req.page_token(token);
         }
 
-        let (_, message_list) = req.doit().await.context("Failed to list messages")?;
+        let mut retry = self.quota.acquire(units::MESSAGES_LIST).await;
+        let (_, message_list) = req
+            .delegate(&mut retry)
+            .doit()
+            .await
+            .context("Failed to list messages")?;
 
         let ids = message_list
             .messages
@@ -97,29 +141,43 @@ impl GmailClient {
     }
 
     pub async fn get_message(&self, id: &str) -> Result<models::Message> {
+        self.fetch_message(id, "full").await
+    }
+
+    /// Headers, labels and snippet only; `body_plain` is left empty
+    pub async fn get_message_metadata(&self, id: &str) -> Result<models::Message> {
+        self.fetch_message(id, "metadata").await
+    }
+
+    #[tracing::instrument(level = "debug", skip(self), err(Display))]
+    async fn fetch_message(&self, id: &str, format: &str) -> Result<models::Message> {
+        let mut retry = self.quota.acquire(units::MESSAGES_GET).await;
         let (_, msg) = self
             .hub
             .users()
             .messages_get("me", id)
-            .format("full")
+            .format(format)
+            .delegate(&mut retry)
             .doit()
             .await
             .context(format!("Failed to get message {}", id))?;
 
         let mut from = None;
         let mut to = None;
+        let mut cc = None;
         let mut subject = None;
         let internal_date = msg.internal_date.unwrap_or(0);
 
-        if let Some(payload) = &msg.payload {
-            if let Some(headers) = &payload.headers {
-                for header in headers {
-                    match header.name.as_deref() {
-                        Some("From") => from = header.value.clone(),
-                        Some("To") => to = header.value.clone(),
-                        Some("Subject") => subject = header.value.clone(),
-                        _ => {}
-                    }
+        if let Some(payload) = &msg.payload
+            && let Some(headers) = &payload.headers
+        {
+            for header in headers {
+                match header.name.as_deref() {
+                    Some("From") => from = header.value.clone(),
+                    Some("To") => to = header.value.clone(),
+                    Some("Cc") => cc = header.value.clone(),
+                    Some("Subject") => subject = header.value.clone(),
+                    _ => {}
                 }
             }
         }
@@ -135,6 +193,7 @@ impl GmailClient {
             snippet: msg.snippet,
             from_address: from,
             to_address: to,
+            cc_address: cc,
             subject,
             internal_date,
             body_plain,
@@ -148,24 +207,96 @@ impl GmailClient {
                 .label_ids
                 .unwrap_or_default()
                 .contains(&"SENT".to_string()),
+            ..Default::default()
         })
     }
 
+    /// Fetch names and addresses from the People API (saved contacts and
+    /// "other contacts"). Requires `auth::PEOPLE_SCOPES` to have been granted.
+    #[tracing::instrument(level = "debug", skip(self), err(Display))]
+    pub async fn list_people_contacts(&self) -> Result<Vec<Mailbox>> {
+        let token = self
+            .hub
+            .auth
+            .get_token(auth::PEOPLE_SCOPES)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get People API token: {}", e))?
+            .context("No token available for the People API")?;
+
+        let endpoints = [
+            "https://people.googleapis.com/v1/people/me/connections?personFields=names,emailAddresses&pageSize=1000",
+            "https://people.googleapis.com/v1/otherContacts?readMask=names,emailAddresses&pageSize=1000",
+        ];
+
+        let mut mailboxes = Vec::new();
+        for endpoint in endpoints {
+            let mut page_token: Option<String> = None;
+            loop {
+                let mut url = url::Url::parse(endpoint)?;
+                if let Some(token) = &page_token {
+                    url.query_pairs_mut().append_pair("pageToken", token);
+                }
+                let req = hyper::Request::get(url.as_str())
+                    .header(hyper::header::AUTHORIZATION, format!("Bearer {}", token))
+                    .body(hyper::Body::empty())?;
+                let resp = self
+                    .hub
+                    .client
+                    .request(req)
+                    .await
+                    .context("Failed to query the People API")?;
+                let status = resp.status();
+                let bytes = hyper::body::to_bytes(resp.into_body()).await?;
+                if !status.is_success() {
+                    anyhow::bail!(
+                        "People API returned {}: {}",
+                        status,
+                        String::from_utf8_lossy(&bytes)
+                    );
+                }
+
+                let page: PeopleList = serde_json::from_slice(&bytes)
+                    .context("Failed to parse People API response")?;
+                for person in page.connections.into_iter().chain(page.other_contacts) {
+                    let name = person.names.into_iter().find_map(|n| n.display_name);
+                    for email in person.email_addresses.into_iter().filter_map(|e| e.value) {
+                        mailboxes.push(Mailbox {
+                            name: name.clone(),
+                            email,
+                        });
+                    }
+                }
+
+                page_token = page.next_page_token;
+                if page_token.is_none() {
+                    break;
+                }
+            }
+        }
+
+        Ok(mailboxes)
+    }
+
     #[allow(dead_code)]
     pub async fn trash_message(&self, id: &str) -> Result<()> {
         self.trash_messages(&[id.to_string()]).await
     }
 
+    /// Move messages to Trash, where `untrash_message` can bring them back.
+    /// Unlike `messages.batchDelete`, nothing is permanently deleted.
+    #[tracing::instrument(level = "debug", skip(self), err(Display))]
     pub async fn trash_messages(&self, ids: &[String]) -> Result<()> {
-        if self.debug_logging {
-            self.debug_log(&format!("Trashing messages: {:?}", ids));
-        }
-        let req = google_gmail1::api::BatchDeleteMessagesRequest {
+        tracing::debug!("Trashing messages: {:?}", ids);
+        let req = google_gmail1::api::BatchModifyMessagesRequest {
             ids: Some(ids.to_vec()),
+            remove_label_ids: None,
+            add_label_ids: Some(vec!["TRASH".to_string()]),
         };
+        let mut retry = self.quota.acquire(units::MESSAGES_BATCH_MODIFY).await;
         self.hub
             .users()
-            .messages_batch_delete(req, "me")
+            .messages_batch_modify(req, "me")
+            .delegate(&mut retry)
             .doit()
             .await
             .context("Failed to trash messages")?;
@@ -177,205 +308,266 @@ impl GmailClient {
         self.archive_messages(&[id.to_string()]).await
     }
 
+    #[tracing::instrument(level = "debug", skip(self), err(Display))]
     pub async fn archive_messages(&self, ids: &[String]) -> Result<()> {
-        if self.debug_logging {
-            self.debug_log(&format!("Archiving messages: {:?}", ids));
-        }
+        tracing::debug!("Archiving messages: {:?}", ids);
         let req = google_gmail1::api::BatchModifyMessagesRequest {
             ids: Some(ids.to_vec()),
             remove_label_ids: Some(vec!["INBOX".to_string()]),
             add_label_ids: None,
         };
+        let mut retry = self.quota.acquire(units::MESSAGES_BATCH_MODIFY).await;
         self.hub
             .users()
             .messages_batch_modify(req, "me")
+            .delegate(&mut retry)
             .doit()
             .await
             .context("Failed to archive messages")?;
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip(self), err(Display))]
     pub async fn remove_label_from_messages(&self, ids: &[String], label_id: &str) -> Result<()> {
-        if self.debug_logging {
-            self.debug_log(&format!("Removing label {} from messages: {:?}", label_id, ids));
-        }
+        tracing::debug!("Removing label {} from messages: {:?}", label_id, ids);
         let req = google_gmail1::api::BatchModifyMessagesRequest {
             ids: Some(ids.to_vec()),
             remove_label_ids: Some(vec![label_id.to_string()]),
             add_label_ids: None,
         };
+        let mut retry = self.quota.acquire(units::MESSAGES_BATCH_MODIFY).await;
         self.hub
             .users()
             .messages_batch_modify(req, "me")
+            .delegate(&mut retry)
             .doit()
             .await
             .context("Failed to remove label from messages")?;
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip(self), err(Display))]
     pub async fn add_label_to_message(&self, id: &str, label_id: &str) -> Result<()> {
-        if self.debug_logging {
-            self.debug_log(&format!("Adding label {} to message: {}", label_id, id));
-        }
+        tracing::debug!("Adding label {} to message: {}", label_id, id);
         let req = google_gmail1::api::ModifyMessageRequest {
             add_label_ids: Some(vec![label_id.to_string()]),
             remove_label_ids: None,
         };
+        let mut retry = self.quota.acquire(units::MESSAGES_MODIFY).await;
         self.hub
             .users()
             .messages_modify(req, "me", id)
+            .delegate(&mut retry)
             .doit()
             .await
             .context("Failed to add label to message")?;
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip(self), err(Display))]
     pub async fn untrash_message(&self, id: &str) -> Result<()> {
-        if self.debug_logging {
-            self.debug_log(&format!("Untrashing message: {}", id));
-        }
+        tracing::debug!("Untrashing message: {}", id);
+        let mut retry = self.quota.acquire(units::MESSAGES_UNTRASH).await;
         self.hub
             .users()
             .messages_untrash("me", id)
+            .delegate(&mut retry)
             .doit()
             .await
             .context("Failed to untrash message")?;
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip(self), err(Display))]
     pub async fn unarchive_message(&self, id: &str) -> Result<()> {
-        if self.debug_logging {
-            self.debug_log(&format!("Unarchiving message: {}", id));
-        }
+        tracing::debug!("Unarchiving message: {}", id);
         let req = google_gmail1::api::BatchModifyMessagesRequest {
             ids: Some(vec![id.to_string()]),
             add_label_ids: Some(vec!["INBOX".to_string()]),
             remove_label_ids: None,
         };
+        let mut retry = self.quota.acquire(units::MESSAGES_BATCH_MODIFY).await;
         self.hub
             .users()
             .messages_batch_modify(req, "me")
+            .delegate(&mut retry)
             .doit()
             .await
             .context("Failed to unarchive message")?;
         Ok(())
     }
 
-    pub async fn send_message(
-        &self,
-        to: &str,
-        cc: &str,
-        bcc: &str,
-        subject: &str,
-        body: &str,
-    ) -> Result<Option<String>> {
-        let mut headers = vec![
-            format!("From: me"),
-            format!("To: {}", to),
-            format!("Subject: {}", encode_header_value(subject)),
-        ];
-
-        if !cc.is_empty() {
-            headers.push(format!("Cc: {}", cc));
-        }
-        if !bcc.is_empty() {
-            headers.push(format!("Bcc: {}", bcc));
-        }
+    #[tracing::instrument(level = "debug", skip_all, err(Display))]
+    pub async fn send_message(&self, message: &OutgoingMessage) -> Result<Option<String>> {
+        let raw_message = build_raw_message(message);
 
-        headers.push(format!("Content-Type: text/plain; charset=\"UTF-8\""));
-
-        let raw_message = format!("{}\r\n\r\n{}", headers.join("\r\n"), body);
-
-        // Logging for troubleshooting
-        if self.debug_logging {
-            if let Ok(mut file) = std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open("gtui_debug.log")
-            {
-                use std::io::Write;
-                let _ = writeln!(file, "--- SEND ATTEMPT ---");
-                let _ = writeln!(file, "To: {}", to);
-                let _ = writeln!(file, "Subject: {}", subject);
-                let _ = writeln!(file, "Raw Message Body Length: {}", body.len());
-            }
-        }
+        tracing::debug!(
+            to = %format_address_header(&message.to),
+            subject = %message.subject,
+            body_len = message.body.len(),
+            "sending message"
+        );
 
         use std::io::Cursor;
         let cursor = Cursor::new(raw_message.into_bytes());
 
+        let mut retry = self.quota.acquire_once(units::MESSAGES_SEND).await;
         let result = self
             .hub
             .users()
             .messages_send(google_gmail1::api::Message::default(), "me")
+            .delegate(&mut retry)
             .upload(cursor, "message/rfc822".parse().unwrap())
             .await;
 
-        if self.debug_logging {
-            if let Ok(mut file) = std::fs::OpenOptions::new()
-                .append(true)
-                .open("gtui_debug.log")
-            {
-                use std::io::Write;
-                match &result {
-                    Ok(_) => {
-                        let _ = writeln!(file, "Result: SUCCESS");
-                    }
-                    Err(e) => {
-                        let _ = writeln!(file, "Result: ERROR: {:?}", e);
-                    }
-                }
-            }
-        }
-
         let response = result.context("Failed to send message")?;
-        
+
         // Return the sent message ID so it can be fetched and stored
         Ok(response.1.id)
     }
 
+    #[tracing::instrument(level = "debug", skip(self), err(Display))]
     pub async fn mark_as_read(&self, id: &str) -> Result<()> {
         let req = google_gmail1::api::BatchModifyMessagesRequest {
             ids: Some(vec![id.to_string()]),
             remove_label_ids: Some(vec!["UNREAD".to_string()]),
             add_label_ids: None,
         };
+        let mut retry = self.quota.acquire(units::MESSAGES_BATCH_MODIFY).await;
         self.hub
             .users()
             .messages_batch_modify(req, "me")
+            .delegate(&mut retry)
             .doit()
             .await
             .context("Failed to mark message as read")?;
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip(self), err(Display))]
     pub async fn mark_as_unread(&self, id: &str) -> Result<()> {
         let req = google_gmail1::api::BatchModifyMessagesRequest {
             ids: Some(vec![id.to_string()]),
             remove_label_ids: None,
             add_label_ids: Some(vec!["UNREAD".to_string()]),
         };
+        let mut retry = self.quota.acquire(units::MESSAGES_BATCH_MODIFY).await;
         self.hub
             .users()
             .messages_batch_modify(req, "me")
+            .delegate(&mut retry)
             .doit()
             .await
             .context("Failed to mark message as unread")?;
         Ok(())
     }
+}
 
-    pub fn debug_log(&self, msg: &str) {
-        if self.debug_logging {
-            if let Ok(mut file) = std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open("gtui_debug.log")
-            {
-                use std::io::Write;
-                let _ = writeln!(file, "{}", msg);
-            }
-        }
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct PeopleList {
+    connections: Vec<Person>,
+    other_contacts: Vec<Person>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Person {
+    names: Vec<PersonName>,
+    email_addresses: Vec<PersonEmail>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct PersonName {
+    display_name: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PersonEmail {
+    value: Option<String>,
+}
+
+/// Assemble the RFC 822 message uploaded to `messages.send`
+fn build_raw_message(message: &OutgoingMessage) -> String {
+    let from = match &message.from {
+        Some(from) => format_address_header(std::slice::from_ref(from)),
+        None => "me".to_string(),
+    };
+    let mut headers = vec![
+        format!("From: {}", from),
+        format!("To: {}", format_address_header(&message.to)),
+        format!("Subject: {}", encode_header_value(&message.subject)),
+    ];
+
+    if !message.cc.is_empty() {
+        headers.push(format!("Cc: {}", format_address_header(&message.cc)));
+    }
+    if !message.bcc.is_empty() {
+        headers.push(format!("Bcc: {}", format_address_header(&message.bcc)));
+    }
+
+    if !message.markdown {
+        headers.push("Content-Type: text/plain; charset=\"UTF-8\"".to_string());
+        return format!("{}\r\n\r\n{}", headers.join("\r\n"), message.body);
+    }
+
+    // The Markdown source doubles as the plain-text alternative. Parts are
+    // listed least to most preferred, so clients that can show HTML do.
+    let boundary = format!(
+        "gtui-{:x}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default()
+    );
+    headers.push("MIME-Version: 1.0".to_string());
+    headers.push(format!(
+        "Content-Type: multipart/alternative; boundary=\"{}\"",
+        boundary
+    ));
+
+    let html = markdown::to_html(&message.body);
+    let parts = [
+        ("text/plain", encode_body_base64(&message.body)),
+        ("text/html", encode_body_base64(&html)),
+    ];
+    let mut raw = format!("{}\r\n\r\n", headers.join("\r\n"));
+    for (content_type, encoded) in parts {
+        raw.push_str(&format!(
+            "--{}\r\nContent-Type: {}; charset=\"UTF-8\"\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+            boundary, content_type, encoded
+        ));
     }
+    raw.push_str(&format!("--{}--\r\n", boundary));
+    raw
+}
+
+/// Base64-encode a MIME part body, wrapped at 76 columns
+fn encode_body_base64(body: &str) -> String {
+    let encoded = general_purpose::STANDARD.encode(body);
+    encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|line| std::str::from_utf8(line).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+/// Format mailboxes for an address header, encoding non-ASCII display names
+fn format_address_header(mailboxes: &[Mailbox]) -> String {
+    mailboxes
+        .iter()
+        .map(|m| match &m.name {
+            Some(name) if !name.is_ascii() => {
+                format!("{} <{}>", encode_header_value(name), m.email)
+            }
+            _ => m.to_display_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 /// Encode a header value using RFC 2047 MIME encoded-word syntax if it contains non-ASCII characters.
@@ -385,11 +577,31 @@ fn encode_header_value(value: &str) -> String {
     if value.is_ascii() {
         return value.to_string();
     }
-    
+
     // Use Base64 encoding for the header (RFC 2047)
     // Format: =?charset?encoding?encoded_text?=
-    let encoded = general_purpose::STANDARD.encode(value.as_bytes());
-    format!("=?UTF-8?B?{}?=", encoded)
+    // Encoded words may be at most 75 characters, so split long values on
+    // character boundaries (45 bytes encode to 60) and fold between words.
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in value.chars() {
+        if chunk.len() + c.len_utf8() > 45 {
+            words.push(std::mem::take(&mut chunk));
+        }
+        chunk.push(c);
+    }
+    words.push(chunk);
+
+    words
+        .iter()
+        .map(|w| {
+            format!(
+                "=?UTF-8?B?{}?=",
+                general_purpose::STANDARD.encode(w.as_bytes())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\r\n ")
 }
 
 fn convert_html_to_plain_text(html: &str) -> String {
@@ -450,31 +662,29 @@ fn convert_html_to_plain_text(html: &str) -> String {
 }
 
 fn extract_text_body(part: &google_gmail1::api::MessagePart, mime_type: &str) -> Option<String> {
-    if let Some(mime) = &part.mime_type {
-        if mime == mime_type {
-            if let Some(body) = &part.body {
-                if let Some(data) = &body.data {
-                    use base64::{Engine as _, engine::general_purpose};
-                    let data_str = String::from_utf8_lossy(data);
-
-                    // Try decoding as base64url (Gmail's default)
-                    let decoded = general_purpose::URL_SAFE_NO_PAD
-                        .decode(data_str.trim().replace('-', "+").replace('_', "/"))
-                        .or_else(|_| {
-                            general_purpose::URL_SAFE
-                                .decode(data_str.trim().replace('-', "+").replace('_', "/"))
-                        })
-                        .or_else(|_| general_purpose::STANDARD_NO_PAD.decode(data_str.trim()))
-                        .or_else(|_| general_purpose::STANDARD.decode(data_str.trim()));
-
-                    match decoded {
-                        Ok(bytes) => return String::from_utf8(bytes).ok(),
-                        Err(_) => {
-                            // If base64 decoding fails, it might already be raw content
-                            return String::from_utf8(data.clone()).ok();
-                        }
-                    }
-                }
+    if let Some(mime) = &part.mime_type
+        && mime == mime_type
+        && let Some(body) = &part.body
+        && let Some(data) = &body.data
+    {
+        use base64::{Engine as _, engine::general_purpose};
+        let data_str = String::from_utf8_lossy(data);
+
+        // Try decoding as base64url (Gmail's default)
+        let decoded = general_purpose::URL_SAFE_NO_PAD
+            .decode(data_str.trim().replace('-', "+").replace('_', "/"))
+            .or_else(|_| {
+                general_purpose::URL_SAFE
+                    .decode(data_str.trim().replace('-', "+").replace('_', "/"))
+            })
+            .or_else(|_| general_purpose::STANDARD_NO_PAD.decode(data_str.trim()))
+            .or_else(|_| general_purpose::STANDARD.decode(data_str.trim()));
+
+        match decoded {
+            Ok(bytes) => return String::from_utf8(bytes).ok(),
+            Err(_) => {
+                // If base64 decoding fails, it might already be raw content
+                return String::from_utf8(data.clone()).ok();
             }
         }
     }
@@ -493,3 +703,52 @@ fn extract_text_body(part: &google_gmail1::api::MessagePart, mime_type: &str) ->
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_header_value_keeps_ascii() {
+        assert_eq!(encode_header_value("Hello"), "Hello");
+    }
+
+    #[test]
+    fn test_encode_header_value_splits_long_words() {
+        let encoded = encode_header_value(&"é".repeat(40));
+        for word in encoded.split("\r\n ") {
+            assert!(word.len() <= 75, "{} is too long", word);
+            assert!(word.starts_with("=?UTF-8?B?") && word.ends_with("?="));
+        }
+    }
+
+    #[test]
+    fn test_format_address_header_encodes_non_ascii_names() {
+        let mailboxes = vec![
+            Mailbox {
+                name: Some("Zoë".to_string()),
+                email: "zoe@x.com".to_string(),
+            },
+            Mailbox {
+                name: Some("Doe, Jane".to_string()),
+                email: "jane@x.com".to_string(),
+            },
+        ];
+        assert_eq!(
+            format_address_header(&mailboxes),
+            "=?UTF-8?B?Wm/Dqw==?= <zoe@x.com>, \"Doe, Jane\" <jane@x.com>"
+        );
+    }
+
+    #[test]
+    fn test_markdown_message_is_multipart_alternative() {
+        let mut message = OutgoingMessage::new("a@x.com", "", "", "Hi", "**bold**").unwrap();
+        message.markdown = true;
+        let raw = build_raw_message(&message);
+        assert!(raw.contains("Content-Type: multipart/alternative; boundary="));
+        let plain = raw.find("Content-Type: text/plain").unwrap();
+        let html = raw.find("Content-Type: text/html").unwrap();
+        assert!(plain < html);
+        assert!(raw.contains(&encode_body_base64(&markdown::to_html("**bold**"))));
+    }
+}
@@ -0,0 +1,392 @@
+use crate::commands::{Action, COMMANDS, COMPOSE_KEYS};
+use crate::config::{Keybindings, key_matches, parse_key};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Largest count prefix accepted, so `99999j` can't stall the loop
+const MAX_COUNT: usize = 999;
+
+type KeyPress = (KeyCode, KeyModifiers);
+
+/// Parse a binding into the keys that must be pressed in turn. `ctrl-w l`
+/// is two keys; so is `gg`, since it isn't the name of a single key.
+pub fn parse_binding(binding: &str) -> Option<Vec<KeyPress>> {
+    if let Some(key) = parse_key(binding) {
+        return Some(vec![key]);
+    }
+    if binding.trim().contains(' ') {
+        return binding.split_whitespace().map(parse_key).collect();
+    }
+    if binding.contains('-') {
+        return None;
+    }
+    binding.chars().map(|c| parse_key(&c.to_string())).collect()
+}
+
+struct Entry {
+    keys: Vec<KeyPress>,
+    binding: String,
+    name: &'static str,
+    action: Action,
+}
+
+/// Every binding for one mode
+#[derive(Default)]
+pub struct KeyMap {
+    entries: Vec<Entry>,
+}
+
+impl KeyMap {
+    fn add(
+        &mut self,
+        mode: &str,
+        name: &'static str,
+        bindings: &[String],
+        action: &Action,
+        problems: &mut Vec<String>,
+    ) {
+        for binding in bindings {
+            match parse_binding(binding) {
+                Some(keys) => self.entries.push(Entry {
+                    keys,
+                    binding: binding.clone(),
+                    name,
+                    action: action.clone(),
+                }),
+                None => problems.push(format!(
+                    "{}: unknown key \"{}\" for {}",
+                    mode, binding, name
+                )),
+            }
+        }
+    }
+
+    /// Report bindings that can never fire: the same keys bound twice, or a
+    /// binding that is the start of a longer one and so always wins first
+    fn check(&self, mode: &str, problems: &mut Vec<String>) {
+        for (i, a) in self.entries.iter().enumerate() {
+            for b in &self.entries[i + 1..] {
+                if a.action == b.action {
+                    continue;
+                }
+                if a.keys == b.keys {
+                    problems.push(format!(
+                        "{}: \"{}\" is bound to both {} and {}",
+                        mode, a.binding, a.name, b.name
+                    ));
+                } else if b.keys.starts_with(&a.keys) || a.keys.starts_with(&b.keys) {
+                    let (short, long) = if a.keys.len() < b.keys.len() {
+                        (a, b)
+                    } else {
+                        (b, a)
+                    };
+                    problems.push(format!(
+                        "{}: \"{}\" ({}) hides \"{}\" ({})",
+                        mode, short.binding, short.name, long.binding, long.name
+                    ));
+                }
+            }
+        }
+    }
+
+    /// The action for a single key press, ignoring sequences
+    pub fn lookup(&self, key: KeyEvent) -> Option<Action> {
+        self.entries
+            .iter()
+            .find(|e| e.keys.len() == 1 && key_matches(key, e.keys[0].0, e.keys[0].1))
+            .map(|e| e.action.clone())
+    }
+
+    fn starts_with(&self, pending: &[KeyEvent]) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(move |e| {
+            e.keys.len() >= pending.len()
+                && pending
+                    .iter()
+                    .zip(&e.keys)
+                    .all(|(event, (code, modifiers))| key_matches(*event, *code, *modifiers))
+        })
+    }
+}
+
+/// The binding tables for each mode, built once from `[keybindings]`
+pub struct Keymaps {
+    pub browsing: KeyMap,
+    /// Browsing bindings with `[keybindings.details]` applied, used while the
+    /// Details panel is focused
+    pub details: KeyMap,
+    pub compose: KeyMap,
+    /// Bindings that were ignored or can't be reached, for the status line
+    pub problems: Vec<String>,
+}
+
+impl Keymaps {
+    pub fn new(keybindings: &Keybindings) -> Self {
+        let mut problems = Vec::new();
+        let mut browsing = KeyMap::default();
+        let mut details = KeyMap::default();
+        let mut compose = KeyMap::default();
+
+        for command in COMMANDS {
+            let Some(action) = command.action() else {
+                continue;
+            };
+            let keys = (command.keys)(keybindings);
+            browsing.add("browsing", command.name, keys, &action, &mut problems);
+            let keys = command
+                .binding
+                .and_then(|b| keybindings.details.get(b))
+                .map_or(keys, |k| k);
+            details.add("details", command.name, keys, &action, &mut problems);
+        }
+        let mut unknown: Vec<&String> = keybindings
+            .details
+            .keys()
+            .filter(|name| !COMMANDS.iter().any(|c| c.binding == Some(name.as_str())))
+            .collect();
+        unknown.sort();
+        for name in unknown {
+            problems.push(format!("details: no keybinding named {}", name));
+        }
+
+        for key in COMPOSE_KEYS {
+            let bindings = (key.keys)(keybindings);
+            compose.add("compose", key.name, bindings, &key.action, &mut problems);
+        }
+        for entry in &compose.entries {
+            if entry.keys.len() > 1 {
+                problems.push(format!(
+                    "compose: sequence \"{}\" for {} isn't supported while typing",
+                    entry.binding, entry.name
+                ));
+            } else if matches!(entry.keys[0], (KeyCode::Char(_), m) if (m - KeyModifiers::SHIFT).is_empty())
+            {
+                problems.push(format!(
+                    "compose: \"{}\" for {} would stop you typing it",
+                    entry.binding, entry.name
+                ));
+            }
+        }
+
+        browsing.check("browsing", &mut problems);
+        if !keybindings.details.is_empty() {
+            details.check("details", &mut problems);
+        }
+        compose.check("compose", &mut problems);
+
+        Self {
+            browsing,
+            details,
+            compose,
+            problems,
+        }
+    }
+}
+
+pub enum KeyOutcome {
+    /// Run the action, `count` times if it repeats
+    Action(Action, usize),
+    /// Part of a count or sequence; wait for the next key
+    Pending,
+    Unbound,
+}
+
+/// Keys typed so far towards a count or a multi-key binding
+#[derive(Debug, Default)]
+pub struct KeyState {
+    pending: Vec<KeyEvent>,
+    count: usize,
+}
+
+impl KeyState {
+    pub fn handle(&mut self, map: &KeyMap, key: KeyEvent) -> KeyOutcome {
+        // Digits start a count unless something is bound to them
+        if self.pending.is_empty()
+            && let KeyCode::Char(c @ '0'..='9') = key.code
+            && (key.modifiers - KeyModifiers::SHIFT).is_empty()
+            && (c != '0' || self.count > 0)
+            && map.starts_with(&[key]).next().is_none()
+        {
+            let digit = c.to_digit(10).unwrap_or(0) as usize;
+            self.count = (self.count * 10 + digit).min(MAX_COUNT);
+            return KeyOutcome::Pending;
+        }
+
+        self.pending.push(key);
+        let len = self.pending.len();
+        let (any, exact) = {
+            let mut candidates = map.starts_with(&self.pending).peekable();
+            let any = candidates.peek().is_some();
+            (
+                any,
+                candidates
+                    .find(|e| e.keys.len() == len)
+                    .map(|e| e.action.clone()),
+            )
+        };
+        if !any {
+            // A dead end; the last key may still start something new
+            self.reset();
+            return if len > 1 {
+                self.handle(map, key)
+            } else {
+                KeyOutcome::Unbound
+            };
+        }
+        if let Some(action) = exact {
+            let outcome = KeyOutcome::Action(action, self.count.max(1));
+            self.reset();
+            return outcome;
+        }
+        KeyOutcome::Pending
+    }
+
+    /// The count and keys typed so far, for display
+    pub fn pending(&self) -> Option<String> {
+        if self.pending.is_empty() && self.count == 0 {
+            return None;
+        }
+        let mut shown = if self.count > 0 {
+            self.count.to_string()
+        } else {
+            String::new()
+        };
+        for key in &self.pending {
+            if let KeyCode::Char(c) = key.code {
+                shown.push(c);
+            }
+        }
+        Some(shown)
+    }
+
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.count = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    fn run(state: &mut KeyState, map: &KeyMap, keys: &str) -> Option<(Action, usize)> {
+        let mut last = None;
+        for c in keys.chars() {
+            last = match state.handle(map, press(c)) {
+                KeyOutcome::Action(action, count) => Some((action, count)),
+                _ => None,
+            };
+        }
+        last
+    }
+
+    #[test]
+    fn test_parse_binding() {
+        assert_eq!(
+            parse_binding("gg"),
+            Some(vec![
+                (KeyCode::Char('g'), KeyModifiers::NONE),
+                (KeyCode::Char('g'), KeyModifiers::NONE)
+            ])
+        );
+        assert_eq!(
+            parse_binding("ctrl-w l"),
+            Some(vec![
+                (KeyCode::Char('w'), KeyModifiers::CONTROL),
+                (KeyCode::Char('l'), KeyModifiers::NONE)
+            ])
+        );
+        assert_eq!(
+            parse_binding("PageDown"),
+            Some(vec![(KeyCode::PageDown, KeyModifiers::NONE)])
+        );
+        assert_eq!(
+            parse_binding("F5"),
+            Some(vec![(KeyCode::F(5), KeyModifiers::NONE)])
+        );
+        assert_eq!(
+            parse_binding("ctrl--"),
+            Some(vec![(KeyCode::Char('-'), KeyModifiers::CONTROL)])
+        );
+        assert_eq!(parse_binding("hyper-x"), None);
+    }
+
+    #[test]
+    fn test_modifiers_match_exactly() {
+        let keymaps = Keymaps::new(&Keybindings::default());
+        let ctrl_s = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        let ctrl_alt_s = KeyEvent::new(
+            KeyCode::Char('s'),
+            KeyModifiers::CONTROL | KeyModifiers::ALT,
+        );
+        assert_eq!(keymaps.compose.lookup(ctrl_s), Some(Action::SendMessage));
+        assert_eq!(keymaps.compose.lookup(ctrl_alt_s), None);
+        // Shift is part of the character itself
+        let shift_l = KeyEvent::new(KeyCode::Char('L'), KeyModifiers::SHIFT);
+        assert_eq!(keymaps.browsing.lookup(shift_l), Some(Action::ToggleLabels));
+    }
+
+    #[test]
+    fn test_sequences_and_counts() {
+        let keymaps = Keymaps::new(&Keybindings::default());
+        let mut state = KeyState::default();
+        assert_eq!(
+            run(&mut state, &keymaps.browsing, "gg"),
+            Some((Action::GoTop, 1))
+        );
+        assert_eq!(
+            run(&mut state, &keymaps.browsing, "gi"),
+            Some((Action::GoToInbox, 1))
+        );
+        assert_eq!(
+            run(&mut state, &keymaps.browsing, "12j"),
+            Some((Action::MoveDown, 12))
+        );
+        assert_eq!(state.pending(), None);
+        // An abandoned sequence doesn't swallow the key that broke it
+        assert_eq!(
+            run(&mut state, &keymaps.browsing, "gj"),
+            Some((Action::MoveDown, 1))
+        );
+    }
+
+    #[test]
+    fn test_details_overrides_use_keybinding_names() {
+        let mut keybindings = Keybindings::default();
+        keybindings
+            .details
+            .insert("move_down".to_string(), vec!["J".to_string()]);
+        let keymaps = Keymaps::new(&keybindings);
+        assert!(keymaps.problems.is_empty(), "{:?}", keymaps.problems);
+        assert_eq!(keymaps.details.lookup(press('J')), Some(Action::MoveDown));
+        assert_eq!(keymaps.details.lookup(press('j')), None);
+        assert_eq!(keymaps.browsing.lookup(press('j')), Some(Action::MoveDown));
+    }
+
+    #[test]
+    fn test_conflicts_are_reported() {
+        assert!(Keymaps::new(&Keybindings::default()).problems.is_empty());
+
+        let mut keybindings = Keybindings {
+            archive: vec!["d".to_string()],
+            go_top: vec!["g".to_string()],
+            send_message: vec!["s".to_string()],
+            ..Default::default()
+        };
+        keybindings
+            .details
+            .insert("move_dwon".to_string(), vec!["n".to_string()]);
+        let problems = Keymaps::new(&keybindings).problems;
+        assert!(
+            problems.contains(&"browsing: \"d\" is bound to both delete and archive".to_string())
+        );
+        assert!(problems.contains(&"browsing: \"g\" (top) hides \"gi\" (inbox)".to_string()));
+        assert!(
+            problems
+                .contains(&"compose: \"s\" for send_message would stop you typing it".to_string())
+        );
+        assert!(problems.contains(&"details: no keybinding named move_dwon".to_string()));
+    }
+}
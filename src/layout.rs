@@ -0,0 +1,183 @@
+use crate::config::{LayoutConfig, Orientation};
+use crate::ui::FocusedPanel;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+
+/// How much one resize keypress moves a panel edge, in percent
+const RESIZE_STEP: u16 = 5;
+const MIN_PANEL_PCT: u16 = 5;
+/// What is left for Details never drops below this
+const MIN_DETAILS_PCT: u16 = 10;
+
+/// Panel sizes and visibility, seeded from `[layout]` and changed at runtime
+#[derive(Debug, Clone)]
+pub struct LayoutState {
+    pub labels_pct: u16,
+    pub list_pct: u16,
+    pub orientation: Orientation,
+    /// Terminals narrower than this stack panels when orientation is `auto`
+    pub stack_below: u16,
+    pub show_labels: bool,
+    /// Show only the focused panel, full screen
+    pub zoomed: bool,
+}
+
+impl Default for LayoutState {
+    fn default() -> Self {
+        Self::from_config(&LayoutConfig::default())
+    }
+}
+
+/// Where each browsing panel was drawn. A hidden panel gets an empty `Rect`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PanelAreas {
+    pub labels: Rect,
+    pub messages: Rect,
+    pub details: Rect,
+}
+
+impl LayoutState {
+    pub fn from_config(config: &LayoutConfig) -> Self {
+        let mut state = Self {
+            labels_pct: config.labels_width,
+            list_pct: config.list_width,
+            orientation: config.orientation,
+            stack_below: config.stack_below,
+            show_labels: config.show_labels,
+            zoomed: false,
+        };
+        // Bring hand-edited percentages back into range
+        state.resize(FocusedPanel::Labels, 0);
+        state.resize(FocusedPanel::Messages, 0);
+        state
+    }
+
+    /// Grow (positive `steps`) or shrink the focused panel. Details has no
+    /// size of its own; it gets whatever Labels and the list leave over.
+    pub fn resize(&mut self, focused: FocusedPanel, steps: i16) {
+        let delta = steps as i32 * RESIZE_STEP as i32;
+        // Signed, since `other` may come straight from a hand-edited config
+        let clamp = |pct: i32, other: u16| {
+            let max = (100 - MIN_DETAILS_PCT as i32 - other as i32).max(MIN_PANEL_PCT as i32);
+            pct.clamp(MIN_PANEL_PCT as i32, max) as u16
+        };
+        match focused {
+            FocusedPanel::Labels => {
+                self.labels_pct = clamp(self.labels_pct as i32 + delta, self.list_pct)
+            }
+            FocusedPanel::Messages => {
+                self.list_pct = clamp(self.list_pct as i32 + delta, self.labels_pct)
+            }
+            FocusedPanel::Details => {
+                self.list_pct = clamp(self.list_pct as i32 - delta, self.labels_pct)
+            }
+        }
+    }
+
+    pub fn is_stacked(&self, area: Rect) -> bool {
+        match self.orientation {
+            Orientation::Horizontal => false,
+            Orientation::Vertical => true,
+            Orientation::Auto => area.width < self.stack_below,
+        }
+    }
+
+    /// Split `area` into the three browsing panels
+    pub fn panel_areas(&self, area: Rect, focused: FocusedPanel) -> PanelAreas {
+        if self.zoomed {
+            let mut areas = PanelAreas::default();
+            match focused {
+                FocusedPanel::Labels => areas.labels = area,
+                FocusedPanel::Messages => areas.messages = area,
+                FocusedPanel::Details => areas.details = area,
+            }
+            return areas;
+        }
+
+        let direction = if self.is_stacked(area) {
+            Direction::Vertical
+        } else {
+            Direction::Horizontal
+        };
+        let labels_pct = if self.show_labels { self.labels_pct } else { 0 };
+        let chunks = Layout::default()
+            .direction(direction)
+            .constraints([
+                Constraint::Percentage(labels_pct),
+                Constraint::Percentage(self.list_pct),
+                Constraint::Min(0),
+            ])
+            .split(area);
+
+        PanelAreas {
+            labels: if self.show_labels {
+                chunks[0]
+            } else {
+                Rect::default()
+            },
+            messages: chunks[1],
+            details: chunks[2],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resize_keeps_room_for_details() {
+        let mut layout = LayoutState::default();
+        for _ in 0..40 {
+            layout.resize(FocusedPanel::Messages, 1);
+        }
+        assert_eq!(layout.labels_pct + layout.list_pct, 100 - MIN_DETAILS_PCT);
+        for _ in 0..40 {
+            layout.resize(FocusedPanel::Details, 1);
+        }
+        assert_eq!(layout.list_pct, MIN_PANEL_PCT);
+    }
+
+    #[test]
+    fn test_out_of_range_config_is_clamped() {
+        let layout = LayoutState::from_config(&LayoutConfig {
+            labels_width: 95,
+            list_width: 95,
+            ..Default::default()
+        });
+        assert_eq!(layout.labels_pct, MIN_PANEL_PCT);
+        assert_eq!(layout.list_pct, 100 - MIN_DETAILS_PCT - MIN_PANEL_PCT);
+
+        let layout = LayoutState::from_config(&LayoutConfig {
+            labels_width: 0,
+            list_width: 500,
+            ..Default::default()
+        });
+        assert_eq!(layout.labels_pct + layout.list_pct, 100 - MIN_DETAILS_PCT);
+    }
+
+    #[test]
+    fn test_auto_stacks_on_narrow_terminals() {
+        let layout = LayoutState::default();
+        let narrow = layout.panel_areas(Rect::new(0, 0, 60, 40), FocusedPanel::Messages);
+        assert_eq!(narrow.messages.width, 60);
+        let wide = layout.panel_areas(Rect::new(0, 0, 200, 40), FocusedPanel::Messages);
+        assert_eq!(wide.messages.height, 40);
+    }
+
+    #[test]
+    fn test_zoom_and_hidden_labels() {
+        let mut layout = LayoutState {
+            show_labels: false,
+            ..Default::default()
+        };
+        let area = Rect::new(0, 0, 200, 40);
+        let areas = layout.panel_areas(area, FocusedPanel::Messages);
+        assert_eq!(areas.labels, Rect::default());
+        assert_eq!(areas.messages.x, 0);
+
+        layout.zoomed = true;
+        let areas = layout.panel_areas(area, FocusedPanel::Details);
+        assert_eq!(areas.details, area);
+        assert_eq!(areas.messages, Rect::default());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::config::LogConfig;
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::{Context as LayerContext, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, fmt};
+
+/// Lines kept for the in-app console
+const CONSOLE_LINES: usize = 500;
+
+/// One event as shown in the log console
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub time: String,
+    pub level: Level,
+    pub text: String,
+}
+
+/// The most recent events, newest last, shared with the UI
+#[derive(Debug, Clone, Default)]
+pub struct LogBuffer(Arc<Mutex<VecDeque<LogLine>>>);
+
+impl LogBuffer {
+    fn push(&self, line: LogLine) {
+        if let Ok(mut lines) = self.0.lock() {
+            if lines.len() >= CONSOLE_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line);
+        }
+    }
+
+    /// The last `count` lines, oldest first
+    pub fn tail(&self, count: usize) -> Vec<LogLine> {
+        let Ok(lines) = self.0.lock() else {
+            return Vec::new();
+        };
+        lines
+            .iter()
+            .skip(lines.len().saturating_sub(count))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Copies events into a `LogBuffer` for the console
+struct ConsoleLayer {
+    buffer: LogBuffer,
+}
+
+impl<S: Subscriber> Layer<S> for ConsoleLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: LayerContext<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        self.buffer.push(LogLine {
+            time: chrono::Local::now().format("%H:%M:%S").to_string(),
+            level: *event.metadata().level(),
+            text: visitor.message + &visitor.fields,
+        });
+    }
+}
+
+/// Collects an event's message and its `key=value` fields
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            let _ = write!(self.fields, " {}={}", field.name(), value);
+        }
+    }
+}
+
+/// Send all tracing output to a daily log file in `dir`, never to the
+/// terminal, and our own info-and-above events to `console` as well.
+/// `RUST_LOG` overrides the configured level; `--debug` raises it to
+/// `debug`. Keep the returned guard alive until exit so buffered lines are
+/// written.
+pub fn init(
+    dir: &Path,
+    config: &LogConfig,
+    debug: bool,
+    console: LogBuffer,
+) -> Result<WorkerGuard> {
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("gtui")
+        .filename_suffix("log")
+        .max_log_files(config.max_files.max(1))
+        .build(dir)
+        .with_context(|| format!("Failed to open a log file in {}", dir.display()))?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    let level = if debug {
+        "debug"
+    } else {
+        config.level.as_str()
+    };
+    // A bare level applies to our own events; anything else is a full filter
+    let directives = if level.contains(['=', ',']) {
+        level.to_string()
+    } else {
+        format!("gtui={}", level)
+    };
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(directives))
+        .context("Invalid log level")?;
+
+    tracing_subscriber::registry()
+        .with(
+            fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_filter(filter),
+        )
+        .with(
+            ConsoleLayer { buffer: console }
+                .with_filter(Targets::new().with_target("gtui", Level::INFO)),
+        )
+        .init();
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_console_keeps_recent_events() {
+        let buffer = LogBuffer::default();
+        let subscriber = tracing_subscriber::registry().with(ConsoleLayer {
+            buffer: buffer.clone(),
+        });
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(status = 429, "Gmail request failed");
+            assert_eq!(buffer.tail(1)[0].text, "Gmail request failed status=429");
+            assert_eq!(buffer.tail(1)[0].level, Level::WARN);
+            for i in 0..CONSOLE_LINES {
+                tracing::info!("line {}", i);
+            }
+        });
+        let all = buffer.tail(usize::MAX);
+        assert_eq!(all.len(), CONSOLE_LINES);
+        assert_eq!(all[0].text, "line 0");
+        let tail = buffer.tail(2);
+        assert_eq!(tail[1].text, format!("line {}", CONSOLE_LINES - 1));
+    }
+}
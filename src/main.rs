@@ -1,1088 +1,2395 @@
+mod actions;
+mod address;
 mod auth;
+mod commands;
 mod config;
+mod contacts;
 mod db;
+mod editor;
+mod fuzzy;
 mod gmail;
+mod keymap;
+mod layout;
+mod logging;
+mod markdown;
 mod models;
+mod notifications;
+mod outgoing;
+mod paths;
+mod quota;
+mod row_template;
 mod sync;
+mod terminal;
+mod theme;
 mod ui;
 mod undo;
 
+use crate::actions::ActionResult;
+use crate::commands::Action;
 use crate::config::{Config, matches_key};
 use crate::gmail::GmailClient;
+use crate::notifications::Level;
 use crate::ui::FocusedPanel;
 use crate::undo::UndoableAction;
 use chrono::{DateTime, Local};
 use crossterm::{
-    event::{self, Event, KeyCode},
+    event::{Event, EventStream, KeyCode, MouseButton, MouseEvent, MouseEventKind},
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode},
 };
+use futures::StreamExt;
 use google_gmail1::Gmail;
 use ratatui::Terminal;
 use ratatui::backend::CrosstermBackend;
 use std::io;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// How often the screen is redrawn when nothing else happens, so background
+/// progress such as syncing shows up
+const RENDER_TICK: Duration = Duration::from_millis(250);
+
+/// Everything the main loop waits on
+enum LoopEvent {
+    Input(Event),
+    AuthUrl(String),
+    Authenticated,
+    Refresh,
+    Action(ActionResult),
+    Tick,
+    /// SIGTERM or SIGHUP
+    Exit(&'static str),
+}
 
-/// Write to debug log file if debug mode is enabled
-fn debug_log(enabled: bool, msg: &str) {
-    if enabled {
-        if let Ok(mut file) = std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open("gtui_debug.log")
-        {
-            use std::io::Write;
-            let _ = writeln!(file, "{}", msg);
-        }
+/// Clicks on the same conversation within this window count as a double-click
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+/// Lines the Details panel moves per scroll-wheel notch
+const WHEEL_SCROLL_LINES: u16 = 3;
+
+/// Select the label at `index` and load the first page of its conversations
+async fn select_label(
+    ui_state: &mut ui::UIState<'_>,
+    db: &db::Database,
+    index: usize,
+    limit: i64,
+    priority_tx: &mpsc::Sender<String>,
+) -> anyhow::Result<()> {
+    let Some(label) = ui_state.labels.get(index) else {
+        return Ok(());
+    };
+    let label_id = label.id.clone();
+    ui_state.selected_label_index = index;
+    ui_state.search_query = None;
+    ui_state.jump_target = None;
+    ui_state.messages = db.get_messages_by_label(&label_id, limit, 0).await?;
+    ui_state.end_of_cache = (ui_state.messages.len() as i64) < limit;
+    ui_state.selected_message_index = 0;
+    ui_state.detail_scroll = 0;
+    if let Some(msg) = ui_state.messages.first() {
+        ui_state.threaded_messages = db.get_messages_by_thread(&msg.thread_id).await?;
+    } else {
+        ui_state.threaded_messages.clear();
     }
+    let _ = priority_tx.try_send(label_id);
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let config = Config::load();
-    let debug_logging = std::env::args().any(|arg| arg == "--debug");
-    let db_url = "sqlite:gtui.db?mode=rwc".to_string();
-    let db = db::Database::new(&db_url).await?;
-    db.run_migrations().await?;
-
-    // Handle token reset
-    if std::env::args().any(|arg| arg == "--reset-token") {
-        auth::RingStorage.clear_token().await?;
-        println!("Token cleared. Please restart without --reset-token to re-authenticate.");
+/// Select the conversation at `index` and load its thread into Details
+async fn select_message(
+    ui_state: &mut ui::UIState<'_>,
+    db: &db::Database,
+    index: usize,
+) -> anyhow::Result<()> {
+    let Some(msg) = ui_state.messages.get(index) else {
         return Ok(());
+    };
+    tracing::debug!(
+        "[Main] Navigating: idx {} -> {}, thread_id: {:?}",
+        ui_state.selected_message_index,
+        index,
+        msg.thread_id
+    );
+    let thread_id = msg.thread_id.clone();
+    ui_state.selected_message_index = index;
+    ui_state.detail_scroll = 0;
+    ui_state.threaded_messages = db.get_messages_by_thread(&thread_id).await?;
+    tracing::debug!(
+        "[Main] Loaded {} messages for thread",
+        ui_state.threaded_messages.len()
+    );
+    Ok(())
+}
+
+/// Append the next page of conversations once the selection nears the end.
+/// Offsets count conversations already loaded, so pages never overlap.
+async fn load_more_if_near_end(
+    ui_state: &mut ui::UIState<'_>,
+    db: &db::Database,
+    limit: i64,
+) -> anyhow::Result<()> {
+    if ui_state.selected_message_index >= ui_state.messages.len().saturating_sub(5) {
+        let offset = ui_state.messages.len() as i64;
+        let mut additional = load_conversations(ui_state, db, limit, offset).await?;
+        ui_state.end_of_cache = (additional.len() as i64) < limit;
+        ui_state.messages.append(&mut additional);
+        // Past the end of the cache, ask Gmail for older mail
+        ui_state.want_older_page = ui_state.end_of_cache && ui_state.search_query.is_none();
     }
+    Ok(())
+}
 
-    // Setup terminal early
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(
-        stdout,
-        crossterm::terminal::EnterAlternateScreen,
-        crossterm::event::EnableMouseCapture
-    )?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+/// A page of the list being shown: search results, or the selected label
+async fn load_conversations(
+    ui_state: &ui::UIState<'_>,
+    db: &db::Database,
+    limit: i64,
+    offset: i64,
+) -> anyhow::Result<Vec<models::Message>> {
+    if let Some(query) = &ui_state.search_query {
+        return db.search_messages(query, limit, offset).await;
+    }
+    match ui_state.labels.get(ui_state.selected_label_index) {
+        Some(label) => db.get_messages_by_label(&label.id, limit, offset).await,
+        None => Ok(Vec::new()),
+    }
+}
 
-    let mut ui_state = ui::UIState::default();
-    ui_state.debug_logging = debug_logging;
+/// Put a deleted or archived thread back in the database and the list,
+/// without touching Gmail. Used by undo and to roll back failed API calls.
+async fn restore_locally(
+    ui_state: &mut ui::UIState<'_>,
+    db: &db::Database,
+    action: &UndoableAction,
+) -> anyhow::Result<()> {
+    match action {
+        UndoableAction::Delete {
+            messages,
+            label_id,
+            original_index,
+        } => {
+            // Get the representative message (first one) for UI insertion
+            let representative = messages.first().cloned().unwrap_or_default();
+
+            // Re-insert into UI at original position (clamped to list size)
+            let insert_index = (*original_index).min(ui_state.messages.len());
+            ui_state
+                .messages
+                .insert(insert_index, representative.clone());
+            ui_state.selected_message_index = insert_index;
+
+            // Re-insert all messages into database
+            if let Err(e) = db.upsert_messages(messages, label_id).await {
+                tracing::warn!("Restoring deleted messages locally failed: {:#}", e);
+            }
 
-    // Shared sync state for UI awareness
-    let sync_state = Arc::new(Mutex::new(sync::SyncState::default()));
-    ui_state.sync_state = sync_state.clone();
+            // Refresh detail view
+            ui_state.threaded_messages =
+                db.get_messages_by_thread(&representative.thread_id).await?;
+        }
+        UndoableAction::Archive {
+            messages,
+            label_id,
+            original_index,
+        } => {
+            // Get the representative message (first one) for UI insertion
+            let representative = messages.first().cloned().unwrap_or_default();
+
+            // Re-insert into UI at original position (only if viewing the same label)
+            let viewing_label = ui_state
+                .labels
+                .get(ui_state.selected_label_index)
+                .is_some_and(|l| &l.id == label_id);
+            if viewing_label {
+                let insert_index = (*original_index).min(ui_state.messages.len());
+                ui_state
+                    .messages
+                    .insert(insert_index, representative.clone());
+                ui_state.selected_message_index = insert_index;
+            }
 
-    // Initial Auth setup
-    let secret = auth::Authenticator::load_secret("credentials.json").await?;
+            // Re-add the removed label in database for all messages
+            let ids: Vec<String> = messages.iter().map(|m| m.id.clone()).collect();
+            if let Err(e) = db.add_label_to_messages(&ids, label_id).await {
+                tracing::warn!("Restoring archived messages locally failed: {:#}", e);
+            }
 
-    use tokio::sync::mpsc;
-    let (tx, mut rx) = mpsc::channel::<String>(1);
-    let (done_tx, mut done_rx) = mpsc::channel::<bool>(1);
-    let (refresh_tx, mut refresh_rx) = mpsc::channel::<()>(1);
-    let (priority_tx, priority_rx) = mpsc::channel::<String>(16);
-    let mut priority_rx = Some(priority_rx);
+            // Refresh detail view if message was re-added
+            if viewing_label {
+                ui_state.threaded_messages =
+                    db.get_messages_by_thread(&representative.thread_id).await?;
+            }
+        }
+    }
+    Ok(())
+}
 
-    let auth_builder = auth::Authenticator::authenticate(secret, auth::TuiDelegate { tx }).await?;
+/// Drop the selected conversation from the list after a delete or archive
+/// and show whichever one takes its place
+async fn remove_selected_thread(
+    ui_state: &mut ui::UIState<'_>,
+    db: &db::Database,
+) -> anyhow::Result<()> {
+    if ui_state.selected_message_index < ui_state.messages.len() {
+        ui_state.messages.remove(ui_state.selected_message_index);
+    }
+    if ui_state.selected_message_index >= ui_state.messages.len() && !ui_state.messages.is_empty() {
+        ui_state.selected_message_index = ui_state.messages.len() - 1;
+    }
 
-    let auth_clone = auth_builder.clone();
+    // Refresh detail view
+    if let Some(msg) = ui_state.messages.get(ui_state.selected_message_index) {
+        ui_state.threaded_messages = db.get_messages_by_thread(&msg.thread_id).await?;
+    } else {
+        ui_state.threaded_messages.clear();
+    }
+    Ok(())
+}
+
+/// Fetch the next older page of the current label from Gmail in the
+/// background, unless one is already on its way or there is nothing older
+fn request_older_page(
+    ui_state: &mut ui::UIState<'_>,
+    gmail_client: Option<&GmailClient>,
+    sync_state: &Arc<Mutex<sync::SyncState>>,
+    action_tx: &mpsc::UnboundedSender<ActionResult>,
+    db: &db::Database,
+    body_on_demand: bool,
+) {
+    ui_state.want_older_page = false;
+    let (Some(gmail), Some(label)) = (
+        gmail_client,
+        ui_state.labels.get(ui_state.selected_label_index),
+    ) else {
+        return;
+    };
+    let label_id = label.id.clone();
+    let page_token = {
+        let Ok(mut state) = sync_state.lock() else {
+            return;
+        };
+        let page_token = match state.older_pages.get(&label_id) {
+            Some(sync::OlderPages::Done) => return,
+            Some(sync::OlderPages::Next(token)) => Some(token.clone()),
+            None => None,
+        };
+        if !state.fetching_older.insert(label_id.clone()) {
+            return;
+        }
+        page_token
+    };
+
+    if ui_state.jump_target.is_none() {
+        ui_state.notify(Level::Info, "Loading older mail...");
+    }
+    let gmail = gmail.clone();
+    let sync_state = sync_state.clone();
+    let action_tx = action_tx.clone();
+    let db = db.clone();
     tokio::spawn(async move {
-        if let Ok(_) = auth_clone.token(auth::SCOPES).await {
-            let _ = done_tx.send(true).await;
+        let result =
+            sync::fetch_older_page(&gmail, &db, &label_id, page_token, body_on_demand).await;
+        let result = match result {
+            Ok((fetched, next)) => {
+                if let Ok(mut state) = sync_state.lock() {
+                    state.older_pages.insert(label_id.clone(), next);
+                }
+                Ok(fetched)
+            }
+            Err(e) => Err(format!("{:#}", e)),
+        };
+        if let Ok(mut state) = sync_state.lock() {
+            state.fetching_older.remove(&label_id);
         }
+        let _ = action_tx.send(ActionResult::OlderPage { label_id, result });
     });
+}
 
-    let mut authenticated = false;
-    let mut current_offset = 0;
-    let limit = 50;
-
-    // We'll hold these in Options until authenticated
-    let mut gmail_client: Option<GmailClient> = None;
-
-    // Clone sync_state for use in the main loop
-    let sync_state_loop = sync_state.clone();
+/// How long exiting waits for Gmail changes still being sent
+const EXIT_GRACE: Duration = Duration::from_secs(10);
+
+/// Before exiting, keep an open composition as an outbox draft and give
+/// Gmail changes still in flight a chance to finish
+async fn flush_before_exit(ui_state: &ui::UIState<'_>, db: &db::Database) {
+    if let Some(cs) = &ui_state.compose_state
+        && !(cs.get_to().trim().is_empty()
+            && cs.get_subject().trim().is_empty()
+            && cs.get_body().trim().is_empty())
+    {
+        let mut draft = cs.to_outbox();
+        draft.draft = true;
+        draft.last_error = Some("Saved unsent when gtui exited".to_string());
+        match db.queue_outbox(&draft).await {
+            Ok(id) => tracing::info!("Saved the open message to the outbox as {}", id),
+            Err(e) => tracing::error!("Saving the open message failed: {:#}", e),
+        }
+    }
+    let pending = ui_state.pending.count();
+    if pending > 0 {
+        tracing::info!("Waiting for {} Gmail change(s) to finish", pending);
+        let left = ui_state.pending.wait(EXIT_GRACE).await;
+        if left > 0 {
+            tracing::warn!("Exiting with {} Gmail change(s) unfinished", left);
+        }
+    }
+}
 
-    loop {
-        // Check for auth messages
-        while let Ok(url) = rx.try_recv() {
-            ui_state.auth_url = Some(url);
-            ui_state.mode = ui::UIMode::Authentication;
+/// Send a message already saved to the outbox. On success it leaves the
+/// outbox and the sent copy is cached; on failure it stays there with the
+/// error, for the user to retry or reopen.
+fn send_from_outbox(
+    ui_state: &mut ui::UIState<'_>,
+    gmail: &GmailClient,
+    db: &db::Database,
+    outbox_id: i64,
+    message: outgoing::OutgoingMessage,
+    action_tx: &mpsc::UnboundedSender<ActionResult>,
+    refresh_tx: &mpsc::Sender<()>,
+) {
+    if !ui_state.sending.insert(outbox_id) {
+        return;
+    }
+    let gmail = gmail.clone();
+    let db = db.clone();
+    let action_tx = action_tx.clone();
+    let refresh_tx = refresh_tx.clone();
+    let pending = ui_state.pending.start();
+    tokio::spawn(async move {
+        let _pending = pending;
+        let sent_id = match gmail.send_message(&message).await {
+            Ok(sent_id) => sent_id,
+            Err(e) => {
+                let error = format!("{:#}", e);
+                if let Err(e) = db.set_outbox_error(outbox_id, &error).await {
+                    tracing::warn!("Recording the send error failed: {:#}", e);
+                }
+                let _ = action_tx.send(ActionResult::SendFailed { outbox_id, error });
+                return;
+            }
+        };
+        if let Err(e) = db.delete_outbox(outbox_id).await {
+            tracing::error!(
+                "Removing sent message {} from the outbox failed: {:#}",
+                outbox_id,
+                e
+            );
+        }
+        let _ = action_tx.send(ActionResult::Sent { outbox_id });
+
+        // Fetch the sent message to get full details including thread_id
+        if let Some(sent_id) = sent_id {
+            match gmail.get_message(&sent_id).await {
+                Ok(sent_msg) => {
+                    // Store in database with SENT label
+                    if let Err(e) = db
+                        .upsert_messages(std::slice::from_ref(&sent_msg), "SENT")
+                        .await
+                    {
+                        tracing::warn!("Caching the sent message failed: {:#}", e);
+                    }
+                    if let Err(e) = db.record_contacts(&[sent_msg]).await {
+                        let _ = action_tx.send(ActionResult::Failed(format!(
+                            "Adding the recipients to the address book failed: {:#}",
+                            e
+                        )));
+                    }
+                    // Trigger a refresh so the UI updates
+                    let _ = refresh_tx.send(()).await;
+                }
+                Err(e) => tracing::warn!("Fetching the sent message failed: {:#}", e),
+            }
         }
+    });
+}
 
-        if !authenticated {
-            if let Ok(true) = done_rx.try_recv() {
-                authenticated = true;
-                ui_state.mode = ui::UIMode::Browsing;
-                ui_state.auth_url = None;
-
-                // Now create the hub and client
-                let hub = Gmail::new(
-                    hyper::Client::builder().build(
-                        hyper_rustls::HttpsConnectorBuilder::new()
-                            .with_native_roots()
-                            .expect("Failed to load native roots")
-                            .https_only()
-                            .enable_http1()
-                            .build(),
-                    ),
-                    auth_builder.clone(),
-                );
+/// In body-on-demand mode, fetch the bodies of the open conversation the
+/// first time it is shown
+fn request_bodies(
+    ui_state: &ui::UIState<'_>,
+    gmail_client: Option<&GmailClient>,
+    sync_state: &Arc<Mutex<sync::SyncState>>,
+    action_tx: &mpsc::UnboundedSender<ActionResult>,
+    db: &db::Database,
+) {
+    let (Some(gmail), Some(first)) = (gmail_client, ui_state.threaded_messages.first()) else {
+        return;
+    };
+    let ids: Vec<String> = ui_state
+        .threaded_messages
+        .iter()
+        .filter(|m| m.body_plain.is_none() && m.body_html.is_none())
+        .map(|m| m.id.clone())
+        .collect();
+    if ids.is_empty() {
+        return;
+    }
+    let thread_id = first.thread_id.clone();
+    let Ok(mut state) = sync_state.lock() else {
+        return;
+    };
+    if !state.fetched_bodies.insert(thread_id.clone()) {
+        return;
+    }
+    drop(state);
 
-                let client = GmailClient::new(hub, debug_logging);
-                gmail_client = Some(client.clone());
+    let gmail = gmail.clone();
+    let sync_state = sync_state.clone();
+    let action_tx = action_tx.clone();
+    let db = db.clone();
+    tokio::spawn(async move {
+        let result = sync::fetch_bodies(&gmail, &db, &ids).await.map_err(|e| {
+            // Try again next time the conversation is opened
+            if let Ok(mut state) = sync_state.lock() {
+                state.fetched_bodies.remove(&thread_id);
+            }
+            format!("{:#}", e)
+        });
+        let _ = action_tx.send(ActionResult::Bodies { thread_id, result });
+    });
+}
 
-                // Fetch remote signature
-                if let Ok(Some(sig)) = client.get_signature().await {
-                    ui_state.remote_signature = Some(sig);
+/// Reconcile the UI with a finished background task
+async fn apply_action_result(
+    ui_state: &mut ui::UIState<'_>,
+    db: &db::Database,
+    sync_state: &Mutex<sync::SyncState>,
+    result: ActionResult,
+    limit: i64,
+) -> anyhow::Result<()> {
+    match result {
+        ActionResult::OlderPage { label_id, result } => {
+            let showing = ui_state.search_query.is_none()
+                && ui_state
+                    .labels
+                    .get(ui_state.selected_label_index)
+                    .is_some_and(|l| l.id == label_id);
+            if !showing {
+                return Ok(());
+            }
+            let fetched = match result {
+                Ok(fetched) => fetched,
+                Err(e) => {
+                    ui_state.notify(Level::Error, format!("Loading older mail failed: {}", e));
+                    ui_state.jump_target = None;
+                    return Ok(());
                 }
+            };
+            let offset = ui_state.messages.len() as i64;
+            let mut additional = load_conversations(ui_state, db, limit, offset).await?;
+            ui_state.end_of_cache = (additional.len() as i64) < limit;
+            let added = !additional.is_empty();
+            ui_state.messages.append(&mut additional);
+            let more_remote = sync_state.lock().is_ok_and(|state| {
+                state.older_pages.get(&label_id) != Some(&sync::OlderPages::Done)
+            });
+
+            if let Some(target) = ui_state.jump_target {
+                if let Some(index) = ui_state
+                    .messages
+                    .iter()
+                    .position(|m| m.internal_date <= target)
+                {
+                    ui_state.jump_target = None;
+                    select_message(ui_state, db, index).await?;
+                } else if more_remote {
+                    // Keep paging back until the date is reached
+                    ui_state.want_older_page = true;
+                } else {
+                    ui_state.jump_target = None;
+                    ui_state.notify(Level::Warn, "No mail that old");
+                }
+            } else if fetched == 0 && !added && more_remote {
+                // That page was already cached; keep going until something new shows up
+                ui_state.want_older_page = true;
+            } else if !added {
+                ui_state.notify(Level::Info, "No older mail");
+            }
+        }
+        ActionResult::Bodies { thread_id, result } => match result {
+            Ok(()) => {
+                let thread = db.get_messages_by_thread(&thread_id).await?;
+                // List rows are copies too; reply and forward read from them
+                for row in ui_state
+                    .messages
+                    .iter_mut()
+                    .filter(|m| m.thread_id == thread_id)
+                {
+                    if let Some(fetched) = thread.iter().find(|m| m.id == row.id) {
+                        row.body_plain = fetched.body_plain.clone();
+                        row.body_html = fetched.body_html.clone();
+                    }
+                }
+                if ui_state
+                    .threaded_messages
+                    .first()
+                    .is_some_and(|m| m.thread_id == thread_id)
+                {
+                    ui_state.threaded_messages = thread;
+                }
+            }
+            Err(e) => ui_state.notify(Level::Error, format!("Loading message failed: {}", e)),
+        },
+        ActionResult::SendAs(send_as) => ui_state.send_as = send_as,
+        ActionResult::Sent { outbox_id } => {
+            ui_state.sending.remove(&outbox_id);
+            ui_state.outbox = db.get_outbox().await?;
+            ui_state.notify(Level::Info, "Message sent");
+        }
+        ActionResult::SendFailed { outbox_id, error } => {
+            ui_state.sending.remove(&outbox_id);
+            ui_state.outbox = db.get_outbox().await?;
+            ui_state.notify(
+                Level::Error,
+                format!(
+                    "Send failed: {}. The message is in the outbox; use :outbox retry or :outbox edit",
+                    error
+                ),
+            );
+        }
+        ActionResult::ReadStateFailed { id, is_read, error } => {
+            if let Some(m) = ui_state.messages.iter_mut().find(|m| m.id == id) {
+                m.is_read = is_read;
+            }
+            ui_state.notify(
+                Level::Error,
+                format!(
+                    "Marking {} failed: {}",
+                    if is_read { "unread" } else { "read" },
+                    error
+                ),
+            );
+        }
+        ActionResult::LabelFailed {
+            add,
+            label_id,
+            message_ids,
+            row,
+            index,
+            error,
+        } => {
+            let restored = if add {
+                db.remove_label_from_messages(&message_ids, &label_id).await
+            } else {
+                db.add_label_to_messages(&message_ids, &label_id).await
+            };
+            if let Err(e) = restored {
+                tracing::warn!("Rolling back the label change locally failed: {:#}", e);
+            }
+            // Let sync pick these messages up again
+            if let Ok(mut state) = sync_state.lock() {
+                for id in &message_ids {
+                    state.recently_modified.remove(id);
+                }
+            }
+            if let Some(m) = ui_state
+                .messages
+                .iter_mut()
+                .find(|m| m.thread_id == row.thread_id)
+            {
+                m.thread_label_ids = row.thread_label_ids;
+            } else if !add
+                && ui_state.search_query.is_none()
+                && ui_state
+                    .labels
+                    .get(ui_state.selected_label_index)
+                    .is_some_and(|l| l.id == label_id)
+            {
+                // Removing the label took the conversation out of this list
+                let index = index.min(ui_state.messages.len());
+                ui_state.messages.insert(index, *row);
+                if ui_state.selected_message_index >= index && ui_state.messages.len() > 1 {
+                    ui_state.selected_message_index += 1;
+                }
+            }
+            ui_state.notify(Level::Error, format!("Label failed: {}", error));
+        }
+        ActionResult::Failed(message) => ui_state.notify(Level::Error, message),
+        ActionResult::Applied {
+            action,
+            result: Ok(()),
+        } => {
+            ui_state.notify(
+                Level::Info,
+                match action {
+                    UndoableAction::Delete { .. } => "Deleted successfully",
+                    UndoableAction::Archive { .. } => "Archived successfully",
+                },
+            );
+        }
+        ActionResult::Applied {
+            action,
+            result: Err(e),
+        } => {
+            ui_state.notify(
+                Level::Error,
+                match action {
+                    UndoableAction::Delete { .. } => format!("Delete failed: {}", e),
+                    UndoableAction::Archive { .. } => format!("Archive failed: {}", e),
+                },
+            );
+            // Let sync pick these messages up again
+            if let Ok(mut state) = sync_state.lock() {
+                for message in action.messages() {
+                    state.recently_modified.remove(&message.id);
+                }
+            }
+            // If the user already undid it there is nothing left to roll back
+            if let Some(pos) = ui_state.undo_stack.iter().rposition(|a| a.same_as(&action)) {
+                ui_state.undo_stack.remove(pos);
+                restore_locally(ui_state, db, &action).await?;
+            }
+        }
+    }
+    Ok(())
+}
 
-                // Kick off sync
-                let sync_client = client.clone();
-                let sync_db_url = db_url.clone();
-                let sync_refresh_tx = refresh_tx.clone();
-                let sync_state_clone = sync_state.clone();
-                let mut priority_rx = priority_rx.take().unwrap();
-                tokio::spawn(async move {
-                    if let Ok(sync_db) = db::Database::new(&sync_db_url).await {
-                        loop {
-                            let mut has_new_data = false;
-                            if let Ok(l) = sync_client.list_labels().await {
-                                let _ = sync_db.upsert_labels(&l).await;
-                                has_new_data = true;
-
-                                // Build label list, with priority label first
-                                let mut label_ids: Vec<String> =
-                                    l.iter().map(|label| label.id.clone()).collect();
-
-                                // Drain priority channel and move priority label to front
-                                let mut priority_label = None;
-                                while let Ok(p) = priority_rx.try_recv() {
-                                    priority_label = Some(p);
-                                }
-                                if let Some(ref priority) = priority_label {
-                                    if let Some(pos) =
-                                        label_ids.iter().position(|id| id == priority)
-                                    {
-                                        let p = label_ids.remove(pos);
-                                        label_ids.insert(0, p);
-                                    }
-                                }
-
-                                for label_id in &label_ids {
-                                    // Update currently_syncing state
-                                    if let Ok(mut state) = sync_state_clone.lock() {
-                                        state.currently_syncing = Some(label_id.clone());
-                                    }
+/// One pass over every label the sync policy selects: list what Gmail has,
+/// fetch what the cache lacks and drop labels that were removed elsewhere
+#[tracing::instrument(skip_all, name = "sync")]
+async fn sync_pass(
+    client: &GmailClient,
+    db: &db::Database,
+    policy: &sync::SyncPolicy,
+    sync_state: &Mutex<sync::SyncState>,
+    refresh_tx: &mpsc::Sender<()>,
+    priority_rx: &mut mpsc::Receiver<String>,
+    priority_label: &mut Option<String>,
+) {
+    let mut has_new_data = false;
+    let mut fetched = 0;
+    let mut failed = 0;
+    if let Ok(l) = client.list_labels().await {
+        if let Err(e) = db.upsert_labels(&l).await {
+            tracing::warn!("Saving labels failed: {:#}", e);
+        }
+        has_new_data = true;
 
-                                    // Clean up expired entries from recently_modified
-                                    if let Ok(mut state) = sync_state_clone.lock() {
-                                        state.cleanup_expired();
-                                    }
+        while let Ok(p) = priority_rx.try_recv() {
+            *priority_label = Some(p);
+        }
+        let labels = policy.labels_to_sync(&l, priority_label.as_deref());
+
+        for label in &labels {
+            let label_id = &label.id;
+            let depth = policy.depth(label);
+            // Update currently_syncing state
+            if let Ok(mut state) = sync_state.lock() {
+                state.currently_syncing = Some(label_id.clone());
+            }
 
-                                    if let Ok((ids, next_page_token)) = sync_client
-                                        .list_messages(vec![label_id.to_string()], 100, None)
-                                        .await
-                                    {
-                                        let mut messages = Vec::new();
-                                        let mut remote_ids = std::collections::HashSet::new();
-                                        let mut oldest_date = i64::MAX;
-
-                                        for id in &ids {
-                                            // Skip messages that were recently modified locally
-                                            // to avoid race conditions with archive/delete
-                                            let is_recently_modified = if let Ok(state) = sync_state_clone.lock() {
-                                                state.is_recently_modified(id)
-                                            } else {
-                                                false
-                                            };
-
-                                            if is_recently_modified {
-                                                sync_client.debug_log(&format!(
-                                                    "SYNC SKIP: {} was recently modified, skipping",
-                                                    id
-                                                ));
-                                                // Don't add to remote_ids so removal detection works
-                                                continue;
-                                            }
+            // Clean up expired entries from recently_modified
+            if let Ok(mut state) = sync_state.lock() {
+                state.cleanup_expired();
+            }
 
-                                            remote_ids.insert(id.clone());
-                                            if let Ok(exists) = sync_db.message_exists(id).await {
-                                                if !exists {
-                                                    if let Ok(msg) =
-                                                        sync_client.get_message(id).await
-                                                    {
-                                                        oldest_date =
-                                                            oldest_date.min(msg.internal_date);
-                                                        messages.push(msg);
-                                                    }
-                                                } else {
-                                                    if let Ok(Some(date)) =
-                                                        sync_db.get_message_date(id).await
-                                                    {
-                                                        oldest_date = oldest_date.min(date);
-                                                    }
-                                                }
-                                            }
-                                        }
+            if let Ok((ids, next_page_token)) = sync::list_label_ids(client, label_id, depth).await
+            {
+                // Scrolling past the cache continues from here
+                if let Ok(mut state) = sync_state.lock() {
+                    state
+                        .older_pages
+                        .entry(label_id.clone())
+                        .or_insert(match &next_page_token {
+                            Some(token) => sync::OlderPages::Next(token.clone()),
+                            None => sync::OlderPages::Done,
+                        });
+                }
 
-                                        // Only perform removal if we have the COMPLETE picture from Gmail
-                                        // (no next page token means we got all results) AND we actually got results.
-                                        // If there's a next_page_token, we only have a partial view
-                                        // and MUST NOT remove anything — doing so would incorrectly
-                                        // strip labels from messages outside the partial window.
-                                        let should_remove =
-                                            next_page_token.is_none() && !ids.is_empty();
-
-                                        sync_client.debug_log(&format!(
-                                        "SYNC {}: {} remote IDs, next_page={}, oldest_date={}, should_remove={}",
-                                        label_id, ids.len(), next_page_token.is_some(), oldest_date, should_remove
-                                    ));
-
-                                        let _ = sync_db.upsert_messages(&messages, label_id).await;
-                                        if !messages.is_empty() {
-                                            has_new_data = true;
-                                        }
+                let mut missing = Vec::new();
+                let mut remote_ids = std::collections::HashSet::new();
+                let mut oldest_date = i64::MAX;
+
+                for id in &ids {
+                    // Skip messages that were recently modified locally
+                    // to avoid race conditions with archive/delete
+                    let is_recently_modified = if let Ok(state) = sync_state.lock() {
+                        state.is_recently_modified(id)
+                    } else {
+                        false
+                    };
+
+                    if is_recently_modified {
+                        tracing::debug!("SYNC SKIP: {} was recently modified, skipping", id);
+                        // Don't add to remote_ids so removal detection works
+                        continue;
+                    }
 
-                                        // Detection of removals (archived/deleted from other clients)
-                                        // Only do this if we have the complete remote picture
-                                        if should_remove {
-                                            if let Ok(local_info) = sync_db
-                                                .get_messages_with_dates_by_label(label_id, 200)
-                                                .await
-                                            {
-                                                for (local_id, local_date) in local_info {
-                                                    // Skip messages that were recently modified locally
-                                                    let is_recently_modified = if let Ok(state) = sync_state_clone.lock() {
-                                                        state.is_recently_modified(&local_id)
-                                                    } else {
-                                                        false
-                                                    };
-                                                    
-                                                    if is_recently_modified {
-                                                        continue;
-                                                    }
-                                                    
-                                                    // Only remove if the message is within the date range
-                                                    // of what the remote returned (i.e. it SHOULD have been
-                                                    // in the remote set if it still had this label)
-                                                    if local_date >= oldest_date
-                                                        && !remote_ids.contains(&local_id)
-                                                    {
-                                                        if let Ok(_) = sync_db
-                                                            .remove_label_from_message(
-                                                                &local_id, label_id,
-                                                            )
-                                                            .await
-                                                        {
-                                                            has_new_data = true;
-                                                            sync_client.debug_log(&format!(
-                                                            "REMOVAL: Confirmed {} missing from {} (oldest_date: {})", 
-                                                            local_id, label_id, oldest_date
-                                                        ));
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
+                    remote_ids.insert(id.clone());
+                    if let Ok(exists) = db.message_exists(id).await {
+                        if !exists {
+                            missing.push(id.clone());
+                        } else if let Ok(Some(date)) = db.get_message_date(id).await {
+                            oldest_date = oldest_date.min(date);
+                        }
+                    }
+                }
 
-                                    // Mark this label as synced and send refresh
-                                    if let Ok(mut state) = sync_state_clone.lock() {
-                                        state.synced_labels.insert(label_id.clone());
-                                        state.currently_syncing = None;
-                                    }
-                                    if has_new_data {
-                                        let _ = sync_refresh_tx.send(()).await;
-                                        has_new_data = false;
-                                    }
-                                }
+                // Fetch what isn't cached a chunk at a time, so the
+                // list fills in while a large label syncs
+                for (done, chunk) in missing
+                    .chunks(sync::FETCH_CHUNK)
+                    .enumerate()
+                    .map(|(i, chunk)| (i * sync::FETCH_CHUNK, chunk))
+                {
+                    if let Ok(mut state) = sync_state.lock() {
+                        state.progress = Some(sync::SyncProgress {
+                            label: label.display_name.clone(),
+                            fetched: done,
+                            total: missing.len(),
+                        });
+                    }
+                    let results =
+                        sync::fetch_messages(client, chunk, policy.body_on_demand()).await;
+                    let mut messages = Vec::with_capacity(results.len());
+                    for (id, result) in chunk.iter().zip(results) {
+                        match result {
+                            Ok(msg) => messages.push(msg),
+                            Err(e) => {
+                                tracing::warn!("Fetching message {} failed: {:#}", id, e);
+                                failed += 1;
                             }
+                        }
+                    }
+                    fetched += messages.len();
+                    for msg in &messages {
+                        oldest_date = oldest_date.min(msg.internal_date);
+                    }
+                    if let Err(e) = db.upsert_messages(&messages, label_id).await {
+                        tracing::warn!("Saving messages failed: {:#}", e);
+                    }
+                    if let Err(e) = db.record_contacts(&messages).await {
+                        tracing::warn!("Recording contacts failed: {:#}", e);
+                    }
+                    if !messages.is_empty() {
+                        let _ = refresh_tx.send(()).await;
+                    }
+                }
+                if let Ok(mut state) = sync_state.lock() {
+                    state.progress = None;
+                }
 
-                            if has_new_data {
-                                let _ = sync_refresh_tx.send(()).await;
-                            }
+                // Only perform removal if we have the COMPLETE picture from Gmail
+                // (no next page token means we got all results) AND we actually got results.
+                // If there's a next_page_token, we only have a partial view
+                // and MUST NOT remove anything — doing so would incorrectly
+                // strip labels from messages outside the partial window.
+                let should_remove = next_page_token.is_none() && !ids.is_empty();
+
+                tracing::debug!(
+                    "SYNC {}: {} remote IDs, next_page={}, oldest_date={}, should_remove={}",
+                    label_id,
+                    ids.len(),
+                    next_page_token.is_some(),
+                    oldest_date,
+                    should_remove
+                );
+
+                // Detection of removals (archived/deleted from other clients)
+                // Only do this if we have the complete remote picture
+                if should_remove
+                    && let Ok(local_info) = db
+                        .get_messages_with_dates_by_label(label_id, depth as i64 * 2)
+                        .await
+                {
+                    for (local_id, local_date) in local_info {
+                        // Skip messages that were recently modified locally
+                        let is_recently_modified = if let Ok(state) = sync_state.lock() {
+                            state.is_recently_modified(&local_id)
+                        } else {
+                            false
+                        };
+
+                        if is_recently_modified {
+                            continue;
+                        }
 
-                            tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
+                        // Only remove if the message is within the date range
+                        // of what the remote returned (i.e. it SHOULD have been
+                        // in the remote set if it still had this label)
+                        if local_date >= oldest_date
+                            && !remote_ids.contains(&local_id)
+                            && let Ok(_) = db.remove_label_from_message(&local_id, label_id).await
+                        {
+                            has_new_data = true;
+                            tracing::debug!(
+                                "REMOVAL: Confirmed {} missing from {} (oldest_date: {})",
+                                local_id,
+                                label_id,
+                                oldest_date
+                            );
                         }
                     }
-                });
+                }
+            }
+
+            // Mark this label as synced and send refresh
+            if let Ok(mut state) = sync_state.lock() {
+                state.synced_labels.insert(label_id.clone());
+                state.currently_syncing = None;
+            }
+            if has_new_data {
+                let _ = refresh_tx.send(()).await;
+                has_new_data = false;
+            }
+        }
+    }
+
+    if failed > 0 {
+        tracing::warn!(fetched, failed, "Sync pass finished with failed fetches");
+    } else {
+        tracing::info!(fetched, "Sync pass finished");
+    }
+    if let Ok(mut state) = sync_state.lock() {
+        state.failed_fetches = failed;
+    }
+
+    if has_new_data {
+        let _ = refresh_tx.send(()).await;
+    }
+}
+
+/// Stop gtui with the terminal restored, as Ctrl-Z does in the shell, and
+/// pick up where it left off on `fg`
+fn suspend(
+    ui_state: &mut ui::UIState<'_>,
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    mouse_capture: bool,
+) {
+    if let Err(e) = editor::with_suspended_terminal(terminal, mouse_capture, terminal::stop_process)
+    {
+        ui_state.notify(Level::Error, format!("Suspend failed: {:#}", e));
+    }
+}
+
+/// What running a browsing action needs besides the UI state
+struct ActionContext<'a> {
+    terminal: &'a mut Terminal<CrosstermBackend<io::Stdout>>,
+    db: &'a db::Database,
+    gmail_client: &'a Option<GmailClient>,
+    config: &'a Config,
+    sync_state: &'a Arc<Mutex<sync::SyncState>>,
+    action_tx: &'a mpsc::UnboundedSender<ActionResult>,
+    refresh_tx: &'a mpsc::Sender<()>,
+    priority_tx: &'a mpsc::Sender<String>,
+    sync_now: &'a tokio::sync::Notify,
+    limit: i64,
+}
 
-                // Load initial data for UI
-                ui_state.labels = db.get_labels().await?;
-                if let Some(index) = ui_state.labels.iter().position(|l| l.id == "INBOX") {
-                    ui_state.selected_label_index = index;
+/// Run one browsing action, from a key or the command palette. Called once
+/// per repetition when the action takes a count.
+async fn run_action(
+    ui_state: &mut ui::UIState<'_>,
+    action: Action,
+    ctx: ActionContext<'_>,
+) -> anyhow::Result<()> {
+    let ActionContext {
+        terminal,
+        db,
+        gmail_client,
+        config,
+        sync_state,
+        action_tx,
+        refresh_tx,
+        priority_tx,
+        sync_now,
+        limit,
+    } = ctx;
+
+    // Panel switching
+    if action == Action::PrevPanel {
+        ui_state.focused_panel = match ui_state.focused_panel {
+            FocusedPanel::Details => FocusedPanel::Messages,
+            FocusedPanel::Messages if ui_state.layout.show_labels => FocusedPanel::Labels,
+            other => other,
+        };
+    } else if action == Action::NextPanel {
+        ui_state.focused_panel = match ui_state.focused_panel {
+            FocusedPanel::Labels => FocusedPanel::Messages,
+            FocusedPanel::Messages => FocusedPanel::Details,
+            FocusedPanel::Details => FocusedPanel::Details,
+        };
+    } else if action == Action::GrowPanel {
+        ui_state.layout.resize(ui_state.focused_panel, 1);
+    } else if action == Action::ShrinkPanel {
+        ui_state.layout.resize(ui_state.focused_panel, -1);
+    } else if action == Action::ToggleZoom {
+        ui_state.layout.zoomed = !ui_state.layout.zoomed;
+    } else if action == Action::ToggleLabels {
+        ui_state.layout.show_labels = !ui_state.layout.show_labels;
+        if !ui_state.layout.show_labels && ui_state.focused_panel == FocusedPanel::Labels {
+            ui_state.focused_panel = FocusedPanel::Messages;
+        }
+    }
+    // Navigation within panels
+    else if action == Action::MoveDown {
+        match ui_state.focused_panel {
+            FocusedPanel::Labels => {
+                if ui_state.selected_label_index < ui_state.labels.len().saturating_sub(1) {
+                    let index = ui_state.selected_label_index + 1;
+                    select_label(ui_state, db, index, limit, priority_tx).await?;
                 }
-                if let Some(label) = ui_state.labels.get(ui_state.selected_label_index) {
-                    ui_state.messages = db
-                        .get_messages_by_label(&label.id, limit, current_offset)
-                        .await?;
-                    if let Some(msg) = ui_state.messages.get(ui_state.selected_message_index) {
-                        ui_state.threaded_messages =
-                            db.get_messages_by_thread(&msg.thread_id).await?;
-                    }
+            }
+            FocusedPanel::Messages => {
+                if ui_state.selected_message_index < ui_state.messages.len().saturating_sub(1) {
+                    let index = ui_state.selected_message_index + 1;
+                    select_message(ui_state, db, index).await?;
+                    load_more_if_near_end(ui_state, db, limit).await?;
                 }
             }
+            FocusedPanel::Details => {
+                ui_state.detail_scroll = ui_state.detail_scroll.saturating_add(1);
+            }
+        }
+    } else if action == Action::MoveUp {
+        match ui_state.focused_panel {
+            FocusedPanel::Labels => {
+                if ui_state.selected_label_index > 0 {
+                    let index = ui_state.selected_label_index - 1;
+                    select_label(ui_state, db, index, limit, priority_tx).await?;
+                }
+            }
+            FocusedPanel::Messages => {
+                if ui_state.selected_message_index > 0 {
+                    let index = ui_state.selected_message_index - 1;
+                    select_message(ui_state, db, index).await?;
+                }
+            }
+            FocusedPanel::Details => {
+                ui_state.detail_scroll = ui_state.detail_scroll.saturating_sub(1);
+            }
+        }
+    }
+    // Email Actions
+    else if action == Action::MarkRead {
+        // Toggle Read/Unread
+        if let Some(m) = ui_state.messages.get_mut(ui_state.selected_message_index) {
+            let is_currently_read = m.is_read;
+            m.is_read = !is_currently_read;
+            let id = m.id.clone();
+            if let Some(gmail) = gmail_client {
+                let gmail = gmail.clone();
+                let db = db.clone();
+                let action_tx = action_tx.clone();
+                let new_status = !is_currently_read;
+                let pending = ui_state.pending.start();
+                tokio::spawn(async move {
+                    let _pending = pending;
+                    let result = if new_status {
+                        gmail.mark_as_read(&id).await
+                    } else {
+                        gmail.mark_as_unread(&id).await
+                    };
+                    if let Err(e) = result {
+                        let _ = action_tx.send(ActionResult::ReadStateFailed {
+                            id,
+                            is_read: is_currently_read,
+                            error: format!("{:#}", e),
+                        });
+                    } else if let Err(e) = db.mark_message_as_read(&id, new_status).await {
+                        let _ = action_tx.send(ActionResult::Failed(format!(
+                            "Marking {} failed: {:#}",
+                            if new_status { "read" } else { "unread" },
+                            e
+                        )));
+                    }
+                });
+            }
         }
+    } else if action == Action::Reply {
+        // Reply
+        if let Some(m) = ui_state
+            .messages
+            .get(ui_state.selected_message_index)
+            .cloned()
+        {
+            let subject = m.subject.as_deref().unwrap_or("");
+            let new_subject = if subject.to_lowercase().starts_with("re:") {
+                subject.to_string()
+            } else {
+                format!("Re: {}", subject)
+            };
+
+            let mut quoted_body = String::new();
+            let date = DateTime::from_timestamp_millis(m.internal_date)
+                .unwrap_or_default()
+                .with_timezone(&Local);
+
+            quoted_body.push_str(&format!(
+                "\nOn {}, {} wrote:\n",
+                date.format("%a, %b %d, %Y at %l:%M %p"),
+                m.from_address.as_deref().unwrap_or("Unknown")
+            ));
+
+            // List rows synced with headers only have no body; the snippet
+            // is only a last resort
+            let body_to_quote = sync::load_body(gmail_client.as_ref(), db, &m)
+                .await
+                .or_else(|| m.snippet.clone());
+            if let Some(body) = body_to_quote {
+                quoted_body.push_str(&outgoing::quote_body(&body));
+            }
+
+            // Reply from the alias the original was addressed to
+            let from_index = outgoing::pick_send_as(
+                &ui_state.send_as,
+                &[
+                    m.to_address.as_deref().unwrap_or(""),
+                    m.cc_address.as_deref().unwrap_or(""),
+                ],
+            );
+            let mut signature_part = String::new();
+            let sig_to_use = ui_state
+                .send_as
+                .get(from_index)
+                .and_then(|a| a.signature.as_ref())
+                .or(config.signatures.reply.as_ref());
+            if let Some(sig) = sig_to_use {
+                signature_part.push_str("--\n");
+                signature_part.push_str(sig);
+                signature_part.push_str("\n\n");
+            }
 
-        // Check for sync refresh — drain all pending signals, then reload once
-        let mut needs_refresh = false;
-        while let Ok(()) = refresh_rx.try_recv() {
-            needs_refresh = true;
+            let final_body = format!("\n\n{}{}", signature_part, quoted_body);
+
+            ui_state.mode = ui::UIMode::Composing;
+            let _ = execute!(io::stdout(), crossterm::cursor::Show);
+            let mut compose = ui::ComposeState::new(
+                &m.from_address.clone().unwrap_or_default(),
+                "",
+                "",
+                &new_subject,
+                &final_body,
+            )
+            .with_send_as(
+                ui_state.send_as.clone(),
+                from_index,
+                config.signatures.reply.clone(),
+            );
+            compose.focused_field = ui::ComposeField::Body;
+            ui_state.compose_state = Some(compose);
         }
-        if needs_refresh {
-            // Re-load labels
-            ui_state.labels = db.get_labels().await?;
-            if let Some(label) = ui_state.labels.get(ui_state.selected_label_index) {
-                // Re-load messages for current label
-                let mut new_messages = db
-                    .get_messages_by_label(&label.id, limit, current_offset)
-                    .await?;
+    } else if action == Action::Forward {
+        // Forward
+        if let Some(m) = ui_state
+            .messages
+            .get(ui_state.selected_message_index)
+            .cloned()
+        {
+            let subject = m.subject.as_deref().unwrap_or("");
+            let new_subject = if subject.to_lowercase().starts_with("fwd:")
+                || subject.to_lowercase().starts_with("fw:")
+            {
+                subject.to_string()
+            } else {
+                format!("Fwd: {}", subject)
+            };
+
+            // Build forwarded body
+            let mut forward_body = String::new();
+
+            // Two blank lines at top for user's context
+            forward_body.push_str("\n\n");
+
+            // Add signature (new_message signature for forwards)
+            let from_index = outgoing::pick_send_as(&ui_state.send_as, &[]);
+            let sig_to_use = ui_state
+                .send_as
+                .get(from_index)
+                .and_then(|a| a.signature.as_ref())
+                .or(config.signatures.new_message.as_ref());
+            if let Some(sig) = sig_to_use {
+                forward_body.push_str("--\n");
+                forward_body.push_str(sig);
+                forward_body.push('\n');
+            }
 
-                // If we got no messages but have an offset, we might be scrolled past the end.
-                // Reset to 0 and try again.
-                if new_messages.is_empty() && current_offset > 0 {
-                    current_offset = 0;
-                    new_messages = db.get_messages_by_label(&label.id, limit, 0).await?;
-                }
+            // Forwarding header block
+            forward_body.push_str("\n---------- Forwarded message ----------\n");
+            forward_body.push_str(&format!(
+                "From: {}\n",
+                m.from_address.as_deref().unwrap_or("Unknown")
+            ));
+
+            let date = DateTime::from_timestamp_millis(m.internal_date)
+                .unwrap_or_default()
+                .with_timezone(&Local);
+            forward_body.push_str(&format!(
+                "Date: {}\n",
+                date.format("%a, %b %d, %Y at %l:%M %p")
+            ));
+
+            forward_body.push_str(&format!("Subject: {}\n", subject));
+            forward_body.push_str(&format!(
+                "To: {}\n",
+                m.to_address.as_deref().unwrap_or("Unknown")
+            ));
+
+            // Original message body
+            let body_to_forward = sync::load_body(gmail_client.as_ref(), db, &m)
+                .await
+                .or_else(|| m.snippet.clone());
+            if let Some(body) = body_to_forward {
+                forward_body.push_str(&format!("\n{}", body));
+            }
+
+            ui_state.mode = ui::UIMode::Composing;
+            let _ = execute!(io::stdout(), crossterm::cursor::Show);
+            let compose = ui::ComposeState::new(
+                "", // Empty To field
+                "",
+                "",
+                &new_subject,
+                &forward_body,
+            )
+            .with_send_as(
+                ui_state.send_as.clone(),
+                from_index,
+                config.signatures.new_message.clone(),
+            );
+            // Cursor starts in To field (default)
+            ui_state.compose_state = Some(compose);
+        }
+    } else if action == Action::NewMessage {
+        // New message
+        ui_state.mode = ui::UIMode::Composing;
+        let _ = execute!(io::stdout(), crossterm::cursor::Show);
+
+        let mut body = String::new();
+        let from_index = outgoing::pick_send_as(&ui_state.send_as, &[]);
+        let sig_to_use = ui_state
+            .send_as
+            .get(from_index)
+            .and_then(|a| a.signature.as_ref())
+            .or(config.signatures.new_message.as_ref());
+        if let Some(sig) = sig_to_use {
+            body.push_str("\n\n--\n");
+            body.push_str(sig);
+        }
+
+        ui_state.compose_state = Some(ui::ComposeState::new("", "", "", "", &body).with_send_as(
+            ui_state.send_as.clone(),
+            from_index,
+            config.signatures.new_message.clone(),
+        ));
+    } else if action == Action::Delete {
+        // Do nothing if labels panel is active
+        if ui_state.focused_panel == FocusedPanel::Labels {
+            return Ok(());
+        }
+        // Ensure conversations list is the active panel
+        ui_state.focused_panel = FocusedPanel::Messages;
+        // Delete all messages in the thread
+        if let Some(m) = ui_state.messages.get(ui_state.selected_message_index) {
+            let thread_id = m.thread_id.clone();
+
+            // Get all messages in the thread from the database
+            let thread_messages = db.get_messages_by_thread(&thread_id).await?;
+            let message_ids: Vec<String> = thread_messages.iter().map(|m| m.id.clone()).collect();
+
+            // Mark messages as recently modified to prevent sync from re-adding them
+            if let Ok(mut state) = sync_state.lock() {
+                state.mark_modified_many(message_ids.clone());
+            }
+
+            // Capture for undo BEFORE removing
+            let current_label_id = ui_state
+                .labels
+                .get(ui_state.selected_label_index)
+                .map(|l| l.id.clone())
+                .unwrap_or_else(|| "INBOX".to_string());
+            let original_index = ui_state.selected_message_index;
+
+            // Delete locally right away; Gmail catches up in the
+            // background and a failure puts the thread back
+            if let Err(e) = db.delete_messages(&message_ids).await {
+                tracing::error!("Error deleting message from DB: {:#}", e);
+            }
+            let action = UndoableAction::Delete {
+                messages: thread_messages,
+                label_id: current_label_id,
+                original_index,
+            };
+            ui_state.undo_stack.push(action.clone());
+
+            if let Some(gmail) = gmail_client {
+                let gmail = gmail.clone();
+                let action_tx = action_tx.clone();
+                let pending = ui_state.pending.start();
+                tokio::spawn(async move {
+                    let _pending = pending;
+                    let result = gmail
+                        .trash_messages(&message_ids)
+                        .await
+                        .map_err(|e| format!("{:#}", e));
+                    let _ = action_tx.send(ActionResult::Applied { action, result });
+                });
+            }
 
-                // If the message list changed, we need to be careful with the selection index
-                ui_state.messages = new_messages;
+            remove_selected_thread(ui_state, db).await?;
+        }
+    } else if action == Action::Archive {
+        // Do nothing if labels panel is active
+        if ui_state.focused_panel == FocusedPanel::Labels {
+            return Ok(());
+        }
+        // Ensure conversations list is the active panel
+        ui_state.focused_panel = FocusedPanel::Messages;
+        // Archive all messages in the thread
+        if let Some(m) = ui_state.messages.get(ui_state.selected_message_index) {
+            let thread_id = m.thread_id.clone();
+
+            // Get all messages in the thread from the database
+            let thread_messages = db.get_messages_by_thread(&thread_id).await?;
+            let message_ids: Vec<String> = thread_messages.iter().map(|m| m.id.clone()).collect();
+
+            // Mark messages as recently modified to prevent sync from re-adding them
+            if let Ok(mut state) = sync_state.lock() {
+                state.mark_modified_many(message_ids.clone());
+            }
 
-                // Clamp selection index
-                if !ui_state.messages.is_empty() {
-                    if ui_state.selected_message_index >= ui_state.messages.len() {
-                        ui_state.selected_message_index = ui_state.messages.len().saturating_sub(1);
+            // Determine which label to remove: INBOX normally, or Category label if viewing a Category
+            let current_label_id = ui_state
+                .labels
+                .get(ui_state.selected_label_index)
+                .map(|l| l.id.clone())
+                .unwrap_or_else(|| "INBOX".to_string());
+
+            // If viewing a Category label (CATEGORY_*), remove that label instead of INBOX
+            // Otherwise, remove INBOX (standard archive behavior)
+            let label_to_remove = if current_label_id.starts_with("CATEGORY_") {
+                current_label_id.clone()
+            } else {
+                "INBOX".to_string()
+            };
+
+            // Remove the label locally right away; Gmail catches up
+            // in the background and a failure puts the thread back
+            if let Err(e) = db
+                .remove_label_from_messages(&message_ids, &label_to_remove)
+                .await
+            {
+                tracing::error!("Error removing {} label from DB: {:#}", label_to_remove, e);
+            }
+            let action = UndoableAction::Archive {
+                messages: thread_messages,
+                label_id: label_to_remove.clone(),
+                original_index: ui_state.selected_message_index,
+            };
+            ui_state.undo_stack.push(action.clone());
+
+            if let Some(gmail) = gmail_client {
+                let gmail = gmail.clone();
+                let action_tx = action_tx.clone();
+                let pending = ui_state.pending.start();
+                tokio::spawn(async move {
+                    let _pending = pending;
+                    // If archiving from a Category, remove the category label from Gmail
+                    // Otherwise use standard archive (remove INBOX)
+                    let result = if label_to_remove == "INBOX" {
+                        gmail.archive_messages(&message_ids).await
+                    } else {
+                        gmail
+                            .remove_label_from_messages(&message_ids, &label_to_remove)
+                            .await
                     }
+                    .map_err(|e| format!("{:#}", e));
+                    let _ = action_tx.send(ActionResult::Applied { action, result });
+                });
+            }
 
-                    // Re-load threaded messages for selected message
-                    if let Some(msg) = ui_state.messages.get(ui_state.selected_message_index) {
-                        debug_log(debug_logging, &format!("[Main] Sync refresh loading thread_id: {:?}", msg.thread_id));
-                        ui_state.threaded_messages =
-                            db.get_messages_by_thread(&msg.thread_id).await?;
-                        debug_log(debug_logging, &format!("[Main] Sync refresh loaded {} messages", ui_state.threaded_messages.len()));
+            remove_selected_thread(ui_state, db).await?;
+        }
+    } else if action == Action::Undo {
+        // Undo - only in Messages or Details panel
+        if matches!(
+            ui_state.focused_panel,
+            FocusedPanel::Messages | FocusedPanel::Details
+        ) && let Some(action) = ui_state.undo_stack.pop()
+        {
+            let description = action.description();
+            restore_locally(ui_state, db, &action).await?;
+
+            // Revert the change in Gmail
+            if let Some(gmail) = gmail_client {
+                let gmail = gmail.clone();
+                let action_tx = action_tx.clone();
+                let pending = ui_state.pending.start();
+                tokio::spawn(async move {
+                    let _pending = pending;
+                    let ids = action.messages().iter().map(|m| m.id.clone());
+                    let mut result = Ok(());
+                    for id in ids {
+                        result = match &action {
+                            UndoableAction::Delete { .. } => gmail.untrash_message(&id).await,
+                            // Use unarchive for INBOX
+                            UndoableAction::Archive { label_id, .. } if label_id == "INBOX" => {
+                                gmail.unarchive_message(&id).await
+                            }
+                            // Use add_label_to_message for other labels (like categories)
+                            UndoableAction::Archive { label_id, .. } => {
+                                gmail.add_label_to_message(&id, label_id).await
+                            }
+                        };
+                        if result.is_err() {
+                            break;
+                        }
+                    }
+                    if let Err(e) = result {
+                        let _ =
+                            action_tx.send(ActionResult::Failed(format!("Undo failed: {:#}", e)));
                     }
+                });
+            }
+            ui_state.notify(Level::Info, format!("Undone: {}", description));
+        }
+    } else if action == Action::ViewInPager {
+        // View the selected thread in $PAGER
+        if !ui_state.threaded_messages.is_empty() {
+            let text = ui::format_thread(&ui_state.threaded_messages);
+            if let Err(e) = editor::with_suspended_terminal(terminal, config.mouse.enabled, || {
+                editor::view_in_pager(&text)
+            }) {
+                ui_state.notify(Level::Error, format!("Pager failed: {:#}", e));
+            }
+        }
+    } else if action == Action::GoTop || action == Action::GoBottom {
+        let top = action == Action::GoTop;
+        match ui_state.focused_panel {
+            FocusedPanel::Labels => {
+                let index = if top {
+                    0
+                } else {
+                    ui_state.labels.len().saturating_sub(1)
+                };
+                select_label(ui_state, db, index, limit, priority_tx).await?;
+            }
+            FocusedPanel::Messages => {
+                let index = if top {
+                    0
+                } else {
+                    ui_state.messages.len().saturating_sub(1)
+                };
+                select_message(ui_state, db, index).await?;
+                if !top {
+                    load_more_if_near_end(ui_state, db, limit).await?;
+                }
+            }
+            FocusedPanel::Details => {
+                ui_state.detail_scroll = if top {
+                    0
+                } else {
+                    // Approximate: wrapping can add lines the
+                    // plain text doesn't have
+                    let lines = ui::format_thread(&ui_state.threaded_messages)
+                        .lines()
+                        .count();
+                    let visible = ui_state.panel_areas.details.height.saturating_sub(2);
+                    (lines as u16).saturating_sub(visible)
+                };
+            }
+        }
+    } else if action == Action::PageDown || action == Action::PageUp {
+        let page = ui_state.page_size(ui_state.focused_panel);
+        let down = action == Action::PageDown;
+        let step = |index: usize, len: usize| {
+            if down {
+                (index + page).min(len.saturating_sub(1))
+            } else {
+                index.saturating_sub(page)
+            }
+        };
+        match ui_state.focused_panel {
+            FocusedPanel::Labels => {
+                let index = step(ui_state.selected_label_index, ui_state.labels.len());
+                select_label(ui_state, db, index, limit, priority_tx).await?;
+            }
+            FocusedPanel::Messages => {
+                let index = step(ui_state.selected_message_index, ui_state.messages.len());
+                select_message(ui_state, db, index).await?;
+                if down {
+                    load_more_if_near_end(ui_state, db, limit).await?;
+                }
+            }
+            FocusedPanel::Details => {
+                let page = page as u16;
+                ui_state.detail_scroll = if down {
+                    ui_state.detail_scroll.saturating_add(page)
                 } else {
-                    ui_state.selected_message_index = 0;
-                    debug_log(debug_logging, "[Main] Clearing threaded_messages (no messages in label)");
+                    ui_state.detail_scroll.saturating_sub(page)
+                };
+            }
+        }
+    } else if let Action::JumpToDate(date) = &action {
+        // The newest conversation from the end of that day or earlier
+        let target = date
+            .and_hms_opt(23, 59, 59)
+            .and_then(|t| t.and_local_timezone(Local).earliest())
+            .map_or(i64::MAX, |t| t.timestamp_millis());
+        ui_state.focused_panel = FocusedPanel::Messages;
+        ui_state.jump_target = None;
+        let found = loop {
+            if let Some(index) = ui_state
+                .messages
+                .iter()
+                .position(|m| m.internal_date <= target)
+            {
+                break Some(index);
+            }
+            let offset = ui_state.messages.len() as i64;
+            let mut additional = load_conversations(ui_state, db, limit, offset).await?;
+            if additional.is_empty() {
+                ui_state.end_of_cache = true;
+                break None;
+            }
+            ui_state.messages.append(&mut additional);
+        };
+        match found {
+            Some(index) => select_message(ui_state, db, index).await?,
+            None if ui_state.search_query.is_none() => {
+                // Page back through Gmail until the date turns up
+                let last = ui_state.messages.len().saturating_sub(1);
+                select_message(ui_state, db, last).await?;
+                ui_state.jump_target = Some(target);
+                ui_state.want_older_page = true;
+                ui_state.notify(Level::Info, format!("Looking for mail from {}...", date));
+            }
+            None => {
+                ui_state.notify(Level::Warn, "No mail that old");
+            }
+        }
+    } else if action == Action::GoToInbox {
+        if let Some(index) = ui_state.labels.iter().position(|l| l.id == "INBOX") {
+            ui_state.focused_panel = FocusedPanel::Messages;
+            select_label(ui_state, db, index, limit, priority_tx).await?;
+        }
+    } else if action == Action::Help {
+        ui_state.show_help = true;
+        ui_state.help_scroll = 0;
+    } else if action == Action::RetryUnsent {
+        ui_state.outbox = db.get_outbox().await?;
+        let Some(gmail) = gmail_client else {
+            ui_state.notify(Level::Warn, "Not signed in yet");
+            return Ok(());
+        };
+        let unsent: Vec<_> = ui_state
+            .outbox
+            .iter()
+            .filter(|m| !m.draft && !ui_state.sending.contains(&m.id))
+            .cloned()
+            .collect();
+        if unsent.is_empty() {
+            ui_state.notify(Level::Info, "Nothing to retry");
+        }
+        for msg in unsent {
+            match outgoing::OutgoingMessage::from_outbox(&msg) {
+                Ok(message) => {
+                    send_from_outbox(ui_state, gmail, db, msg.id, message, action_tx, refresh_tx)
+                }
+                Err(e) => ui_state.notify(
+                    Level::Warn,
+                    format!("\"{}\" can't be sent: {}; use :outbox edit", msg.subject, e),
+                ),
+            }
+        }
+    } else if action == Action::EditUnsent {
+        ui_state.outbox = db.get_outbox().await?;
+        let newest = ui_state
+            .outbox
+            .iter()
+            .rev()
+            .find(|m| !ui_state.sending.contains(&m.id))
+            .cloned();
+        let Some(msg) = newest else {
+            ui_state.notify(Level::Info, "The outbox is empty");
+            return Ok(());
+        };
+        ui_state.mode = ui::UIMode::Composing;
+        let _ = execute!(io::stdout(), crossterm::cursor::Show);
+        ui_state.compose_state = Some(ui::ComposeState::from_outbox(
+            &msg,
+            ui_state.send_as.clone(),
+            config.signatures.new_message.clone(),
+        ));
+    } else if action == Action::Suspend {
+        suspend(ui_state, terminal, config.mouse.enabled);
+    } else if action == Action::ToggleConsole {
+        ui_state.show_console = !ui_state.show_console;
+    } else if action == Action::CommandPalette {
+        ui_state.palette = Some(ui::PaletteState::default());
+    } else if action == Action::OpenInBrowser {
+        if let Some(m) = ui_state.messages.get(ui_state.selected_message_index) {
+            let url = format!("https://mail.google.com/mail/u/0/#all/{}", m.thread_id);
+            if let Err(e) = open::that(&url) {
+                ui_state.notify(Level::Error, format!("Could not open browser: {}", e));
+            }
+        }
+    } else if action == Action::SyncNow {
+        sync_now.notify_one();
+        ui_state.notify(Level::Info, "Syncing...");
+    } else if let Action::Search(query) = &action {
+        let query = query.trim();
+        if query.is_empty() {
+            let index = ui_state.selected_label_index;
+            select_label(ui_state, db, index, limit, priority_tx).await?;
+        } else {
+            match db.search_messages(query, limit, 0).await {
+                Ok(messages) => {
+                    ui_state.search_query = Some(query.to_string());
+                    ui_state.messages = messages;
+                    ui_state.end_of_cache = (ui_state.messages.len() as i64) < limit;
+                    ui_state.jump_target = None;
                     ui_state.threaded_messages.clear();
+                    ui_state.focused_panel = FocusedPanel::Messages;
+                    select_message(ui_state, db, 0).await?;
+                    if ui_state.messages.is_empty() {
+                        ui_state.notify(Level::Info, "No matches");
+                    }
+                }
+                Err(e) => {
+                    ui_state.notify(Level::Error, format!("Search failed: {:#}", e));
                 }
             }
         }
-
-        terminal.draw(|f| ui::render(f, &mut ui_state))?;
-
-        if !event::poll(std::time::Duration::from_millis(100))? {
-            continue;
+    } else if let Action::Label { add, name } = &action {
+        let label = ui_state
+            .labels
+            .iter()
+            .find(|l| {
+                l.name.eq_ignore_ascii_case(name) || l.display_name.eq_ignore_ascii_case(name)
+            })
+            .map(|l| l.id.clone());
+        let Some(label_id) = label else {
+            ui_state.notify(Level::Warn, format!("No label named {}", name));
+            return Ok(());
+        };
+        let index = ui_state.selected_message_index;
+        let Some(m) = ui_state.messages.get_mut(index) else {
+            return Ok(());
+        };
+        let row = m.clone();
+        let thread_messages = db.get_messages_by_thread(&m.thread_id).await?;
+        let message_ids: Vec<String> = thread_messages.iter().map(|m| m.id.clone()).collect();
+        let saved = if *add {
+            db.add_label_to_messages(&message_ids, &label_id).await
+        } else {
+            db.remove_label_from_messages(&message_ids, &label_id).await
+        };
+        if let Err(e) = saved {
+            ui_state.notify(Level::Error, format!("Label failed: {:#}", e));
+            return Ok(());
+        }
+        m.thread_label_ids.retain(|l| *l != label_id);
+        if *add {
+            m.thread_label_ids.push(label_id.clone());
+        }
+        ui_state.notify(
+            Level::Info,
+            format!("{} label {}", if *add { "Added" } else { "Removed" }, name),
+        );
+
+        if let Some(gmail) = gmail_client {
+            let gmail = gmail.clone();
+            let action_tx = action_tx.clone();
+            let add = *add;
+            let label_id = label_id.clone();
+            let pending = ui_state.pending.start();
+            tokio::spawn(async move {
+                let _pending = pending;
+                let result = if add {
+                    let mut result = Ok(());
+                    for id in &message_ids {
+                        result = gmail.add_label_to_message(id, &label_id).await;
+                        if result.is_err() {
+                            break;
+                        }
+                    }
+                    result
+                } else {
+                    gmail
+                        .remove_label_from_messages(&message_ids, &label_id)
+                        .await
+                };
+                if let Err(e) = result {
+                    let _ = action_tx.send(ActionResult::LabelFailed {
+                        add,
+                        label_id,
+                        message_ids,
+                        row: Box::new(row),
+                        index,
+                        error: format!("{:#}", e),
+                    });
+                }
+            });
         }
 
-        if let Event::Key(key) = event::read()? {
-            // Only handle keys if authenticated or to quit
-            if !authenticated && key.code != KeyCode::Char('q') {
-                continue;
+        // The thread no longer belongs in the label being viewed
+        let viewing = ui_state
+            .labels
+            .get(ui_state.selected_label_index)
+            .is_some_and(|l| l.id == label_id);
+        if !*add && viewing && ui_state.search_query.is_none() {
+            if let Ok(mut state) = sync_state.lock() {
+                state.mark_modified_many(thread_messages.iter().map(|m| m.id.clone()).collect());
             }
+            remove_selected_thread(ui_state, db).await?;
+        }
+    }
+    Ok(())
+}
 
-            match ui_state.mode {
-                ui::UIMode::Authentication => {
-                    if key.code == KeyCode::Char('q') {
-                        break;
+/// Clicks focus a panel and select the label or conversation under the
+/// pointer; double-clicking a conversation opens it in Details. The wheel
+/// moves the selection in lists and scrolls Details.
+async fn handle_mouse(
+    ui_state: &mut ui::UIState<'_>,
+    db: &db::Database,
+    mouse: MouseEvent,
+    last_click: &mut Option<(Instant, usize)>,
+    limit: i64,
+    priority_tx: &mpsc::Sender<String>,
+) -> anyhow::Result<()> {
+    let Some(panel) = ui_state.panel_at(mouse.column, mouse.row) else {
+        return Ok(());
+    };
+
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) => {
+            ui_state.focused_panel = panel;
+            match panel {
+                FocusedPanel::Labels => {
+                    if let Some(index) = ui_state.label_index_at(mouse.row)
+                        && index != ui_state.selected_label_index
+                    {
+                        select_label(ui_state, db, index, limit, priority_tx).await?;
                     }
                 }
-                ui::UIMode::Browsing => {
-                    // Clear status message on any keypress (will be set again if undo is pressed)
-                    if !matches_key(key, &config.keybindings.undo) {
-                        ui_state.status_message = None;
+                FocusedPanel::Messages => {
+                    if let Some(index) = ui_state.message_index_at(mouse.row) {
+                        let now = Instant::now();
+                        let double = last_click.is_some_and(|(at, last)| {
+                            last == index && now.duration_since(at) < DOUBLE_CLICK
+                        });
+                        if index != ui_state.selected_message_index {
+                            select_message(ui_state, db, index).await?;
+                        }
+                        if double {
+                            ui_state.focused_panel = FocusedPanel::Details;
+                            *last_click = None;
+                        } else {
+                            *last_click = Some((now, index));
+                        }
                     }
-                    if matches_key(key, &config.keybindings.quit) {
-                        break;
+                }
+                FocusedPanel::Details => {}
+            }
+        }
+        MouseEventKind::ScrollDown | MouseEventKind::ScrollUp => {
+            let down = mouse.kind == MouseEventKind::ScrollDown;
+            match panel {
+                FocusedPanel::Labels => {
+                    let index = if down {
+                        ui_state.selected_label_index + 1
+                    } else {
+                        ui_state.selected_label_index.saturating_sub(1)
+                    };
+                    if index != ui_state.selected_label_index && index < ui_state.labels.len() {
+                        select_label(ui_state, db, index, limit, priority_tx).await?;
                     }
-
-                    // Panel switching
-                    if matches_key(key, &config.keybindings.prev_panel) {
-                        ui_state.focused_panel = match ui_state.focused_panel {
-                            FocusedPanel::Details => FocusedPanel::Messages,
-                            FocusedPanel::Messages => FocusedPanel::Labels,
-                            FocusedPanel::Labels => FocusedPanel::Labels,
-                        };
-                    } else if matches_key(key, &config.keybindings.next_panel) {
-                        ui_state.focused_panel = match ui_state.focused_panel {
-                            FocusedPanel::Labels => FocusedPanel::Messages,
-                            FocusedPanel::Messages => FocusedPanel::Details,
-                            FocusedPanel::Details => FocusedPanel::Details,
-                        };
+                }
+                FocusedPanel::Messages => {
+                    let index = if down {
+                        ui_state.selected_message_index + 1
+                    } else {
+                        ui_state.selected_message_index.saturating_sub(1)
+                    };
+                    if index != ui_state.selected_message_index && index < ui_state.messages.len() {
+                        select_message(ui_state, db, index).await?;
+                        if down {
+                            load_more_if_near_end(ui_state, db, limit).await?;
+                        }
                     }
-                    // Navigation within panels
-                    else if matches_key(key, &config.keybindings.move_down) {
-                        match ui_state.focused_panel {
-                            FocusedPanel::Labels => {
-                                if ui_state.selected_label_index
-                                    < ui_state.labels.len().saturating_sub(1)
-                                {
-                                    ui_state.selected_label_index += 1;
-                                    let label = &ui_state.labels[ui_state.selected_label_index];
-                                    current_offset = 0;
-                                    ui_state.messages = db
-                                        .get_messages_by_label(&label.id, limit, current_offset)
-                                        .await?;
-                                    ui_state.selected_message_index = 0;
-                                    ui_state.detail_scroll = 0;
-                                    if let Some(msg) = ui_state.messages.get(0) {
-                                        ui_state.threaded_messages =
-                                            db.get_messages_by_thread(&msg.thread_id).await?;
-                                    } else {
-                                        ui_state.threaded_messages.clear();
-                                    }
-                                    let _ = priority_tx.try_send(label.id.clone());
-                                }
-                            }
-                            FocusedPanel::Messages => {
-                                if ui_state.selected_message_index
-                                    < ui_state.messages.len().saturating_sub(1)
-                                {
-                                    let old_idx = ui_state.selected_message_index;
-                                    ui_state.selected_message_index += 1;
-                                    ui_state.detail_scroll = 0;
-                                    if let Some(msg) =
-                                        ui_state.messages.get(ui_state.selected_message_index)
-                                    {
-                                        debug_log(debug_logging, &format!("[Main] Navigating: idx {} -> {}, thread_id: {:?}",
-                                            old_idx, ui_state.selected_message_index, msg.thread_id));
-                                        ui_state.threaded_messages =
-                                            db.get_messages_by_thread(&msg.thread_id).await?;
-                                        debug_log(debug_logging, &format!("[Main] Loaded {} messages for thread",
-                                            ui_state.threaded_messages.len()));
-                                    }
+                }
+                FocusedPanel::Details => {
+                    ui_state.detail_scroll = if down {
+                        ui_state.detail_scroll.saturating_add(WHEEL_SCROLL_LINES)
+                    } else {
+                        ui_state.detail_scroll.saturating_sub(WHEEL_SCROLL_LINES)
+                    };
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
 
-                                    if ui_state.selected_message_index
-                                        >= ui_state.messages.len().saturating_sub(5)
-                                    {
-                                        current_offset += limit;
-                                        if let Some(label) =
-                                            ui_state.labels.get(ui_state.selected_label_index)
-                                        {
-                                            let mut additional = db
-                                                .get_messages_by_label(
-                                                    &label.id,
-                                                    limit,
-                                                    current_offset,
-                                                )
-                                                .await?;
-                                            ui_state.messages.append(&mut additional);
-                                        }
-                                    }
-                                }
-                            }
-                            FocusedPanel::Details => {
-                                ui_state.detail_scroll = ui_state.detail_scroll.saturating_add(1);
-                            }
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let paths = paths::Paths::from_env(&args)?;
+    paths.create()?;
+    // Older versions kept everything in the working directory
+    let adopted = match std::env::current_dir() {
+        Ok(cwd) if paths.account.is_none() => paths.adopt_files_from(&cwd)?,
+        _ => Vec::new(),
+    };
+
+    let (config, config_error) = match Config::load(&paths.settings()) {
+        Ok(config) => (config, None),
+        Err(e) => (Config::default(), Some(e)),
+    };
+    let debug_logging = args.iter().any(|arg| arg == "--debug");
+    let log = logging::LogBuffer::default();
+    let log_guard = logging::init(&paths.data_dir, &config.log, debug_logging, log.clone())?;
+    let db = db::Database::new(&format!("sqlite:{}", paths.database().display())).await?;
+    db.run_migrations().await?;
+
+    // Handle token reset
+    if args.iter().any(|arg| arg == "--reset-token") {
+        auth::RingStorage::new(paths.account.as_deref())
+            .clear_token()
+            .await?;
+        println!("Token cleared. Please restart without --reset-token to re-authenticate.");
+        return Ok(());
+    }
+
+    // Setup terminal early; the guard and the panic hook put it back however
+    // we exit
+    terminal::install_panic_hook();
+    let terminal_guard = terminal::TerminalGuard::enter(config.mouse.enabled)?;
+    let backend = CrosstermBackend::new(io::stdout());
+    let mut terminal = Terminal::new(backend)?;
+    let mut exit_signals = terminal::ExitSignals::new()?;
+
+    // Shared sync state for UI awareness
+    let sync_state = Arc::new(Mutex::new(sync::SyncState::default()));
+
+    let (theme, invalid_colors) = theme::Theme::from_config(&config.theme);
+    let mut ui_state = ui::UIState {
+        sync_state: sync_state.clone(),
+        theme,
+        layout: layout::LayoutState::from_config(&config.layout),
+        keybindings: config.keybindings.clone(),
+        log,
+        ..Default::default()
+    };
+    if let Some(e) = config_error {
+        ui_state.notify(Level::Error, format!("{:#}; using the default settings", e));
+    }
+    let keymaps = keymap::Keymaps::new(&config.keybindings);
+    let mut key_state = keymap::KeyState::default();
+    if !keymaps.problems.is_empty() {
+        ui_state.notify(
+            Level::Warn,
+            format!("Keybinding problems: {}", keymaps.problems.join("; ")),
+        );
+    }
+    if !invalid_colors.is_empty() {
+        ui_state.notify(
+            Level::Warn,
+            format!("Invalid theme colors: {}", invalid_colors.join("; ")),
+        );
+    }
+    match row_template::RowTemplate::from_config(&config.list) {
+        Ok(template) => ui_state.row_template = template,
+        Err(e) => ui_state.notify(Level::Warn, format!("Invalid list template: {:#}", e)),
+    }
+
+    ui_state.outbox = db.get_outbox().await?;
+    if !ui_state.outbox.is_empty() {
+        ui_state.notify(
+            Level::Warn,
+            format!(
+                "{} unsent message(s) in the outbox; use :outbox retry or :outbox edit",
+                ui_state.outbox.len()
+            ),
+        );
+    }
+
+    if !adopted.is_empty() {
+        ui_state.notify(
+            Level::Warn,
+            format!(
+                "Copied {} from the current directory to {} and {}; the originals are no longer used",
+                adopted.join(", "),
+                paths.config_dir.display(),
+                paths.data_dir.display()
+            ),
+        );
+    }
+
+    // Initial Auth setup
+    let secret = auth::Authenticator::load_secret(paths.credentials()).await?;
+
+    let (tx, mut rx) = mpsc::channel::<String>(1);
+    let (done_tx, mut done_rx) = mpsc::channel::<bool>(1);
+    let (refresh_tx, mut refresh_rx) = mpsc::channel::<()>(1);
+    let (priority_tx, priority_rx) = mpsc::channel::<String>(16);
+    let (action_tx, mut action_rx) = mpsc::unbounded_channel::<ActionResult>();
+    let mut priority_rx = Some(priority_rx);
+
+    let auth_builder = auth::Authenticator::authenticate(
+        secret,
+        auth::RingStorage::new(paths.account.as_deref()),
+        auth::TuiDelegate { tx },
+    )
+    .await?;
+
+    let mut scopes = auth::SCOPES.to_vec();
+    if config.contacts.people_api {
+        scopes.extend_from_slice(auth::PEOPLE_SCOPES);
+    }
+
+    let auth_clone = auth_builder.clone();
+    tokio::spawn(async move {
+        if auth_clone.token(&scopes).await.is_ok() {
+            let _ = done_tx.send(true).await;
+        }
+    });
+
+    let mut authenticated = false;
+    let limit = 50;
+    let mut last_click: Option<(Instant, usize)> = None;
+
+    let mut events = EventStream::new();
+    let mut tick = tokio::time::interval(RENDER_TICK);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    // We'll hold these in Options until authenticated
+    let mut gmail_client: Option<GmailClient> = None;
+
+    // Clone sync_state for use in the main loop
+    let sync_state_loop = sync_state.clone();
+    // Wakes the sync loop early for "sync now"
+    let sync_now = Arc::new(tokio::sync::Notify::new());
+
+    // Set by `:account`; gtui restarts as that account once it has exited
+    let mut switch_to: Option<String> = None;
+
+    // Errors still go through the exit flush below, so a lost terminal
+    // does not cost the open draft or the queued Gmail changes
+    let result: anyhow::Result<()> = async {
+        loop {
+            if ui_state.want_older_page {
+                request_older_page(
+                    &mut ui_state,
+                    gmail_client.as_ref(),
+                    &sync_state_loop,
+                    &action_tx,
+                    &db,
+                    config.sync.body_on_demand,
+                );
+            }
+            if config.sync.body_on_demand {
+                request_bodies(
+                    &ui_state,
+                    gmail_client.as_ref(),
+                    &sync_state_loop,
+                    &action_tx,
+                    &db,
+                );
+            }
+            ui_state.retrying_until = gmail_client.as_ref().and_then(|g| g.retrying_until());
+            terminal.draw(|f| ui::render(f, &mut ui_state))?;
+
+            // Nothing below awaits the network: Gmail calls run in spawned tasks
+            // and report back through `action_rx` or `refresh_rx`
+            let loop_event = tokio::select! {
+                maybe_event = events.next() => match maybe_event {
+                    Some(Ok(event)) => LoopEvent::Input(event),
+                    Some(Err(e)) => return Err(e.into()),
+                    None => break,
+                },
+                Some(url) = rx.recv() => LoopEvent::AuthUrl(url),
+                Some(true) = done_rx.recv(), if !authenticated => LoopEvent::Authenticated,
+                Some(()) = refresh_rx.recv() => LoopEvent::Refresh,
+                Some(result) = action_rx.recv() => LoopEvent::Action(result),
+                _ = tick.tick() => LoopEvent::Tick,
+                signal = exit_signals.recv() => LoopEvent::Exit(signal),
+            };
+
+            let event = match loop_event {
+                LoopEvent::Input(event) => event,
+                LoopEvent::AuthUrl(url) => {
+                    ui_state.auth_url = Some(url);
+                    ui_state.mode = ui::UIMode::Authentication;
+                    continue;
+                }
+                LoopEvent::Authenticated => {
+                    authenticated = true;
+                    ui_state.mode = ui::UIMode::Browsing;
+                    ui_state.auth_url = None;
+
+                    // Now create the hub and client
+                    let hub = Gmail::new(
+                        hyper::Client::builder().build(
+                            hyper_rustls::HttpsConnectorBuilder::new()
+                                .with_native_roots()
+                                .expect("Failed to load native roots")
+                                .https_only()
+                                .enable_http1()
+                                .build(),
+                        ),
+                        auth_builder.clone(),
+                    );
+
+                    let client = GmailClient::new(hub);
+                    gmail_client = Some(client.clone());
+
+                    // Fetch send-as aliases and their signatures
+                    let send_as_client = client.clone();
+                    let send_as_tx = action_tx.clone();
+                    tokio::spawn(async move {
+                        if let Ok(send_as) = send_as_client.list_send_as().await {
+                            let _ = send_as_tx.send(ActionResult::SendAs(send_as));
                         }
-                    } else if matches_key(key, &config.keybindings.move_up) {
-                        match ui_state.focused_panel {
-                            FocusedPanel::Labels => {
-                                if ui_state.selected_label_index > 0 {
-                                    ui_state.selected_label_index -= 1;
-                                    let label = &ui_state.labels[ui_state.selected_label_index];
-                                    current_offset = 0;
-                                    ui_state.messages = db
-                                        .get_messages_by_label(&label.id, limit, current_offset)
-                                        .await?;
-                                    ui_state.selected_message_index = 0;
-                                    ui_state.detail_scroll = 0;
-                                    if let Some(msg) = ui_state.messages.get(0) {
-                                        ui_state.threaded_messages =
-                                            db.get_messages_by_thread(&msg.thread_id).await?;
-                                    } else {
-                                        ui_state.threaded_messages.clear();
+                    });
+
+                    // Kick off sync
+                    let sync_client = client.clone();
+                    let sync_db = db.clone();
+                    let sync_refresh_tx = refresh_tx.clone();
+                    let sync_state_clone = sync_state.clone();
+                    let mut priority_rx = priority_rx.take().unwrap();
+                    let people_api = config.contacts.people_api;
+                    let sync_wake = sync_now.clone();
+                    let sync_policy = sync::SyncPolicy::new(config.sync.clone());
+                    tokio::spawn(async move {
+                        // Seed the address book from Google Contacts once per run
+                        if people_api {
+                            match sync_client.list_people_contacts().await {
+                                Ok(people) => {
+                                    if let Err(e) = sync_db.upsert_people_contacts(&people).await {
+                                        tracing::warn!("Saving Google Contacts failed: {:#}", e);
                                     }
-                                    let _ = priority_tx.try_send(label.id.clone());
                                 }
-                            }
-                            FocusedPanel::Messages => {
-                                if ui_state.selected_message_index > 0 {
-                                    ui_state.selected_message_index -= 1;
-                                    ui_state.detail_scroll = 0;
-                                    if let Some(msg) =
-                                        ui_state.messages.get(ui_state.selected_message_index)
-                                    {
-                                        ui_state.threaded_messages =
-                                            db.get_messages_by_thread(&msg.thread_id).await?;
-                                    }
+                                Err(e) => {
+                                    tracing::warn!("People API import failed: {:#}", e);
                                 }
                             }
-                            FocusedPanel::Details => {
-                                ui_state.detail_scroll = ui_state.detail_scroll.saturating_sub(1);
-                            }
                         }
-                    }
-                    // Email Actions
-                    else if matches_key(key, &config.keybindings.mark_read) {
-                        // Toggle Read/Unread
-                        if let Some(m) = ui_state.messages.get_mut(ui_state.selected_message_index)
-                        {
-                            let is_currently_read = m.is_read;
-                            m.is_read = !is_currently_read;
-                            let id = m.id.clone();
-                            if let Some(gmail) = &gmail_client {
-                                let gmail = gmail.clone();
-                                let db_url_str = db_url.clone();
-                                let new_status = !is_currently_read;
-                                tokio::spawn(async move {
-                                    if let Ok(db_clone) = db::Database::new(&db_url_str).await {
-                                        if new_status {
-                                            let _ = gmail.mark_as_read(&id).await;
-                                        } else {
-                                            let _ = gmail.mark_as_unread(&id).await;
-                                        }
-                                        let _ =
-                                            db_clone.mark_message_as_read(&id, new_status).await;
-                                    }
-                                });
+
+                        // The label being viewed; synced first, even if excluded
+                        let mut priority_label = None;
+                        loop {
+                            sync_pass(
+                                &sync_client,
+                                &sync_db,
+                                &sync_policy,
+                                &sync_state_clone,
+                                &sync_refresh_tx,
+                                &mut priority_rx,
+                                &mut priority_label,
+                            )
+                            .await;
+
+                            tokio::select! {
+                                _ = tokio::time::sleep(sync_policy.interval()) => {}
+                                _ = sync_wake.notified() => {}
                             }
                         }
-                    } else if matches_key(key, &config.keybindings.reply) {
-                        // Reply
-                        if let Some(m) = ui_state.messages.get(ui_state.selected_message_index) {
-                            let subject = m.subject.as_deref().unwrap_or("");
-                            let new_subject = if subject.to_lowercase().starts_with("re:") {
-                                subject.to_string()
-                            } else {
-                                format!("Re: {}", subject)
-                            };
+                    });
 
-                            let mut quoted_body = String::new();
-                            let date = DateTime::from_timestamp_millis(m.internal_date)
-                                .unwrap_or_default()
-                                .with_timezone(&Local);
-
-                            quoted_body.push_str(&format!(
-                                "\nOn {}, {} wrote:\n",
-                                date.format("%a, %b %d, %Y at %l:%M %p"),
-                                m.from_address.as_deref().unwrap_or("Unknown")
-                            ));
-
-                            let body_to_quote = m.body_plain.as_ref().or(m.snippet.as_ref());
-                            if let Some(body) = body_to_quote {
-                                for line in body.lines() {
-                                    quoted_body.push_str(&format!("> {}\n", line));
-                                }
+                    // Load initial data for UI
+                    ui_state.labels = db.get_labels().await?;
+                    ui_state.contacts = db.get_contacts().await?;
+                    if let Some(index) = ui_state.labels.iter().position(|l| l.id == "INBOX") {
+                        ui_state.selected_label_index = index;
+                    }
+                    if let Some(label) = ui_state.labels.get(ui_state.selected_label_index) {
+                        ui_state.messages = db.get_messages_by_label(&label.id, limit, 0).await?;
+                        if let Some(msg) = ui_state.messages.get(ui_state.selected_message_index) {
+                            ui_state.threaded_messages =
+                                db.get_messages_by_thread(&msg.thread_id).await?;
+                        }
+                    }
+                    continue;
+                }
+                LoopEvent::Refresh => {
+                    // Several syncs may have finished; reload once for all of them
+                    while refresh_rx.try_recv().is_ok() {}
+                    // Re-load labels
+                    ui_state.labels = db.get_labels().await?;
+                    ui_state.contacts = db.get_contacts().await?;
+                    if ui_state.search_query.is_some()
+                        || ui_state.selected_label_index < ui_state.labels.len()
+                    {
+                        // Re-load every conversation already shown for the current label or search
+                        let count = (ui_state.messages.len() as i64).max(limit);
+                        let new_messages = load_conversations(&ui_state, &db, count, 0).await?;
+                        ui_state.end_of_cache = (new_messages.len() as i64) < count;
+
+                        // If the message list changed, we need to be careful with the selection index
+                        ui_state.messages = new_messages;
+
+                        // Clamp selection index
+                        if !ui_state.messages.is_empty() {
+                            if ui_state.selected_message_index >= ui_state.messages.len() {
+                                ui_state.selected_message_index =
+                                    ui_state.messages.len().saturating_sub(1);
                             }
 
-                            let mut signature_part = String::new();
-                            let sig_to_use = ui_state
-                                .remote_signature
-                                .as_ref()
-                                .or(config.signatures.reply.as_ref());
-                            if let Some(sig) = sig_to_use {
-                                signature_part.push_str("--\n");
-                                signature_part.push_str(sig);
-                                signature_part.push_str("\n\n");
+                            // Re-load threaded messages for selected message
+                            if let Some(msg) =
+                                ui_state.messages.get(ui_state.selected_message_index)
+                            {
+                                tracing::debug!(
+                                    "[Main] Sync refresh loading thread_id: {:?}",
+                                    msg.thread_id
+                                );
+                                ui_state.threaded_messages =
+                                    db.get_messages_by_thread(&msg.thread_id).await?;
+                                tracing::debug!(
+                                    "[Main] Sync refresh loaded {} messages",
+                                    ui_state.threaded_messages.len()
+                                );
                             }
-
-                            let final_body = format!("\n\n{}{}", signature_part, quoted_body);
-
-                            ui_state.mode = ui::UIMode::Composing;
-                            let _ = execute!(io::stdout(), crossterm::cursor::Show);
-                            let mut compose = ui::ComposeState::new(
-                                &m.from_address.clone().unwrap_or_default(),
-                                "",
-                                "",
-                                &new_subject,
-                                &final_body,
+                        } else {
+                            ui_state.selected_message_index = 0;
+                            tracing::debug!(
+                                "[Main] Clearing threaded_messages (no messages in label)"
                             );
-                            compose.focused_field = ui::ComposeField::Body;
-                            ui_state.compose_state = Some(compose);
+                            ui_state.threaded_messages.clear();
                         }
-                    } else if matches_key(key, &config.keybindings.forward) {
-                        // Forward
-                        if let Some(m) = ui_state.messages.get(ui_state.selected_message_index) {
-                            let subject = m.subject.as_deref().unwrap_or("");
-                            let new_subject = if subject.to_lowercase().starts_with("fwd:")
-                                || subject.to_lowercase().starts_with("fw:")
-                            {
-                                subject.to_string()
-                            } else {
-                                format!("Fwd: {}", subject)
-                            };
-
-                            // Build forwarded body
-                            let mut forward_body = String::new();
-
-                            // Two blank lines at top for user's context
-                            forward_body.push_str("\n\n");
+                    }
+                    continue;
+                }
+                LoopEvent::Action(result) => {
+                    apply_action_result(&mut ui_state, &db, &sync_state_loop, result, limit)
+                        .await?;
+                    continue;
+                }
+                // Redraw so sync progress stays current
+                LoopEvent::Exit(signal) => {
+                    tracing::info!("{} received, exiting", signal);
+                    break;
+                }
+                LoopEvent::Tick => {
+                    ui_state.notifications.prune(Instant::now());
+                    continue;
+                }
+            };
 
-                            // Add signature (new_message signature for forwards)
-                            let sig_to_use = ui_state
-                                .remote_signature
-                                .as_ref()
-                                .or(config.signatures.new_message.as_ref());
-                            if let Some(sig) = sig_to_use {
-                                forward_body.push_str("--\n");
-                                forward_body.push_str(sig);
-                                forward_body.push('\n');
-                            }
+            if let Event::Resize(..) = event {
+                // Drop whatever the old size left behind before the next draw
+                terminal.clear()?;
+                continue;
+            }
+            if let Event::Mouse(mouse) = event {
+                if authenticated && matches!(ui_state.mode, ui::UIMode::Browsing) {
+                    handle_mouse(
+                        &mut ui_state,
+                        &db,
+                        mouse,
+                        &mut last_click,
+                        limit,
+                        &priority_tx,
+                    )
+                    .await?;
+                }
+                continue;
+            }
 
-                            // Forwarding header block
-                            forward_body.push_str("\n---------- Forwarded message ----------\n");
-                            forward_body.push_str(&format!(
-                                "From: {}\n",
-                                m.from_address.as_deref().unwrap_or("Unknown")
-                            ));
-
-                            let date = DateTime::from_timestamp_millis(m.internal_date)
-                                .unwrap_or_default()
-                                .with_timezone(&Local);
-                            forward_body.push_str(&format!(
-                                "Date: {}\n",
-                                date.format("%a, %b %d, %Y at %l:%M %p")
-                            ));
-
-                            forward_body.push_str(&format!("Subject: {}\n", subject));
-                            forward_body.push_str(&format!(
-                                "To: {}\n",
-                                m.to_address.as_deref().unwrap_or("Unknown")
-                            ));
-
-                            // Original message body
-                            let body_to_forward = m.body_plain.as_ref().or(m.snippet.as_ref());
-                            if let Some(body) = body_to_forward {
-                                forward_body.push_str(&format!("\n{}", body));
-                            }
+            if let Event::Key(key) = event {
+                // Only handle keys if authenticated or to quit
+                if !authenticated && key.code != KeyCode::Char('q') {
+                    continue;
+                }
+                // Suspending works from anywhere, even mid-message
+                if matches_key(key, &config.keybindings.suspend) {
+                    suspend(&mut ui_state, &mut terminal, config.mouse.enabled);
+                    continue;
+                }
 
-                            ui_state.mode = ui::UIMode::Composing;
-                            let _ = execute!(io::stdout(), crossterm::cursor::Show);
-                            let compose = ui::ComposeState::new(
-                                "",  // Empty To field
-                                "",
-                                "",
-                                &new_subject,
-                                &forward_body,
-                            );
-                            // Cursor starts in To field (default)
-                            ui_state.compose_state = Some(compose);
+                match ui_state.mode {
+                    ui::UIMode::Authentication => {
+                        if key.code == KeyCode::Char('q') {
+                            break;
                         }
-                    } else if matches_key(key, &config.keybindings.new_message) {
-                        // New message
-                        ui_state.mode = ui::UIMode::Composing;
-                        let _ = execute!(io::stdout(), crossterm::cursor::Show);
-
-                        let mut body = String::new();
-                        let sig_to_use = ui_state
-                            .remote_signature
-                            .as_ref()
-                            .or(config.signatures.new_message.as_ref());
-                        if let Some(sig) = sig_to_use {
-                            body.push_str("\n\n--\n");
-                            body.push_str(sig);
-                        }
-
-                        ui_state.compose_state = Some(ui::ComposeState::new(
-                            "",
-                            "",
-                            "",
-                            "",
-                            &body,
-                        ));
-                    } else if matches_key(key, &config.keybindings.delete) {
-                        // Do nothing if labels panel is active
-                        if ui_state.focused_panel == FocusedPanel::Labels {
+                    }
+                    ui::UIMode::Browsing => {
+                        if ui_state.show_help {
+                            if matches_key(key, &config.keybindings.move_down) {
+                                ui_state.help_scroll = ui_state.help_scroll.saturating_add(1);
+                            } else if matches_key(key, &config.keybindings.move_up) {
+                                ui_state.help_scroll = ui_state.help_scroll.saturating_sub(1);
+                            } else {
+                                ui_state.show_help = false;
+                            }
                             continue;
                         }
-                        // Ensure conversations list is the active panel
-                        ui_state.focused_panel = FocusedPanel::Messages;
-                        // Delete all messages in the thread
-                        if let Some(m) = ui_state.messages.get(ui_state.selected_message_index) {
-                            let thread_id = m.thread_id.clone();
-                            
-                            // Get all messages in the thread from the database
-                            let thread_messages = db.get_messages_by_thread(&thread_id).await?;
-                            let message_ids: Vec<String> = thread_messages.iter().map(|m| m.id.clone()).collect();
-                            
-                            // Mark messages as recently modified to prevent sync from re-adding them
-                            if let Ok(mut state) = sync_state_loop.lock() {
-                                state.mark_modified_many(message_ids.clone());
-                            }
-                            
-                            // Capture for undo BEFORE removing
-                            let current_label_id = ui_state
-                                .labels
-                                .get(ui_state.selected_label_index)
-                                .map(|l| l.id.clone())
-                                .unwrap_or_else(|| "INBOX".to_string());
-                            let original_index = ui_state.selected_message_index;
-                            ui_state.undo_stack.push(UndoableAction::Delete {
-                                messages: thread_messages.clone(),
-                                label_id: current_label_id.clone(),
-                                original_index,
-                            });
-
-                            // Delete from database SYNCHRONOUSLY to ensure consistency
-                            for id in &message_ids {
-                                if let Err(e) = db.delete_message(id).await {
-                                    eprintln!("Error deleting message from DB: {}", e);
+                        let (action, count) = if let Some(palette) = &mut ui_state.palette {
+                            match palette.handle_key(key) {
+                                ui::PaletteOutcome::Pending => continue,
+                                ui::PaletteOutcome::Close => {
+                                    ui_state.palette = None;
+                                    continue;
                                 }
-                            }
-
-                            // Call Gmail API and await result to ensure it succeeds
-                            let mut api_succeeded = true;
-                            if let Some(gmail) = &gmail_client {
-                                match gmail.trash_messages(&message_ids).await {
-                                    Ok(_) => {
-                                        ui_state.status_message = Some("Deleted successfully".to_string());
-                                    }
-                                    Err(e) => {
-                                        eprintln!("Error trashing messages: {}", e);
-                                        ui_state.status_message = Some(format!("Delete failed: {}", e));
-                                        api_succeeded = false;
-                                        // Restore messages to database since API failed
-                                        if let Err(e) = db.upsert_messages(&thread_messages, &current_label_id).await {
-                                            eprintln!("Error restoring messages to DB: {}", e);
-                                        }
-                                        // Remove from recently_modified since operation failed
-                                        if let Ok(mut state) = sync_state_loop.lock() {
-                                            for id in &message_ids {
-                                                state.recently_modified.remove(id);
-                                            }
-                                        }
-                                    }
+                                ui::PaletteOutcome::Run(action) => {
+                                    ui_state.palette = None;
+                                    (Some(action), 1)
                                 }
                             }
-                            
-                            // Only update UI if API succeeded (or if no gmail client)
-                            if api_succeeded {
-                                ui_state.messages.remove(ui_state.selected_message_index);
-                                if ui_state.selected_message_index >= ui_state.messages.len()
-                                    && !ui_state.messages.is_empty()
-                                {
-                                    ui_state.selected_message_index = ui_state.messages.len() - 1;
-                                }
+                        } else {
+                            let keymap = if ui_state.focused_panel == FocusedPanel::Details {
+                                &keymaps.details
+                            } else {
+                                &keymaps.browsing
+                            };
+                            let outcome = key_state.handle(keymap, key);
+                            ui_state.pending_keys = key_state.pending();
+                            match outcome {
+                                keymap::KeyOutcome::Action(action, count) => (Some(action), count),
+                                keymap::KeyOutcome::Pending => continue,
+                                keymap::KeyOutcome::Unbound => (None, 1),
+                            }
+                        };
+                        let Some(action) = action else {
+                            continue;
+                        };
+                        if action == Action::Quit {
+                            break;
+                        }
+                        if let Action::SwitchAccount(name) = action {
+                            switch_to = Some(name);
+                            break;
+                        }
 
-                                // Refresh detail view
-                                if let Some(msg) =
-                                    ui_state.messages.get(ui_state.selected_message_index)
-                                {
-                                    ui_state.threaded_messages =
-                                        db.get_messages_by_thread(&msg.thread_id).await?;
-                                } else {
-                                    ui_state.threaded_messages.clear();
+                        let repeat = if action.repeats() { count } else { 1 };
+                        for _ in 0..repeat {
+                            let ctx = ActionContext {
+                                terminal: &mut terminal,
+                                db: &db,
+                                gmail_client: &gmail_client,
+                                config: &config,
+                                sync_state: &sync_state_loop,
+                                action_tx: &action_tx,
+                                refresh_tx: &refresh_tx,
+                                priority_tx: &priority_tx,
+                                sync_now: &sync_now,
+                                limit,
+                            };
+                            run_action(&mut ui_state, action.clone(), ctx).await?;
+                        }
+                    }
+                    ui::UIMode::Composing => match key.code {
+                        // While completions are showing, navigation keys drive the popup
+                        KeyCode::Esc
+                        | KeyCode::Up
+                        | KeyCode::Down
+                        | KeyCode::Tab
+                        | KeyCode::Enter
+                            if ui_state
+                                .compose_state
+                                .as_ref()
+                                .is_some_and(|cs| !cs.completions.is_empty()) =>
+                        {
+                            if let Some(cs) = &mut ui_state.compose_state {
+                                match key.code {
+                                    KeyCode::Esc => cs.completions.clear(),
+                                    KeyCode::Up => cs.select_prev_completion(),
+                                    KeyCode::Down => cs.select_next_completion(),
+                                    _ => cs.accept_completion(),
                                 }
                             }
                         }
-                    } else if matches_key(key, &config.keybindings.archive) {
-                        // Do nothing if labels panel is active
-                        if ui_state.focused_panel == FocusedPanel::Labels {
-                            continue;
+                        KeyCode::Esc => {
+                            ui_state.mode = ui::UIMode::Browsing;
+                            let _ = execute!(io::stdout(), crossterm::cursor::Hide);
+                            ui_state.compose_state = None;
                         }
-                        // Ensure conversations list is the active panel
-                        ui_state.focused_panel = FocusedPanel::Messages;
-                        // Archive all messages in the thread
-                        if let Some(m) = ui_state.messages.get(ui_state.selected_message_index) {
-                            let thread_id = m.thread_id.clone();
-                            
-                            // Get all messages in the thread from the database
-                            let thread_messages = db.get_messages_by_thread(&thread_id).await?;
-                            let message_ids: Vec<String> = thread_messages.iter().map(|m| m.id.clone()).collect();
-                            
-                            // Mark messages as recently modified to prevent sync from re-adding them
-                            if let Ok(mut state) = sync_state_loop.lock() {
-                                state.mark_modified_many(message_ids.clone());
+                        _ if keymaps.compose.lookup(key) == Some(Action::ToggleMarkdown) => {
+                            if let Some(cs) = &mut ui_state.compose_state {
+                                cs.toggle_markdown();
                             }
-                            
-                            // Determine which label to remove: INBOX normally, or Category label if viewing a Category
-                            let current_label_id = ui_state
-                                .labels
-                                .get(ui_state.selected_label_index)
-                                .map(|l| l.id.clone())
-                                .unwrap_or_else(|| "INBOX".to_string());
-                            
-                            // If viewing a Category label (CATEGORY_*), remove that label instead of INBOX
-                            // Otherwise, remove INBOX (standard archive behavior)
-                            let label_to_remove = if current_label_id.starts_with("CATEGORY_") {
-                                current_label_id.clone()
-                            } else {
-                                "INBOX".to_string()
-                            };
-                            
-                            // Remove the label from database SYNCHRONOUSLY to ensure consistency
-                            for id in &message_ids {
-                                if let Err(e) = db.remove_label_from_message(id, &label_to_remove).await {
-                                    eprintln!("Error removing {} label from DB: {}", label_to_remove, e);
-                                }
+                        }
+                        _ if keymaps.compose.lookup(key) == Some(Action::TogglePreview) => {
+                            if let Some(cs) = &mut ui_state.compose_state {
+                                cs.toggle_preview();
                             }
-
-                            // Call Gmail API and await result to ensure it succeeds
-                            let mut api_succeeded = true;
-                            if let Some(gmail) = &gmail_client {
-                                // If archiving from a Category, remove the category label from Gmail
-                                // Otherwise use standard archive (remove INBOX)
-                                let result = if current_label_id.starts_with("CATEGORY_") {
-                                    gmail.remove_label_from_messages(&message_ids, &current_label_id).await
-                                } else {
-                                    gmail.archive_messages(&message_ids).await
-                                };
-                                
-                                match result {
-                                    Ok(_) => {
-                                        ui_state.status_message = Some("Archived successfully".to_string());
-                                    }
+                        }
+                        _ if keymaps.compose.lookup(key) == Some(Action::CycleFrom) => {
+                            if let Some(cs) = &mut ui_state.compose_state {
+                                cs.cycle_from();
+                            }
+                        }
+                        _ if keymaps.compose.lookup(key) == Some(Action::ExternalEditor) => {
+                            // Edit the whole draft, headers included, in $VISUAL/$EDITOR
+                            if let Some(cs) = &mut ui_state.compose_state {
+                                let draft = cs.to_draft();
+                                match editor::with_suspended_terminal(
+                                    &mut terminal,
+                                    config.mouse.enabled,
+                                    || editor::edit_draft(&draft),
+                                ) {
+                                    Ok(edited) => cs.apply_draft(&edited),
                                     Err(e) => {
-                                        eprintln!("Error archiving messages: {}", e);
-                                        ui_state.status_message = Some(format!("Archive failed: {}", e));
-                                        api_succeeded = false;
-                                        // Restore label since API failed
-                                        for id in &message_ids {
-                                            if let Err(e) = db.add_label_to_message(id, &label_to_remove).await {
-                                                eprintln!("Error restoring {} label: {}", label_to_remove, e);
-                                            }
-                                        }
-                                        // Remove from recently_modified since operation failed
-                                        if let Ok(mut state) = sync_state_loop.lock() {
-                                            for id in &message_ids {
-                                                state.recently_modified.remove(id);
-                                            }
-                                        }
+                                        ui_state.notify(
+                                            Level::Error,
+                                            format!("Editor failed: {:#}", e),
+                                        );
                                     }
                                 }
                             }
-                            
-                            // Only update UI if API succeeded (or if no gmail client)
-                            if api_succeeded {
-                                // Capture for undo BEFORE removing
-                                let original_index = ui_state.selected_message_index;
-                                ui_state.undo_stack.push(UndoableAction::Archive {
-                                    messages: thread_messages,
-                                    label_id: label_to_remove.clone(),
-                                    original_index,
-                                });
-
-                                ui_state.messages.remove(ui_state.selected_message_index);
-                                if ui_state.selected_message_index >= ui_state.messages.len()
-                                    && !ui_state.messages.is_empty()
-                                {
-                                    ui_state.selected_message_index = ui_state.messages.len() - 1;
+                        }
+                        _ if keymaps.compose.lookup(key) == Some(Action::SendMessage) => {
+                            let Some(cs) = ui_state.compose_state.as_mut() else {
+                                continue;
+                            };
+                            // Validate before anything leaves the composition panel
+                            let outgoing = match cs.to_outgoing() {
+                                Ok(outgoing) => outgoing,
+                                Err(e) => {
+                                    cs.error = Some(e.to_string());
+                                    continue;
                                 }
-
-                                // Refresh detail view
-                                if let Some(msg) =
-                                    ui_state.messages.get(ui_state.selected_message_index)
-                                {
-                                    ui_state.threaded_messages =
-                                        db.get_messages_by_thread(&msg.thread_id).await?;
-                                } else {
-                                    ui_state.threaded_messages.clear();
+                            };
+                            // Only close once the message can no longer be lost
+                            let outbox_id = match db.queue_outbox(&cs.to_outbox()).await {
+                                Ok(id) => id,
+                                Err(e) => {
+                                    cs.error = Some(format!("{:#}", e));
+                                    continue;
                                 }
+                            };
+                            ui_state.mode = ui::UIMode::Browsing;
+                            let _ = execute!(io::stdout(), crossterm::cursor::Hide);
+                            ui_state.compose_state = None;
+                            ui_state.outbox = db.get_outbox().await?;
+
+                            match &gmail_client {
+                                Some(gmail) => send_from_outbox(
+                                    &mut ui_state,
+                                    gmail,
+                                    &db,
+                                    outbox_id,
+                                    outgoing,
+                                    &action_tx,
+                                    &refresh_tx,
+                                ),
+                                None => ui_state.notify(
+                                    Level::Warn,
+                                    "Not signed in; the message is in the outbox",
+                                ),
                             }
                         }
-                    } else if matches_key(key, &config.keybindings.undo) {
-                        // Undo - only in Messages or Details panel
-                        if matches!(
-                            ui_state.focused_panel,
-                            FocusedPanel::Messages | FocusedPanel::Details
-                        ) {
-                            if let Some(action) = ui_state.undo_stack.pop() {
-                                let description = action.description();
-                                match action {
-                                    UndoableAction::Delete { messages, label_id, original_index } => {
-                                        // Get the representative message (first one) for UI insertion
-                                        let representative = messages.first().cloned().unwrap_or_default();
-                                        
-                                        // Re-insert into UI at original position (clamped to list size)
-                                        let insert_index = original_index.min(ui_state.messages.len());
-                                        ui_state.messages.insert(insert_index, representative.clone());
-                                        ui_state.selected_message_index = insert_index;
-
-                                        // Re-insert all messages into database
-                                        let _ = db.upsert_messages(&messages, &label_id).await;
-
-                                        // Untrash all messages via Gmail API
-                                        if let Some(gmail) = &gmail_client {
-                                            let gmail = gmail.clone();
-                                            let ids: Vec<String> = messages.iter().map(|m| m.id.clone()).collect();
-                                            tokio::spawn(async move {
-                                                for id in ids {
-                                                    let _ = gmail.untrash_message(&id).await;
-                                                }
-                                            });
-                                        }
-
-                                        // Refresh detail view
-                                        ui_state.threaded_messages =
-                                            db.get_messages_by_thread(&representative.thread_id).await?;
-                                    }
-                                    UndoableAction::Archive { messages, label_id, original_index } => {
-                                        // Get the representative message (first one) for UI insertion
-                                        let representative = messages.first().cloned().unwrap_or_default();
-
-                                        // Re-insert into UI at original position (only if viewing the same label)
-                                        let current_label = ui_state
-                                            .labels
-                                            .get(ui_state.selected_label_index)
-                                            .map(|l| l.id.as_str());
-                                        if current_label == Some(&label_id) {
-                                            let insert_index = original_index.min(ui_state.messages.len());
-                                            ui_state.messages.insert(insert_index, representative.clone());
-                                            ui_state.selected_message_index = insert_index;
-                                        }
-
-                                        // Re-add the removed label in database for all messages
-                                        for message in &messages {
-                                            let _ = db.add_label_to_message(&message.id, &label_id).await;
-                                        }
-
-                                        // Restore label via Gmail API
-                                        if let Some(gmail) = &gmail_client {
-                                            let gmail = gmail.clone();
-                                            let ids: Vec<String> = messages.iter().map(|m| m.id.clone()).collect();
-                                            let label_to_restore = label_id.clone();
-                                            tokio::spawn(async move {
-                                                if label_to_restore == "INBOX" {
-                                                    // Use unarchive for INBOX
-                                                    for id in ids {
-                                                        let _ = gmail.unarchive_message(&id).await;
-                                                    }
-                                                } else {
-                                                    // Use add_label_to_message for other labels (like categories)
-                                                    for id in ids {
-                                                        let _ = gmail.add_label_to_message(&id, &label_to_restore).await;
-                                                    }
-                                                }
-                                            });
-                                        }
-
-                                        // Refresh detail view if message was re-added
-                                        if current_label == Some(&label_id) {
-                                            ui_state.threaded_messages =
-                                                db.get_messages_by_thread(&representative.thread_id).await?;
+                        _ if keymaps.compose.lookup(key) == Some(Action::ToggleCcBcc) => {
+                            if let Some(cs) = &mut ui_state.compose_state {
+                                cs.show_cc_bcc = !cs.show_cc_bcc;
+                            }
+                        }
+                        KeyCode::Tab => {
+                            if let Some(cs) = &mut ui_state.compose_state {
+                                cs.focused_field = match cs.focused_field {
+                                    ui::ComposeField::To => {
+                                        if cs.show_cc_bcc {
+                                            ui::ComposeField::Cc
+                                        } else {
+                                            ui::ComposeField::Subject
                                         }
                                     }
-                                }
-                                ui_state.status_message = Some(format!("Undone: {}", description));
+                                    ui::ComposeField::Cc => ui::ComposeField::Bcc,
+                                    ui::ComposeField::Bcc => ui::ComposeField::Subject,
+                                    ui::ComposeField::Subject => ui::ComposeField::Body,
+                                    ui::ComposeField::Body => ui::ComposeField::To,
+                                };
                             }
                         }
-                    }
-                }
-                ui::UIMode::Composing => match key.code {
-                    KeyCode::Esc => {
-                        ui_state.mode = ui::UIMode::Browsing;
-                        let _ = execute!(io::stdout(), crossterm::cursor::Hide);
-                        ui_state.compose_state = None;
-                    }
-                    _ if matches_key(key, &config.keybindings.send_message) => {
-                        if let Some(cs) = &ui_state.compose_state {
-                            if let Some(gmail) = &gmail_client {
-                                let (to, cc, bcc, sub, body) = (
-                                    cs.get_to(),
-                                    cs.get_cc(),
-                                    cs.get_bcc(),
-                                    cs.get_subject(),
-                                    cs.get_body(),
-                                );
-                                let gmail = gmail.clone();
-                                let db_url_str = db_url.clone();
-                                let refresh_tx_clone = refresh_tx.clone();
-                                tokio::spawn(async move {
-                                    // Send the message and get its ID
-                                    if let Ok(Some(msg_id)) = gmail.send_message(&to, &cc, &bcc, &sub, &body).await {
-                                        // Fetch the sent message to get full details including thread_id
-                                        if let Ok(sent_msg) = gmail.get_message(&msg_id).await {
-                                            // Store in database with SENT label
-                                            if let Ok(db_clone) = db::Database::new(&db_url_str).await {
-                                                let _ = db_clone.upsert_messages(&[sent_msg], "SENT").await;
-                                                // Trigger a refresh so the UI updates
-                                                let _ = refresh_tx_clone.send(()).await;
-                                            }
+                        KeyCode::BackTab => {
+                            if let Some(cs) = &mut ui_state.compose_state {
+                                cs.focused_field = match cs.focused_field {
+                                    ui::ComposeField::To => ui::ComposeField::Body,
+                                    ui::ComposeField::Cc => ui::ComposeField::To,
+                                    ui::ComposeField::Bcc => ui::ComposeField::Cc,
+                                    ui::ComposeField::Subject => {
+                                        if cs.show_cc_bcc {
+                                            ui::ComposeField::Bcc
+                                        } else {
+                                            ui::ComposeField::To
                                         }
                                     }
-                                });
+                                    ui::ComposeField::Body => ui::ComposeField::Subject,
+                                };
                             }
                         }
-                        ui_state.mode = ui::UIMode::Browsing;
-                        let _ = execute!(io::stdout(), crossterm::cursor::Hide);
-                        ui_state.compose_state = None;
-                    }
-                    KeyCode::Char('b')
-                        if key
-                            .modifiers
-                            .contains(crossterm::event::KeyModifiers::CONTROL) =>
-                    {
-                        if let Some(cs) = &mut ui_state.compose_state {
-                            cs.show_cc_bcc = !cs.show_cc_bcc;
-                        }
-                    }
-                    KeyCode::Tab => {
-                        if let Some(cs) = &mut ui_state.compose_state {
-                            cs.focused_field = match cs.focused_field {
-                                ui::ComposeField::To => {
-                                    if cs.show_cc_bcc {
-                                        ui::ComposeField::Cc
-                                    } else {
-                                        ui::ComposeField::Subject
+                        KeyCode::Enter => {
+                            if let Some(cs) = &mut ui_state.compose_state {
+                                match cs.focused_field {
+                                    ui::ComposeField::Body => {
+                                        // Let TextArea handle Enter in body
+                                        cs.focused_textarea().input(key);
                                     }
-                                }
-                                ui::ComposeField::Cc => ui::ComposeField::Bcc,
-                                ui::ComposeField::Bcc => ui::ComposeField::Subject,
-                                ui::ComposeField::Subject => ui::ComposeField::Body,
-                                ui::ComposeField::Body => ui::ComposeField::To,
-                            };
-                        }
-                    }
-                    KeyCode::BackTab => {
-                        if let Some(cs) = &mut ui_state.compose_state {
-                            cs.focused_field = match cs.focused_field {
-                                ui::ComposeField::To => ui::ComposeField::Body,
-                                ui::ComposeField::Cc => ui::ComposeField::To,
-                                ui::ComposeField::Bcc => ui::ComposeField::Cc,
-                                ui::ComposeField::Subject => {
-                                    if cs.show_cc_bcc {
-                                        ui::ComposeField::Bcc
-                                    } else {
-                                        ui::ComposeField::To
+                                    _ => {
+                                        // Move to next field on Enter in other fields
+                                        cs.focused_field = match cs.focused_field {
+                                            ui::ComposeField::To => {
+                                                if cs.show_cc_bcc {
+                                                    ui::ComposeField::Cc
+                                                } else {
+                                                    ui::ComposeField::Subject
+                                                }
+                                            }
+                                            ui::ComposeField::Cc => ui::ComposeField::Bcc,
+                                            ui::ComposeField::Bcc => ui::ComposeField::Subject,
+                                            ui::ComposeField::Subject => ui::ComposeField::Body,
+                                            _ => ui::ComposeField::Body,
+                                        };
                                     }
                                 }
-                                ui::ComposeField::Body => ui::ComposeField::Subject,
-                            };
+                            }
                         }
-                    }
-                    KeyCode::Enter => {
-                        if let Some(cs) = &mut ui_state.compose_state {
-                            match cs.focused_field {
-                                ui::ComposeField::Body => {
-                                    // Let TextArea handle Enter in body
-                                    cs.focused_textarea().input(key);
-                                }
-                                _ => {
-                                    // Move to next field on Enter in other fields
-                                    cs.focused_field = match cs.focused_field {
-                                        ui::ComposeField::To => {
-                                            if cs.show_cc_bcc {
-                                                ui::ComposeField::Cc
-                                            } else {
-                                                ui::ComposeField::Subject
-                                            }
-                                        }
-                                        ui::ComposeField::Cc => ui::ComposeField::Bcc,
-                                        ui::ComposeField::Bcc => ui::ComposeField::Subject,
-                                        ui::ComposeField::Subject => ui::ComposeField::Body,
-                                        _ => ui::ComposeField::Body,
-                                    };
+                        _ => {
+                            // Let TextArea handle all other input (chars, backspace, arrows, Ctrl+arrows, etc.)
+                            if let Some(cs) = &mut ui_state.compose_state {
+                                if cs.focused_textarea().input(key) {
+                                    cs.error = None;
                                 }
+                                cs.update_completions(&ui_state.contacts);
                             }
                         }
-                    }
-                    _ => {
-                        // Let TextArea handle all other input (chars, backspace, arrows, Ctrl+arrows, etc.)
-                        if let Some(cs) = &mut ui_state.compose_state {
-                            cs.focused_textarea().input(key);
-                        }
-                    }
-                },
+                    },
+                }
             }
         }
+        Ok(())
     }
+    .await;
 
-    // Restore terminal
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        crossterm::terminal::LeaveAlternateScreen,
-        crossterm::event::DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+    if ui_state.pending.count() > 0 {
+        ui_state.notify(Level::Info, "Finishing changes before exiting...");
+        // The terminal may already be gone, as on SIGHUP
+        let _ = terminal.draw(|f| ui::render(f, &mut ui_state));
+    }
+    flush_before_exit(&ui_state, &db).await;
 
-    Ok(())
+    if let (Ok(()), Some(account)) = (&result, switch_to) {
+        drop(terminal_guard);
+        drop(log_guard);
+        return restart_as(&args, &account);
+    }
+    result
+}
+
+/// Run gtui again with the same arguments, signed in to `account`
+fn restart_as(args: &[String], account: &str) -> anyhow::Result<()> {
+    let mut rest = Vec::new();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if arg == "--account" {
+            iter.next();
+        } else {
+            rest.push(arg.clone());
+        }
+    }
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command.args(rest).args(["--account", account]);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        Err(anyhow::Error::new(command.exec()).context("Failed to restart gtui"))
+    }
+    #[cfg(not(unix))]
+    {
+        let status = command.status()?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
 }
@@ -0,0 +1,197 @@
+use crate::theme::Theme;
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+
+/// Parse a message body. Single newlines are kept as line breaks, since
+/// that is what someone typing an email expects, and the `--` signature
+/// separator would otherwise be folded into the line after it.
+fn parse(markdown: &str) -> impl Iterator<Item = Event<'_>> {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    Parser::new_ext(markdown, options).map(|event| match event {
+        Event::SoftBreak => Event::HardBreak,
+        other => other,
+    })
+}
+
+/// Render a Markdown body to a standalone HTML document for the `text/html`
+/// part of the message
+pub fn to_html(markdown: &str) -> String {
+    let mut body = String::new();
+    pulldown_cmark::html::push_html(&mut body, parse(markdown));
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"UTF-8\"></head>\n<body>\n{}</body>\n</html>\n",
+        body
+    )
+}
+
+/// Render a Markdown body as styled terminal text, for the compose preview
+pub fn to_preview(markdown: &str, theme: &Theme) -> Text<'static> {
+    let mut lines: Vec<Line<'static>> = Vec::new();
+    let mut current: Vec<Span<'static>> = Vec::new();
+    let mut styles = vec![Style::default()];
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut quote_depth = 0;
+    let mut link_urls: Vec<String> = Vec::new();
+
+    let style = |styles: &[Style]| *styles.last().unwrap_or(&Style::default());
+    let push_style = |styles: &mut Vec<Style>, patch: Style| {
+        let base = *styles.last().unwrap_or(&Style::default());
+        styles.push(base.patch(patch));
+    };
+
+    let flush = |current: &mut Vec<Span<'static>>, lines: &mut Vec<Line<'static>>| {
+        lines.push(Line::from(std::mem::take(current)));
+    };
+    let start_line = |current: &mut Vec<Span<'static>>, quote_depth: usize| {
+        if current.is_empty() && quote_depth > 0 {
+            current.push(Span::styled("│ ".repeat(quote_depth), theme.dim));
+        }
+    };
+
+    for event in parse(markdown) {
+        match event {
+            Event::Start(tag) => match tag {
+                Tag::Heading { level, .. } => {
+                    let mut heading = Style::default().add_modifier(Modifier::BOLD);
+                    if level == HeadingLevel::H1 {
+                        heading = heading.add_modifier(Modifier::UNDERLINED);
+                    }
+                    push_style(&mut styles, heading);
+                }
+                Tag::Emphasis => {
+                    push_style(&mut styles, Style::default().add_modifier(Modifier::ITALIC))
+                }
+                Tag::Strong => {
+                    push_style(&mut styles, Style::default().add_modifier(Modifier::BOLD))
+                }
+                Tag::Strikethrough => push_style(
+                    &mut styles,
+                    Style::default().add_modifier(Modifier::CROSSED_OUT),
+                ),
+                Tag::Link { dest_url, .. } => {
+                    link_urls.push(dest_url.to_string());
+                    push_style(&mut styles, theme.accent.add_modifier(Modifier::UNDERLINED));
+                }
+                Tag::CodeBlock(kind) => {
+                    if let CodeBlockKind::Fenced(lang) = kind
+                        && !lang.is_empty()
+                    {
+                        lines.push(Line::styled(format!("[{}]", lang), theme.dim));
+                    }
+                    push_style(&mut styles, theme.accent);
+                }
+                Tag::BlockQuote(_) => quote_depth += 1,
+                Tag::List(first) => lists.push(first),
+                Tag::Item => {
+                    start_line(&mut current, quote_depth);
+                    let indent = "  ".repeat(lists.len().saturating_sub(1));
+                    let bullet = match lists.last_mut() {
+                        Some(Some(n)) => {
+                            *n += 1;
+                            format!("{}{}. ", indent, *n - 1)
+                        }
+                        _ => format!("{}• ", indent),
+                    };
+                    current.push(Span::raw(bullet));
+                }
+                _ => {}
+            },
+            Event::End(tag) => match tag {
+                TagEnd::Heading(_) => {
+                    styles.pop();
+                    flush(&mut current, &mut lines);
+                    lines.push(Line::default());
+                }
+                TagEnd::Paragraph => {
+                    flush(&mut current, &mut lines);
+                    if lists.is_empty() {
+                        lines.push(Line::default());
+                    }
+                }
+                TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => {
+                    styles.pop();
+                }
+                TagEnd::Link => {
+                    styles.pop();
+                    if let Some(url) = link_urls.pop() {
+                        current.push(Span::styled(format!(" <{}>", url), theme.dim));
+                    }
+                }
+                TagEnd::CodeBlock => {
+                    styles.pop();
+                    lines.push(Line::default());
+                }
+                TagEnd::BlockQuote(_) => quote_depth = quote_depth.saturating_sub(1),
+                TagEnd::List(_) => {
+                    lists.pop();
+                    if lists.is_empty() {
+                        lines.push(Line::default());
+                    }
+                }
+                TagEnd::Item if !current.is_empty() => flush(&mut current, &mut lines),
+                _ => {}
+            },
+            Event::Text(text) => {
+                // Code blocks arrive as one text event spanning several lines
+                for (i, part) in text.split('\n').enumerate() {
+                    if i > 0 {
+                        flush(&mut current, &mut lines);
+                    }
+                    if !part.is_empty() {
+                        start_line(&mut current, quote_depth);
+                        current.push(Span::styled(part.to_string(), style(&styles)));
+                    }
+                }
+            }
+            Event::Code(code) => {
+                start_line(&mut current, quote_depth);
+                current.push(Span::styled(
+                    code.to_string(),
+                    style(&styles).patch(theme.accent),
+                ));
+            }
+            Event::HardBreak => flush(&mut current, &mut lines),
+            Event::Rule => {
+                lines.push(Line::styled("─".repeat(20), theme.dim));
+                lines.push(Line::default());
+            }
+            Event::TaskListMarker(done) => {
+                current.push(Span::raw(if done { "[x] " } else { "[ ] " }));
+            }
+            _ => {}
+        }
+    }
+    if !current.is_empty() {
+        flush(&mut current, &mut lines);
+    }
+    while lines.last().is_some_and(|l| l.spans.is_empty()) {
+        lines.pop();
+    }
+    Text::from(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_html_renders_markdown() {
+        let html = to_html("# Hello\n\nSome **bold** text");
+        assert!(html.contains("<h1>Hello</h1>"));
+        assert!(html.contains("<strong>bold</strong>"));
+    }
+
+    #[test]
+    fn test_to_html_keeps_line_breaks() {
+        let html = to_html("Thanks,\n\n--\nAlice");
+        assert!(html.contains("--<br />\nAlice"));
+    }
+
+    #[test]
+    fn test_preview_flattens_to_lines() {
+        let text = to_preview("Hi **Bob**\n\n- one\n- two", &Theme::default());
+        let lines: Vec<String> = text.lines.iter().map(|l| l.to_string()).collect();
+        assert_eq!(lines, vec!["Hi Bob", "", "• one", "• two"]);
+    }
+}
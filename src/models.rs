@@ -18,6 +18,8 @@ pub struct Message {
     pub snippet: Option<String>,
     pub from_address: Option<String>,
     pub to_address: Option<String>,
+    #[sqlx(default)]
+    pub cc_address: Option<String>,
     pub subject: Option<String>,
     pub internal_date: i64,
     pub body_plain: Option<String>,
@@ -26,4 +28,57 @@ pub struct Message {
     /// Whether this conversation contains a sent reply from the user
     #[sqlx(default)]
     pub has_sent_reply: bool,
+    /// Conversation totals; only populated when listing conversations
+    #[sqlx(default)]
+    pub message_count: i64,
+    #[sqlx(default)]
+    pub unread_count: i64,
+    /// Labels on any message of the conversation; only populated when
+    /// listing conversations
+    #[sqlx(skip)]
+    pub thread_label_ids: Vec<String>,
+}
+
+/// An address-book entry built from the addresses seen during sync
+#[derive(Debug, Clone, Default, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Contact {
+    pub email: String,
+    pub name: Option<String>,
+    /// Number of synced messages this address appeared on
+    pub times_seen: i64,
+    /// Number of messages we sent to this address
+    pub times_sent: i64,
+    pub last_seen: i64,
+    pub last_sent: i64,
+}
+
+/// A verified address the account can send mail as
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SendAs {
+    pub email: String,
+    pub display_name: Option<String>,
+    /// Plain-text version of the alias's Gmail signature
+    pub signature: Option<String>,
+    pub is_default: bool,
+}
+
+/// A message waiting in the local outbox, as typed in the compose fields,
+/// until Gmail accepts it
+#[derive(Debug, Clone, Default, PartialEq, Eq, sqlx::FromRow)]
+pub struct OutboxMessage {
+    /// 0 until the message is first queued
+    pub id: i64,
+    /// The send-as alias, as a `Name <addr>` header value
+    pub from_address: Option<String>,
+    pub to_address: String,
+    pub cc_address: String,
+    pub bcc_address: String,
+    pub subject: String,
+    pub body: String,
+    pub markdown: bool,
+    /// Why the last attempt to send it failed
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    /// Unfinished, saved on exit: reopened with `:outbox edit`, never retried
+    pub draft: bool,
 }
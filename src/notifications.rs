@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Most notifications shown at once; beyond that the oldest of the least
+/// severe are dropped early
+const MAX_VISIBLE: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    /// How long a notification stays up; problems linger so they get read
+    fn lifetime(self) -> Duration {
+        match self {
+            Level::Info => Duration::from_secs(4),
+            Level::Warn => Duration::from_secs(8),
+            Level::Error => Duration::from_secs(12),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub level: Level,
+    pub text: String,
+    expires: Instant,
+}
+
+/// Short messages shown over the bottom of the screen until they expire
+#[derive(Debug, Default)]
+pub struct Notifications {
+    items: VecDeque<Notification>,
+}
+
+impl Notifications {
+    pub fn push(&mut self, level: Level, text: String, now: Instant) {
+        // Repeating the newest message just keeps it up longer
+        self.items.retain(|n| !(n.level == level && n.text == text));
+        self.items.push_back(Notification {
+            level,
+            text,
+            expires: now + level.lifetime(),
+        });
+        self.prune(now);
+        while self.items.len() > MAX_VISIBLE {
+            let Some(lowest) = self.items.iter().map(|n| n.level).min() else {
+                break;
+            };
+            if let Some(pos) = self.items.iter().position(|n| n.level == lowest) {
+                self.items.remove(pos);
+            }
+        }
+    }
+
+    /// Unexpired notifications, oldest first
+    pub fn visible(&self, now: Instant) -> impl Iterator<Item = &Notification> {
+        self.items.iter().filter(move |n| n.expires > now)
+    }
+
+    /// Drop expired notifications
+    pub fn prune(&mut self, now: Instant) {
+        self.items.retain(|n| n.expires > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notifications_expire_by_level() {
+        let start = Instant::now();
+        let mut notifications = Notifications::default();
+        notifications.push(Level::Info, "Archived".to_string(), start);
+        notifications.push(Level::Error, "Send failed".to_string(), start);
+        assert_eq!(notifications.visible(start).count(), 2);
+
+        let later = start + Duration::from_secs(5);
+        let texts: Vec<_> = notifications.visible(later).map(|n| &n.text).collect();
+        assert_eq!(texts, ["Send failed"]);
+        notifications.prune(start + Duration::from_secs(13));
+        assert_eq!(notifications.visible(start).count(), 0);
+    }
+
+    #[test]
+    fn test_notifications_collapse_repeats_and_cap() {
+        let now = Instant::now();
+        let mut notifications = Notifications::default();
+        for text in ["a", "b", "a", "c", "d"] {
+            notifications.push(Level::Info, text.to_string(), now);
+        }
+        let texts: Vec<_> = notifications
+            .visible(now)
+            .map(|n| n.text.as_str())
+            .collect();
+        assert_eq!(texts, ["a", "c", "d"]);
+    }
+
+    #[test]
+    fn test_cap_drops_least_severe_first() {
+        let now = Instant::now();
+        let mut notifications = Notifications::default();
+        notifications.push(Level::Error, "Send failed".to_string(), now);
+        for text in ["a", "b", "c"] {
+            notifications.push(Level::Info, text.to_string(), now);
+        }
+        let texts: Vec<_> = notifications
+            .visible(now)
+            .map(|n| n.text.as_str())
+            .collect();
+        assert_eq!(texts, ["Send failed", "b", "c"]);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::address::{AddressError, Mailbox, parse_address_list, parse_address_list_strict};
+use crate::models::{OutboxMessage, SendAs};
+
+/// A validated message, ready to be turned into MIME by `GmailClient`
+#[derive(Debug, Clone)]
+pub struct OutgoingMessage {
+    /// Send-as alias; `None` lets Gmail use the account's default
+    pub from: Option<Mailbox>,
+    pub to: Vec<Mailbox>,
+    pub cc: Vec<Mailbox>,
+    pub bcc: Vec<Mailbox>,
+    pub subject: String,
+    pub body: String,
+    /// Send the body as Markdown, with a rendered `text/html` alternative
+    pub markdown: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ComposeError {
+    #[error("Add at least one recipient in To")]
+    EmptyTo,
+    #[error("{field}: {source}")]
+    InvalidAddress {
+        field: &'static str,
+        source: AddressError,
+    },
+    #[error("{0} must not contain line breaks")]
+    HeaderInjection(&'static str),
+}
+
+impl OutgoingMessage {
+    /// Validate the raw compose fields. Every header field must be a single
+    /// line, and every recipient must be a well-formed address.
+    pub fn new(
+        to: &str,
+        cc: &str,
+        bcc: &str,
+        subject: &str,
+        body: &str,
+    ) -> Result<Self, ComposeError> {
+        for (field, value) in [("To", to), ("Cc", cc), ("Bcc", bcc), ("Subject", subject)] {
+            if value.contains(['\r', '\n']) {
+                return Err(ComposeError::HeaderInjection(field));
+            }
+        }
+
+        let parse = |field: &'static str, value: &str| {
+            parse_address_list_strict(value)
+                .map_err(|source| ComposeError::InvalidAddress { field, source })
+        };
+        let to = parse("To", to)?;
+        if to.is_empty() {
+            return Err(ComposeError::EmptyTo);
+        }
+
+        Ok(Self {
+            from: None,
+            to,
+            cc: parse("Cc", cc)?,
+            bcc: parse("Bcc", bcc)?,
+            subject: subject.to_string(),
+            body: body.to_string(),
+            markdown: false,
+        })
+    }
+
+    /// Validate an outbox entry again before retrying it
+    pub fn from_outbox(msg: &OutboxMessage) -> Result<Self, ComposeError> {
+        let mut outgoing = Self::new(
+            &msg.to_address,
+            &msg.cc_address,
+            &msg.bcc_address,
+            &msg.subject,
+            &msg.body,
+        )?;
+        outgoing.from = msg
+            .from_address
+            .as_deref()
+            .and_then(|f| parse_address_list(f).into_iter().next());
+        outgoing.markdown = msg.markdown;
+        Ok(outgoing)
+    }
+}
+
+impl SendAs {
+    pub fn mailbox(&self) -> Mailbox {
+        Mailbox {
+            name: self.display_name.clone(),
+            email: self.email.clone(),
+        }
+    }
+}
+
+/// Pick the alias a reply should come from: the one the original was
+/// addressed to, else the account default, else the primary address
+pub fn pick_send_as(aliases: &[SendAs], original_recipients: &[&str]) -> usize {
+    let recipients: Vec<Mailbox> = original_recipients
+        .iter()
+        .flat_map(|r| parse_address_list(r))
+        .collect();
+    aliases
+        .iter()
+        .position(|a| {
+            recipients
+                .iter()
+                .any(|r| r.email.eq_ignore_ascii_case(&a.email))
+        })
+        .or_else(|| aliases.iter().position(|a| a.is_default))
+        .unwrap_or(0)
+}
+
+/// `body` as quoted text for a reply, each line prefixed with `> `
+pub fn quote_body(body: &str) -> String {
+    body.lines().map(|line| format!("> {}\n", line)).collect()
+}
+
+/// Swap the `--` signature block in `body` from `old` to `new`. When there was
+/// no signature, the new one goes after the user's own text: above a quoted
+/// reply or forwarded message if there is one, else at the end.
+pub fn replace_signature(body: &str, old: Option<&str>, new: Option<&str>) -> String {
+    if old == new {
+        return body.to_string();
+    }
+    match (old, new) {
+        (Some(old), new) if body.contains(&format!("--\n{}", old)) => {
+            let old_block = format!("--\n{}", old);
+            match new {
+                Some(new) => body.replacen(&old_block, &format!("--\n{}", new), 1),
+                None => body
+                    .replacen(&format!("{}\n", old_block), "", 1)
+                    .replacen(&old_block, "", 1),
+            }
+        }
+        (None, Some(new)) => match quote_start(body) {
+            Some(at) => format!(
+                "{}\n\n--\n{}\n\n{}",
+                body[..at].trim_end_matches('\n'),
+                new,
+                &body[at..]
+            ),
+            None => format!("{}\n\n--\n{}", body, new),
+        },
+        _ => body.to_string(),
+    }
+}
+
+/// Byte offset of the "On ... wrote:" or forwarded-message header that starts
+/// the quoted part of a reply or forward
+fn quote_start(body: &str) -> Option<usize> {
+    let mut offset = 0;
+    for line in body.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\r', '\n']);
+        if (text.starts_with("On ") && text.ends_with(" wrote:"))
+            || text == "---------- Forwarded message ----------"
+        {
+            return Some(offset);
+        }
+        offset += line.len();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requires_a_recipient() {
+        assert!(matches!(
+            OutgoingMessage::new(" , ", "", "", "Hi", ""),
+            Err(ComposeError::EmptyTo)
+        ));
+    }
+
+    #[test]
+    fn test_rejects_line_breaks_in_headers() {
+        assert!(matches!(
+            OutgoingMessage::new("a@x.com", "", "", "Hi\r\nBcc: evil@y.com", ""),
+            Err(ComposeError::HeaderInjection("Subject"))
+        ));
+        assert!(matches!(
+            OutgoingMessage::new("a@x.com\nBcc: evil@y.com", "", "", "Hi", ""),
+            Err(ComposeError::HeaderInjection("To"))
+        ));
+    }
+
+    #[test]
+    fn test_reports_the_field_with_a_bad_address() {
+        let err = OutgoingMessage::new("a@x.com", "b@", "", "Hi", "").unwrap_err();
+        assert_eq!(err.to_string(), "Cc: \"b@\" is not a valid email address");
+    }
+
+    fn alias(email: &str, is_default: bool) -> SendAs {
+        SendAs {
+            email: email.to_string(),
+            is_default,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pick_send_as_prefers_addressed_alias() {
+        let aliases = vec![alias("me@x.com", false), alias("work@y.com", true)];
+        assert_eq!(pick_send_as(&aliases, &["Me <ME@x.com>"]), 0);
+        assert_eq!(pick_send_as(&aliases, &["other@z.com"]), 1);
+        assert_eq!(pick_send_as(&[alias("me@x.com", false)], &[]), 0);
+    }
+
+    #[test]
+    fn test_replace_signature() {
+        let body = "\n\n--\nAlice\n\nOn Monday, Bob wrote:";
+        assert_eq!(
+            replace_signature(body, Some("Alice"), Some("Alice at Work")),
+            "\n\n--\nAlice at Work\n\nOn Monday, Bob wrote:"
+        );
+        assert_eq!(
+            replace_signature(body, Some("Alice"), None),
+            "\n\n\nOn Monday, Bob wrote:"
+        );
+        assert_eq!(
+            replace_signature("Thanks!", None, Some("Alice")),
+            "Thanks!\n\n--\nAlice"
+        );
+        assert_eq!(replace_signature("", None, Some("Alice")), "\n\n--\nAlice");
+    }
+
+    #[test]
+    fn test_new_signature_goes_above_the_quote() {
+        let reply = "Sounds good\n\n\nOn Monday, Bob wrote:\n> Lunch?\n";
+        assert_eq!(
+            replace_signature(reply, None, Some("Alice")),
+            "Sounds good\n\n--\nAlice\n\nOn Monday, Bob wrote:\n> Lunch?\n"
+        );
+        let forward = "\n\n\n---------- Forwarded message ----------\nFrom: Bob\n";
+        assert_eq!(
+            replace_signature(forward, None, Some("Alice")),
+            "\n\n--\nAlice\n\n---------- Forwarded message ----------\nFrom: Bob\n"
+        );
+    }
+
+    #[test]
+    fn test_body_may_span_lines() {
+        let msg = OutgoingMessage::new("a@x.com", "", "", "Hi", "one\ntwo").unwrap();
+        assert_eq!(msg.body, "one\ntwo");
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "gtui";
+const SETTINGS_FILE: &str = "settings.toml";
+const CREDENTIALS_FILE: &str = "credentials.json";
+const DATABASE_FILE: &str = "gtui.db";
+/// Under the data directory, one directory per named account
+const ACCOUNTS_DIR: &str = "accounts";
+/// The account name that means the unnamed, original account
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+/// Where settings and credentials are read from and where the cache lives.
+/// Each directory comes from a command-line flag, then an environment
+/// variable, then the XDG base directories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    /// `settings.toml` and `credentials.json`
+    pub config_dir: PathBuf,
+    /// The message cache and the log files
+    pub data_dir: PathBuf,
+    /// Named account from `--account`/`GTUI_ACCOUNT`; `None` is the default
+    /// account. Each named account has its own data directory and token.
+    pub account: Option<String>,
+}
+
+impl Paths {
+    pub fn from_env(args: &[String]) -> Result<Self> {
+        Self::resolve(args, |name| {
+            std::env::var_os(name)
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+        })
+    }
+
+    fn resolve(args: &[String], env: impl Fn(&str) -> Option<PathBuf>) -> Result<Self> {
+        let flag = |name: &str, what: &str| -> Result<Option<String>> {
+            let Some(pos) = args.iter().position(|a| a == name) else {
+                return Ok(None);
+            };
+            match args.get(pos + 1) {
+                Some(value) if !value.starts_with("--") => Ok(Some(value.clone())),
+                _ => bail!("{} needs {}", name, what),
+            }
+        };
+        let home = env("HOME");
+        let base = |xdg: &str, fallback: &[&str]| -> Result<PathBuf> {
+            if let Some(dir) = env(xdg) {
+                return Ok(dir.join(APP_DIR));
+            }
+            let Some(home) = &home else {
+                bail!("Neither ${} nor $HOME is set", xdg);
+            };
+            Ok(fallback
+                .iter()
+                .fold(home.clone(), |dir, part| dir.join(part))
+                .join(APP_DIR))
+        };
+
+        let dir_flag = |name: &str| -> Result<Option<PathBuf>> {
+            Ok(flag(name, "a directory")?.map(PathBuf::from))
+        };
+
+        let config_dir = match dir_flag("--config")?.or_else(|| env("GTUI_CONFIG_DIR")) {
+            Some(dir) => dir,
+            None => base("XDG_CONFIG_HOME", &[".config"])?,
+        };
+        let mut data_dir = match dir_flag("--data-dir")?.or_else(|| env("GTUI_DATA_DIR")) {
+            Some(dir) => dir,
+            None => base("XDG_DATA_HOME", &[".local", "share"])?,
+        };
+        let account = match flag("--account", "an account name")? {
+            Some(name) => Some(name),
+            None => env("GTUI_ACCOUNT").map(|v| v.to_string_lossy().into_owned()),
+        };
+        let account = match account {
+            Some(name) if name == DEFAULT_ACCOUNT => None,
+            Some(name) if !is_valid_account(&name) => bail!(
+                "Invalid account name {:?}: use letters, digits and . _ @ + -",
+                name
+            ),
+            other => other,
+        };
+        if let Some(name) = &account {
+            data_dir = data_dir.join(ACCOUNTS_DIR).join(name);
+        }
+        Ok(Self {
+            config_dir,
+            data_dir,
+            account,
+        })
+    }
+
+    pub fn settings(&self) -> PathBuf {
+        self.config_dir.join(SETTINGS_FILE)
+    }
+
+    pub fn credentials(&self) -> PathBuf {
+        self.config_dir.join(CREDENTIALS_FILE)
+    }
+
+    pub fn database(&self) -> PathBuf {
+        self.data_dir.join(DATABASE_FILE)
+    }
+
+    pub fn create(&self) -> Result<()> {
+        for dir in [&self.config_dir, &self.data_dir] {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Copy files an older version kept in `old_dir` (the working directory)
+    /// to their new homes, unless something is already there. The originals
+    /// are left in place. Returns the names of the files copied.
+    ///
+    /// Only a directory holding a gtui cache counts as one gtui ran in; a
+    /// `settings.toml` or `credentials.json` alone may belong to anything.
+    pub fn adopt_files_from(&self, old_dir: &Path) -> Result<Vec<&'static str>> {
+        let mut copied = Vec::new();
+        if !old_dir.join(DATABASE_FILE).is_file() {
+            return Ok(copied);
+        }
+        for (name, dir) in [
+            (SETTINGS_FILE, &self.config_dir),
+            (CREDENTIALS_FILE, &self.config_dir),
+            (DATABASE_FILE, &self.data_dir),
+        ] {
+            let from = old_dir.join(name);
+            let to = dir.join(name);
+            if !from.is_file() || to.exists() || same_file(&from, &to) {
+                continue;
+            }
+            std::fs::copy(&from, &to)
+                .with_context(|| format!("Failed to copy {} to {}", name, dir.display()))?;
+            // The database may have recent writes still in its write-ahead log
+            if name == DATABASE_FILE {
+                for suffix in ["-wal", "-shm"] {
+                    let side = old_dir.join(format!("{}{}", name, suffix));
+                    if side.is_file() {
+                        std::fs::copy(&side, dir.join(format!("{}{}", name, suffix)))?;
+                    }
+                }
+            }
+            copied.push(name);
+        }
+        Ok(copied)
+    }
+}
+
+/// Account names become directory names, so keep them to a safe alphabet
+pub fn is_valid_account(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._@+-".contains(c))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    matches!(
+        (a.canonicalize(), b.canonicalize()),
+        (Ok(a), Ok(b)) if a == b
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn resolve(args: &[&str], env: &[(&str, &str)]) -> Result<Paths> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let env: HashMap<&str, PathBuf> = env.iter().map(|(k, v)| (*k, PathBuf::from(v))).collect();
+        Paths::resolve(&args, |name| env.get(name).cloned())
+    }
+
+    #[test]
+    fn test_paths_follow_flags_then_env_then_xdg() {
+        let paths = resolve(&["gtui"], &[("HOME", "/home/ann")]).unwrap();
+        assert_eq!(paths.config_dir, PathBuf::from("/home/ann/.config/gtui"));
+        assert_eq!(
+            paths.database(),
+            PathBuf::from("/home/ann/.local/share/gtui/gtui.db")
+        );
+
+        let paths = resolve(
+            &["gtui"],
+            &[
+                ("HOME", "/home/ann"),
+                ("XDG_CONFIG_HOME", "/xdg/config"),
+                ("GTUI_DATA_DIR", "/srv/mail"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            paths.settings(),
+            PathBuf::from("/xdg/config/gtui/settings.toml")
+        );
+        assert_eq!(paths.data_dir, PathBuf::from("/srv/mail"));
+
+        let paths = resolve(
+            &["gtui", "--config", "conf", "--data-dir", "data"],
+            &[("GTUI_CONFIG_DIR", "/ignored")],
+        )
+        .unwrap();
+        assert_eq!(paths.credentials(), PathBuf::from("conf/credentials.json"));
+        assert_eq!(paths.data_dir, PathBuf::from("data"));
+
+        assert!(resolve(&["gtui", "--config"], &[("HOME", "/h")]).is_err());
+        assert!(resolve(&["gtui"], &[]).is_err());
+    }
+
+    #[test]
+    fn test_adopts_files_only_next_to_a_gtui_cache() {
+        let root = std::env::temp_dir().join(format!("gtui-test-adopt-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let old = root.join("old");
+        let paths = Paths {
+            config_dir: root.join("config"),
+            data_dir: root.join("data"),
+            account: None,
+        };
+        std::fs::create_dir_all(&old).unwrap();
+        paths.create().unwrap();
+        std::fs::write(old.join(SETTINGS_FILE), "").unwrap();
+        std::fs::write(old.join(CREDENTIALS_FILE), "{}").unwrap();
+        assert!(paths.adopt_files_from(&old).unwrap().is_empty());
+
+        std::fs::write(old.join(DATABASE_FILE), "").unwrap();
+        assert_eq!(
+            paths.adopt_files_from(&old).unwrap(),
+            vec![SETTINGS_FILE, CREDENTIALS_FILE, DATABASE_FILE]
+        );
+        assert!(paths.credentials().is_file());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_named_accounts_get_their_own_data_dir() {
+        let paths = resolve(&["gtui", "--account", "work"], &[("HOME", "/home/ann")]).unwrap();
+        assert_eq!(paths.account.as_deref(), Some("work"));
+        assert_eq!(
+            paths.database(),
+            PathBuf::from("/home/ann/.local/share/gtui/accounts/work/gtui.db")
+        );
+        assert_eq!(paths.config_dir, PathBuf::from("/home/ann/.config/gtui"));
+
+        let paths = resolve(
+            &["gtui", "--account", "default"],
+            &[("HOME", "/h"), ("GTUI_ACCOUNT", "work")],
+        )
+        .unwrap();
+        assert_eq!(paths.account, None);
+        assert_eq!(paths.data_dir, PathBuf::from("/h/.local/share/gtui"));
+
+        assert!(resolve(&["gtui", "--account", "../x"], &[("HOME", "/h")]).is_err());
+        assert!(resolve(&["gtui", "--account"], &[("HOME", "/h")]).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use google_gmail1::client::{Delegate, Retry};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Gmail's per-user limit, in quota units per second
+const UNITS_PER_SECOND: f64 = 250.0;
+/// Retries of a rate-limited or failed request before giving up
+const MAX_RETRIES: u32 = 5;
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(32);
+
+/// Quota cost of each Gmail method we call, from Google's usage limits table
+pub mod units {
+    pub const LABELS_LIST: u32 = 1;
+    pub const SEND_AS_LIST: u32 = 1;
+    pub const MESSAGES_LIST: u32 = 5;
+    pub const MESSAGES_GET: u32 = 5;
+    pub const MESSAGES_MODIFY: u32 = 5;
+    pub const MESSAGES_UNTRASH: u32 = 5;
+    pub const MESSAGES_BATCH_MODIFY: u32 = 50;
+    pub const MESSAGES_SEND: u32 = 100;
+}
+
+/// Units spent against the per-user limit. Requests reserve their cost up
+/// front and wait until the budget has refilled enough to cover it.
+#[derive(Debug)]
+struct Budget {
+    available: f64,
+    updated: Instant,
+}
+
+impl Budget {
+    fn new(now: Instant) -> Self {
+        Self {
+            available: UNITS_PER_SECOND,
+            updated: now,
+        }
+    }
+
+    /// Take `units` and return how long to wait before sending
+    fn reserve(&mut self, units: u32, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * UNITS_PER_SECOND).min(UNITS_PER_SECOND);
+        self.updated = now;
+        self.available -= units as f64;
+        if self.available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.available / UNITS_PER_SECOND)
+        }
+    }
+}
+
+/// Paces every request a `GmailClient` makes and remembers when a retry is
+/// due, so the status line can say so. Clones share the same budget.
+#[derive(Debug, Clone)]
+pub struct Quota {
+    budget: Arc<Mutex<Budget>>,
+    retrying_until: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Self {
+            budget: Arc::new(Mutex::new(Budget::new(Instant::now()))),
+            retrying_until: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+impl Quota {
+    /// Wait until a request costing `units` fits the per-user limit, then
+    /// return the delegate that retries it
+    pub async fn acquire(&self, units: u32) -> RetryDelegate {
+        self.acquire_with(units, true).await
+    }
+
+    /// Like `acquire`, for a request that must not run twice. A 5xx may come
+    /// after Gmail has acted on it, so only rate limiting is retried.
+    pub async fn acquire_once(&self, units: u32) -> RetryDelegate {
+        self.acquire_with(units, false).await
+    }
+
+    async fn acquire_with(&self, units: u32, idempotent: bool) -> RetryDelegate {
+        let wait = match self.budget.lock() {
+            Ok(mut budget) => budget.reserve(units, Instant::now()),
+            Err(_) => Duration::ZERO,
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        RetryDelegate {
+            attempt: 0,
+            units,
+            idempotent,
+            budget: self.budget.clone(),
+            retrying_until: self.retrying_until.clone(),
+        }
+    }
+
+    /// When the latest rate-limited request will be retried, if one is waiting
+    pub fn retrying_until(&self) -> Option<Instant> {
+        let until = (*self.retrying_until.lock().ok()?)?;
+        (until > Instant::now()).then_some(until)
+    }
+}
+
+/// Retries 429 responses and the 403 Gmail sends for exceeded rate limits,
+/// plus 500 and 503 for requests that are safe to repeat, with jittered
+/// exponential backoff. Each retry is charged against the budget again.
+pub struct RetryDelegate {
+    attempt: u32,
+    units: u32,
+    idempotent: bool,
+    budget: Arc<Mutex<Budget>>,
+    retrying_until: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Delegate for RetryDelegate {
+    fn http_failure(
+        &mut self,
+        response: &hyper::Response<hyper::body::Body>,
+        error: Option<serde_json::Value>,
+    ) -> Retry {
+        let status = response.status().as_u16();
+        let rate_limited = status == 403
+            && error.is_some_and(|e| {
+                let e = e.to_string();
+                e.contains("rateLimitExceeded") || e.contains("userRateLimitExceeded")
+            });
+        if !should_retry(status, rate_limited, self.idempotent) || self.attempt >= MAX_RETRIES {
+            return Retry::Abort;
+        }
+        let retry_after = response
+            .headers()
+            .get(hyper::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let backoff = backoff_delay(self.attempt, retry_after, jitter());
+        let paced = match self.budget.lock() {
+            Ok(mut budget) => budget.reserve(self.units, Instant::now()),
+            Err(_) => Duration::ZERO,
+        };
+        let delay = backoff.max(paced);
+        self.attempt += 1;
+        tracing::warn!(
+            status,
+            attempt = self.attempt,
+            "Gmail request failed, retrying in {:.1}s",
+            delay.as_secs_f64()
+        );
+        if let Ok(mut until) = self.retrying_until.lock() {
+            let at = Instant::now() + delay;
+            *until = Some(until.map_or(at, |u| u.max(at)));
+        }
+        Retry::After(delay)
+    }
+}
+
+/// Whether a failed request is worth another try. Server errors only are
+/// when repeating the request is harmless.
+fn should_retry(status: u16, rate_limited: bool, idempotent: bool) -> bool {
+    match status {
+        429 => true,
+        500 | 503 => idempotent,
+        _ => rate_limited,
+    }
+}
+
+/// Delay before retry number `attempt` (from 0): a random point in the upper
+/// half of an exponentially growing window, and never sooner than the
+/// server's `Retry-After`. `jitter` is in `0.0..1.0`.
+fn backoff_delay(attempt: u32, retry_after: Option<Duration>, jitter: f64) -> Duration {
+    let window = BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_DELAY);
+    let delay = window.mul_f64(0.5 + 0.5 * jitter);
+    retry_after.map_or(delay, |after| after.max(delay))
+}
+
+/// A random number in `0.0..1.0`, good enough to spread out retries
+fn jitter() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_waits_once_spent() {
+        let start = Instant::now();
+        let mut budget = Budget::new(start);
+        // 50 message fetches use up the first second's budget
+        for _ in 0..50 {
+            assert_eq!(budget.reserve(units::MESSAGES_GET, start), Duration::ZERO);
+        }
+        assert_eq!(
+            budget.reserve(units::MESSAGES_SEND, start),
+            Duration::from_millis(400)
+        );
+        // Refilled, but never beyond one second's worth
+        let later = start + Duration::from_secs(10);
+        assert_eq!(budget.reserve(250, later), Duration::ZERO);
+        assert!(budget.reserve(1, later) > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_send_is_not_retried_on_server_errors() {
+        assert!(should_retry(503, false, true));
+        assert!(!should_retry(503, false, false));
+        assert!(!should_retry(500, false, false));
+        assert!(should_retry(429, false, false));
+        assert!(should_retry(403, true, false));
+        assert!(!should_retry(403, false, true));
+        assert!(!should_retry(404, false, true));
+    }
+
+    #[test]
+    fn test_backoff_grows_and_honors_retry_after() {
+        assert_eq!(backoff_delay(0, None, 0.0), Duration::from_millis(500));
+        assert_eq!(backoff_delay(3, None, 0.0), Duration::from_secs(4));
+        assert_eq!(backoff_delay(3, None, 0.999).as_secs(), 7);
+        assert_eq!(backoff_delay(20, None, 0.0), Duration::from_secs(16));
+        assert_eq!(
+            backoff_delay(0, Some(Duration::from_secs(30)), 0.5),
+            Duration::from_secs(30)
+        );
+        let j = jitter();
+        assert!((0.0..1.0).contains(&j));
+    }
+}
@@ -0,0 +1,469 @@
+use crate::address::parse_address_list;
+use crate::config::ListConfig;
+use crate::models;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Datelike, Local};
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// The classic three-line row
+pub const DEFAULT_TEMPLATE: &str =
+    "From: {from}\nTime: {date} {count} {unread}\nSubj: {reply}{subject} {labels}";
+/// One line per conversation, with aligned date and sender columns
+pub const COMPACT_TEMPLATE: &str = "{date:9} {from:20} {reply}{subject} {labels} {count} {unread}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Date,
+    From,
+    Subject,
+    Labels,
+    Count,
+    Unread,
+    Reply,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(String),
+    Field { field: Field, spec: Option<String> },
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("unknown field {{{0}}}")]
+    UnknownField(String),
+    #[error("unclosed {{ in row template")]
+    Unclosed,
+    #[error("invalid date format \"{0}\"")]
+    DateFormat(String),
+}
+
+/// A parsed conversation row template such as
+/// `{date:%b %d} {from:20} {subject} {labels} {count}`.
+///
+/// A numeric spec (`{from:20}`) pads or truncates the field to that many
+/// columns; on `{date}` it also picks the short format of `short_date`, so
+/// a nine-column date fits whole. Any other spec on
+/// `{date}` is a strftime format. `{from}` and
+/// `{subject}` without a width share whatever room is left on the line.
+#[derive(Debug, Clone)]
+pub struct RowTemplate {
+    lines: Vec<Vec<Token>>,
+    relative_dates: bool,
+}
+
+impl Default for RowTemplate {
+    fn default() -> Self {
+        Self::parse(DEFAULT_TEMPLATE, true).expect("default template parses")
+    }
+}
+
+/// Everything a row needs besides the conversation itself
+pub struct RowContext<'a> {
+    pub now: DateTime<Local>,
+    pub labels: &'a [models::Label],
+    /// The label being browsed, left out of `{labels}`
+    pub current_label: Option<&'a str>,
+    pub label_style: Style,
+}
+
+impl RowTemplate {
+    pub fn from_config(config: &ListConfig) -> Result<Self, TemplateError> {
+        let template = match &config.template {
+            Some(template) => template.as_str(),
+            None if config.compact => COMPACT_TEMPLATE,
+            None => DEFAULT_TEMPLATE,
+        };
+        Self::parse(template, config.relative_dates)
+    }
+
+    pub fn parse(template: &str, relative_dates: bool) -> Result<Self, TemplateError> {
+        let lines = template
+            .lines()
+            .map(parse_line)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            lines,
+            relative_dates,
+        })
+    }
+
+    /// Number of terminal lines each conversation takes
+    pub fn height(&self) -> usize {
+        self.lines.len().max(1)
+    }
+
+    /// Render `msg` into lines exactly `width` columns wide
+    pub fn render(
+        &self,
+        msg: &models::Message,
+        ctx: &RowContext<'_>,
+        width: usize,
+    ) -> Vec<Line<'static>> {
+        self.lines
+            .iter()
+            .map(|tokens| self.render_line(tokens, msg, ctx, width))
+            .collect()
+    }
+
+    fn render_line(
+        &self,
+        tokens: &[Token],
+        msg: &models::Message,
+        ctx: &RowContext<'_>,
+        width: usize,
+    ) -> Line<'static> {
+        struct Piece {
+            text: String,
+            style: Style,
+            elastic: bool,
+        }
+
+        let mut pieces: Vec<Piece> = Vec::new();
+        let mut prev_empty = false;
+        for token in tokens {
+            match token {
+                Token::Literal(text) => {
+                    // An empty field should not leave a double space behind
+                    let ends_with_space = pieces.last().is_none_or(|p| p.text.ends_with(' '));
+                    let text = if prev_empty && ends_with_space {
+                        text.trim_start_matches(' ')
+                    } else {
+                        text
+                    };
+                    pieces.push(Piece {
+                        text: text.to_string(),
+                        style: Style::default(),
+                        elastic: false,
+                    });
+                }
+                Token::Field { field, spec } => {
+                    let width_spec = spec.as_deref().and_then(|s| s.parse::<usize>().ok());
+                    let text = self.field_value(*field, spec.as_deref(), msg, ctx);
+                    prev_empty = text.is_empty() && width_spec.is_none();
+                    let style = if *field == Field::Labels {
+                        ctx.label_style
+                    } else {
+                        Style::default()
+                    };
+                    let elastic =
+                        width_spec.is_none() && matches!(field, Field::From | Field::Subject);
+                    let text = match width_spec {
+                        Some(w) => fit(&text, w),
+                        None => text,
+                    };
+                    pieces.push(Piece {
+                        text,
+                        style,
+                        elastic,
+                    });
+                    continue;
+                }
+            }
+            prev_empty = false;
+        }
+
+        let fixed: usize = pieces
+            .iter()
+            .filter(|p| !p.elastic)
+            .map(|p| p.text.width())
+            .sum();
+        let elastic_count = pieces.iter().filter(|p| p.elastic).count();
+        let mut available = width.saturating_sub(fixed);
+        let mut seen = 0;
+        for piece in pieces.iter_mut().filter(|p| p.elastic) {
+            seen += 1;
+            if seen == elastic_count {
+                // The last elastic field fills the line, pushing what follows
+                // it to the right edge
+                piece.text = fit(&piece.text, available);
+            } else {
+                let share = available / (elastic_count - seen + 1);
+                piece.text = truncate(&piece.text, share);
+                available -= piece.text.width();
+            }
+        }
+
+        let mut spans = Vec::new();
+        let mut used = 0;
+        for piece in pieces {
+            let text = truncate(&piece.text, width - used);
+            used += text.width();
+            spans.push(Span::styled(text, piece.style));
+        }
+        if used < width {
+            spans.push(Span::raw(" ".repeat(width - used)));
+        }
+        Line::from(spans)
+    }
+
+    fn field_value(
+        &self,
+        field: Field,
+        spec: Option<&str>,
+        msg: &models::Message,
+        ctx: &RowContext<'_>,
+    ) -> String {
+        match field {
+            Field::Date => {
+                let date = DateTime::from_timestamp_millis(msg.internal_date)
+                    .unwrap_or_default()
+                    .with_timezone(&Local);
+                match spec {
+                    Some(format) if format.parse::<usize>().is_err() => {
+                        date.format(format).to_string()
+                    }
+                    Some(_) => short_date(date, ctx.now, self.relative_dates),
+                    None if self.relative_dates => relative_date(date, ctx.now),
+                    None => date.format("%b %d %Y @ %-I:%M%p").to_string(),
+                }
+            }
+            Field::From => {
+                let from = msg.from_address.as_deref().unwrap_or("Unknown");
+                parse_address_list(from)
+                    .into_iter()
+                    .next()
+                    .map(|m| m.name.unwrap_or(m.email))
+                    .unwrap_or_else(|| from.to_string())
+            }
+            Field::Subject => msg
+                .subject
+                .as_deref()
+                .filter(|s| !s.is_empty())
+                .unwrap_or("(No Subject)")
+                .to_string(),
+            Field::Labels => msg
+                .thread_label_ids
+                .iter()
+                .filter(|id| Some(id.as_str()) != ctx.current_label)
+                .filter_map(|id| {
+                    ctx.labels
+                        .iter()
+                        .find(|l| &l.id == id && l.label_type == "user")
+                })
+                .map(|l| format!("[{}]", l.display_name))
+                .collect::<Vec<_>>()
+                .join(" "),
+            Field::Count if msg.message_count > 1 => format!("({})", msg.message_count),
+            Field::Unread if msg.unread_count > 0 => format!("{} new", msg.unread_count),
+            Field::Reply if msg.has_sent_reply => "↩ ".to_string(),
+            Field::Count | Field::Unread | Field::Reply => String::new(),
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Result<Vec<Token>, TemplateError> {
+    let mut tokens = Vec::new();
+    let mut rest = line;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            tokens.push(Token::Literal(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find('}')
+            .map(|e| start + e)
+            .ok_or(TemplateError::Unclosed)?;
+        let inner = &rest[start + 1..end];
+        let (name, spec) = match inner.split_once(':') {
+            Some((name, spec)) => (name, Some(spec.to_string())),
+            None => (inner, None),
+        };
+        let field = match name.trim() {
+            "date" => Field::Date,
+            "from" => Field::From,
+            "subject" => Field::Subject,
+            "labels" => Field::Labels,
+            "count" => Field::Count,
+            "unread" => Field::Unread,
+            "reply" => Field::Reply,
+            other => return Err(TemplateError::UnknownField(other.to_string())),
+        };
+        // chrono only notices a bad strftime spec when formatting, by panicking
+        if let (Field::Date, Some(spec)) = (field, &spec)
+            && spec.parse::<usize>().is_err()
+            && StrftimeItems::new(spec).any(|item| item == Item::Error)
+        {
+            return Err(TemplateError::DateFormat(spec.clone()));
+        }
+        tokens.push(Token::Field { field, spec });
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Literal(rest.to_string()));
+    }
+    Ok(tokens)
+}
+
+/// "just now", "5m ago", "2h ago", "Yesterday", a weekday within the last
+/// week, then a plain date
+pub fn relative_date(date: DateTime<Local>, now: DateTime<Local>) -> String {
+    let age = now.signed_duration_since(date);
+    let days = (now.date_naive() - date.date_naive()).num_days();
+    if age.num_minutes() < 1 {
+        "just now".to_string()
+    } else if age.num_hours() < 1 {
+        format!("{}m ago", age.num_minutes())
+    } else if days == 0 {
+        format!("{}h ago", age.num_hours())
+    } else if days == 1 {
+        "Yesterday".to_string()
+    } else if days < 7 {
+        date.format("%A").to_string()
+    } else if date.year() == now.year() {
+        date.format("%b %d").to_string()
+    } else {
+        date.format("%b %d %Y").to_string()
+    }
+}
+
+/// A date for a fixed-width column, at most nine columns ("Wednesday",
+/// "Mar 04 23"): like `relative_date`, or the time of day for today's mail
+/// without relative dates, and a two-digit year for older years, so nothing
+/// is ever cut short
+pub fn short_date(date: DateTime<Local>, now: DateTime<Local>, relative: bool) -> String {
+    if date.year() != now.year() {
+        date.format("%b %d %y").to_string()
+    } else if relative {
+        relative_date(date, now)
+    } else if date.date_naive() == now.date_naive() {
+        date.format("%-I:%M%p").to_string()
+    } else {
+        date.format("%b %d").to_string()
+    }
+}
+
+/// Cut `s` to at most `width` terminal columns, marking the cut with `…`
+pub fn truncate(s: &str, width: usize) -> String {
+    if s.width() <= width {
+        return s.to_string();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    if width > 0 {
+        out.push('…');
+    }
+    out
+}
+
+/// Truncate or pad `s` to exactly `width` terminal columns
+pub fn fit(s: &str, width: usize) -> String {
+    let mut out = truncate(s, width);
+    let used = out.width();
+    out.push_str(&" ".repeat(width.saturating_sub(used)));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_fit_uses_display_width() {
+        assert_eq!(fit("日本語のメール", 7), "日本語…");
+        assert_eq!(fit("日本語のメール", 7).width(), 7);
+        assert_eq!(fit("hi 👋", 8), "hi 👋   ");
+        assert_eq!(fit("abc", 2), "a…");
+    }
+
+    #[test]
+    fn test_relative_date() {
+        let now = Local.with_ymd_and_hms(2024, 6, 14, 15, 0, 0).unwrap();
+        assert_eq!(relative_date(now - Duration::seconds(20), now), "just now");
+        assert_eq!(relative_date(now - Duration::minutes(5), now), "5m ago");
+        assert_eq!(relative_date(now - Duration::hours(2), now), "2h ago");
+        assert_eq!(relative_date(now - Duration::hours(20), now), "Yesterday");
+        assert_eq!(relative_date(now - Duration::days(3), now), "Tuesday");
+        assert_eq!(relative_date(now - Duration::days(30), now), "May 15");
+    }
+
+    #[test]
+    fn test_short_date_fits_its_column() {
+        let now = Local.with_ymd_and_hms(2024, 6, 12, 15, 0, 0).unwrap();
+        let dates = [
+            now - Duration::hours(3),
+            now - Duration::days(1),
+            now - Duration::days(7),
+            now - Duration::days(400),
+        ];
+        let shown: Vec<_> = dates.iter().map(|d| short_date(*d, now, true)).collect();
+        assert_eq!(shown, ["3h ago", "Yesterday", "Jun 05", "May 09 23"]);
+        let shown: Vec<_> = dates.iter().map(|d| short_date(*d, now, false)).collect();
+        assert_eq!(shown, ["12:00PM", "Jun 11", "Jun 05", "May 09 23"]);
+        assert_eq!(short_date(now - Duration::days(6), now, true), "Thursday");
+        assert!(shown.iter().all(|s| s.width() <= 9));
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_fields() {
+        assert_eq!(
+            RowTemplate::parse("{from} {size}", true).unwrap_err(),
+            TemplateError::UnknownField("size".to_string())
+        );
+        assert_eq!(
+            RowTemplate::parse("{from", true).unwrap_err(),
+            TemplateError::Unclosed
+        );
+        assert_eq!(
+            RowTemplate::parse("{date:%Q} {from}", true).unwrap_err(),
+            TemplateError::DateFormat("%Q".to_string())
+        );
+    }
+
+    #[test]
+    fn test_compact_row_keeps_counts_visible() {
+        let template = RowTemplate::parse("{date:%b %d} {from:6} {subject} {count}", true).unwrap();
+        let msg = models::Message {
+            from_address: Some("Jane Doe <jane@x.com>".to_string()),
+            subject: Some("A very long subject that will not fit".to_string()),
+            internal_date: Local
+                .with_ymd_and_hms(2024, 3, 4, 9, 0, 0)
+                .unwrap()
+                .timestamp_millis(),
+            message_count: 3,
+            ..Default::default()
+        };
+        let ctx = RowContext {
+            now: Local::now(),
+            labels: &[],
+            current_label: None,
+            label_style: Style::default(),
+        };
+        let lines = template.render(&msg, &ctx, 30);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].to_string(), "Mar 04 Jane … A very long… (3)");
+        assert_eq!(lines[0].width(), 30);
+    }
+
+    #[test]
+    fn test_compact_row_keeps_the_year_of_old_dates() {
+        let template = RowTemplate::parse(COMPACT_TEMPLATE, false).unwrap();
+        let msg = models::Message {
+            from_address: Some("Jane Doe <jane@x.com>".to_string()),
+            subject: Some("Lunch".to_string()),
+            internal_date: Local
+                .with_ymd_and_hms(2022, 3, 4, 9, 0, 0)
+                .unwrap()
+                .timestamp_millis(),
+            ..Default::default()
+        };
+        let ctx = RowContext {
+            now: Local.with_ymd_and_hms(2024, 6, 14, 15, 0, 0).unwrap(),
+            labels: &[],
+            current_label: None,
+            label_style: Style::default(),
+        };
+        let lines = template.render(&msg, &ctx, 40);
+        assert!(lines[0].to_string().starts_with("Mar 04 22 Jane Doe "));
+    }
+}
@@ -1,14 +1,58 @@
+use crate::config::SyncConfig;
+use crate::db::Database;
+use crate::gmail::GmailClient;
+use crate::models::{Label, Message};
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
 use std::collections::{HashMap, HashSet};
 use std::time::{Duration, Instant};
 
+/// Messages fetched per older page
+const OLDER_PAGE_SIZE: u32 = 100;
+/// Most ids Gmail returns from one list call
+const MAX_LIST_PAGE: u32 = 500;
+/// Message fetches in flight at once; each costs 5 of the 250 quota units
+/// Gmail allows per user per second
+const FETCH_CONCURRENCY: usize = 10;
+/// Messages fetched between progress updates and database writes
+pub const FETCH_CHUNK: usize = 50;
+
+/// How far sync has got through the messages it is fetching for a label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncProgress {
+    pub label: String,
+    pub fetched: usize,
+    pub total: usize,
+}
+
+/// Where fetching older mail for a label picks up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OlderPages {
+    /// Gmail's page token for the next older page
+    Next(String),
+    /// Everything in the label has been fetched
+    Done,
+}
+
 #[derive(Debug, Default)]
 pub struct SyncState {
     pub synced_labels: HashSet<String>,
     pub currently_syncing: Option<String>,
+    /// Set while new messages of `currently_syncing` are being fetched
+    pub progress: Option<SyncProgress>,
     /// Tracks messages that were recently modified locally (archived/deleted)
     /// Maps message_id -> timestamp when it was modified
     /// Sync should skip updating these messages for a grace period
     pub recently_modified: HashMap<String, Instant>,
+    /// Per label, where the next page beyond what sync keeps cached starts.
+    /// Sync seeds it from its first page; scrolling past the end advances it.
+    pub older_pages: HashMap<String, OlderPages>,
+    /// Labels with an older-page fetch in flight
+    pub fetching_older: HashSet<String>,
+    /// Conversations whose bodies were requested in body-on-demand mode
+    pub fetched_bodies: HashSet<String>,
+    /// Messages the last finished sync pass couldn't fetch
+    pub failed_fetches: usize,
 }
 
 impl SyncState {
@@ -42,3 +86,293 @@ impl SyncState {
             .retain(|_, &mut timestamp| now.duration_since(timestamp) < GRACE_PERIOD);
     }
 }
+
+/// Which labels sync fetches and how deep, from `[sync]`
+#[derive(Debug, Clone, Default)]
+pub struct SyncPolicy {
+    config: SyncConfig,
+}
+
+impl SyncPolicy {
+    pub fn new(config: SyncConfig) -> Self {
+        Self { config }
+    }
+
+    fn names(list: &[String], label: &Label) -> bool {
+        list.iter()
+            .any(|n| n.eq_ignore_ascii_case(&label.id) || n.eq_ignore_ascii_case(&label.name))
+    }
+
+    pub fn includes(&self, label: &Label) -> bool {
+        (self.config.include.is_empty() || Self::names(&self.config.include, label))
+            && !Self::names(&self.config.exclude, label)
+    }
+
+    pub fn depth(&self, label: &Label) -> u32 {
+        self.config
+            .depth
+            .iter()
+            .find(|(name, _)| Self::names(std::slice::from_ref(name), label))
+            .map_or(self.config.default_depth, |(_, depth)| *depth)
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.config.interval_secs.max(1))
+    }
+
+    pub fn body_on_demand(&self) -> bool {
+        self.config.body_on_demand
+    }
+
+    /// Labels to sync this pass, in order. The label being viewed goes
+    /// first and is synced even when excluded.
+    pub fn labels_to_sync(&self, labels: &[Label], priority: Option<&str>) -> Vec<Label> {
+        let mut selected: Vec<Label> = labels
+            .iter()
+            .filter(|l| self.includes(l) || Some(l.id.as_str()) == priority)
+            .cloned()
+            .collect();
+        if let Some(pos) = selected
+            .iter()
+            .position(|l| Some(l.id.as_str()) == priority)
+        {
+            let label = selected.remove(pos);
+            selected.insert(0, label);
+        }
+        selected
+    }
+}
+
+/// The newest `depth` message ids in a label, paging as needed, and the
+/// token for the page after them
+pub async fn list_label_ids(
+    client: &GmailClient,
+    label_id: &str,
+    depth: u32,
+) -> Result<(Vec<String>, Option<String>)> {
+    let mut ids = Vec::new();
+    let mut page_token = None;
+    loop {
+        let wanted = (depth as usize).saturating_sub(ids.len()) as u32;
+        let (mut page, next) = client
+            .list_messages(
+                vec![label_id.to_string()],
+                wanted.min(MAX_LIST_PAGE),
+                page_token,
+            )
+            .await?;
+        ids.append(&mut page);
+        page_token = next;
+        if page_token.is_none() || ids.len() >= depth as usize {
+            return Ok((ids, page_token));
+        }
+    }
+}
+
+/// Fetch messages with a few requests in flight at once, returning results in
+/// the order of `ids`
+pub async fn fetch_messages(
+    client: &GmailClient,
+    ids: &[String],
+    metadata_only: bool,
+) -> Vec<Result<Message>> {
+    let fetches: Vec<_> = ids
+        .iter()
+        .map(|id| fetch_message(client, id, metadata_only))
+        .collect();
+    stream::iter(fetches)
+        .buffered(FETCH_CONCURRENCY)
+        .collect()
+        .await
+}
+
+async fn fetch_message(client: &GmailClient, id: &str, metadata_only: bool) -> Result<Message> {
+    if metadata_only {
+        client.get_message_metadata(id).await
+    } else {
+        client.get_message(id).await
+    }
+}
+
+/// Fetch one page of older messages for `label_id` into the cache, starting
+/// at `page_token` (or the newest page without one). Returns how many
+/// messages were new and where the following page starts.
+pub async fn fetch_older_page(
+    client: &GmailClient,
+    db: &Database,
+    label_id: &str,
+    page_token: Option<String>,
+    metadata_only: bool,
+) -> Result<(usize, OlderPages)> {
+    let (ids, next_page_token) = client
+        .list_messages(vec![label_id.to_string()], OLDER_PAGE_SIZE, page_token)
+        .await?;
+
+    let mut missing = Vec::new();
+    for id in ids {
+        if !db.message_exists(&id).await? {
+            missing.push(id);
+        }
+    }
+    let messages = fetch_messages(client, &missing, metadata_only)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+    db.upsert_messages(&messages, label_id).await?;
+    db.record_contacts(&messages).await?;
+
+    let next = match next_page_token {
+        Some(token) => OlderPages::Next(token),
+        None => OlderPages::Done,
+    };
+    Ok((messages.len(), next))
+}
+
+/// The plain-text body of `message`, which may be a list row synced with
+/// headers only: from the row, else the cache, else fetched from Gmail into
+/// the cache and waited for. `None` when the message has no plain-text body
+/// or it couldn't be fetched.
+pub async fn load_body(
+    client: Option<&GmailClient>,
+    db: &Database,
+    message: &Message,
+) -> Option<String> {
+    if message.body_plain.is_some() {
+        return message.body_plain.clone();
+    }
+    let cached = |thread: Vec<Message>| {
+        thread
+            .into_iter()
+            .find(|m| m.id == message.id)
+            .filter(|m| m.body_plain.is_some() || m.body_html.is_some())
+    };
+    if let Some(cached) = db
+        .get_messages_by_thread(&message.thread_id)
+        .await
+        .ok()
+        .and_then(cached)
+    {
+        return cached.body_plain;
+    }
+    let client = client?;
+    if let Err(e) = fetch_bodies(client, db, std::slice::from_ref(&message.id)).await {
+        tracing::warn!("Fetching the body of {} failed: {:#}", message.id, e);
+        return None;
+    }
+    db.get_messages_by_thread(&message.thread_id)
+        .await
+        .ok()
+        .and_then(cached)
+        .and_then(|m| m.body_plain)
+}
+
+/// Fetch and store the bodies of messages synced with headers only
+pub async fn fetch_bodies(client: &GmailClient, db: &Database, ids: &[String]) -> Result<()> {
+    for id in ids {
+        db.update_message_body(&client.get_message(id).await?)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn label(id: &str, name: &str) -> Label {
+        Label {
+            id: id.to_string(),
+            name: name.to_string(),
+            label_type: "user".to_string(),
+            color_foreground: None,
+            color_background: None,
+            display_name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_policy_filters_and_orders_labels() {
+        let policy = SyncPolicy::new(SyncConfig {
+            exclude: vec!["spam".to_string(), "Newsletters".to_string()],
+            ..Default::default()
+        });
+        let labels = vec![
+            label("INBOX", "INBOX"),
+            label("SPAM", "SPAM"),
+            label("Label_1", "Newsletters"),
+            label("Label_2", "Work"),
+        ];
+        let ids = |labels: Vec<Label>| labels.into_iter().map(|l| l.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(policy.labels_to_sync(&labels, None)),
+            ["INBOX", "Label_2"]
+        );
+        // Viewing an excluded label still syncs it, first
+        assert_eq!(
+            ids(policy.labels_to_sync(&labels, Some("SPAM"))),
+            ["SPAM", "INBOX", "Label_2"]
+        );
+
+        let policy = SyncPolicy::new(SyncConfig {
+            include: vec!["INBOX".to_string(), "work".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(
+            ids(policy.labels_to_sync(&labels, None)),
+            ["INBOX", "Label_2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reply_to_metadata_only_message_quotes_full_body() {
+        let db = Database::open_in_memory().await;
+        db.upsert_labels(&[label("INBOX", "INBOX")]).await.unwrap();
+        let body = "Line one of a long message\n".repeat(20);
+        let full = Message {
+            id: "m1".to_string(),
+            thread_id: "t1".to_string(),
+            snippet: Some("Line one of a long".to_string()),
+            body_plain: Some(body.clone()),
+            ..Default::default()
+        };
+        db.upsert_messages(std::slice::from_ref(&full), "INBOX")
+            .await
+            .unwrap();
+        // The list row was loaded before the body was fetched
+        let row = Message {
+            body_plain: None,
+            ..full
+        };
+
+        let loaded = load_body(None, &db, &row).await.unwrap();
+        assert_eq!(loaded, body);
+        let quoted = crate::outgoing::quote_body(&loaded);
+        assert_eq!(quoted.lines().count(), 20);
+        assert!(quoted.starts_with("> Line one of a long message\n"));
+    }
+
+    #[tokio::test]
+    async fn test_load_body_without_cache_or_client_is_none() {
+        let db = Database::open_in_memory().await;
+        let row = Message {
+            id: "m1".to_string(),
+            thread_id: "t1".to_string(),
+            snippet: Some("Hi".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(load_body(None, &db, &row).await, None);
+    }
+
+    #[test]
+    fn test_policy_depth_by_id_or_name() {
+        let policy = SyncPolicy::new(SyncConfig {
+            default_depth: 200,
+            depth: HashMap::from([("INBOX".to_string(), 2000), ("work".to_string(), 50)]),
+            ..Default::default()
+        });
+        assert_eq!(policy.depth(&label("INBOX", "INBOX")), 2000);
+        assert_eq!(policy.depth(&label("Label_2", "Work")), 50);
+        assert_eq!(policy.depth(&label("Label_3", "Travel")), 200);
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::Result;
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Raw mode, the alternate screen and mouse capture for as long as it lives.
+/// Dropping it puts the terminal back, so an early `?` return can't leave
+/// the shell unusable.
+pub struct TerminalGuard;
+
+impl TerminalGuard {
+    pub fn enter(mouse_capture: bool) -> Result<Self> {
+        enable_raw_mode()?;
+        let guard = Self;
+        execute!(io::stdout(), crossterm::terminal::EnterAlternateScreen)?;
+        if mouse_capture {
+            execute!(io::stdout(), crossterm::event::EnableMouseCapture)?;
+        }
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Undo everything `TerminalGuard::enter` did, ignoring errors: this runs
+/// on the way out, when there is nothing better to do with them
+pub fn restore() {
+    let _ = disable_raw_mode();
+    let _ = execute!(
+        io::stdout(),
+        crossterm::terminal::LeaveAlternateScreen,
+        crossterm::event::DisableMouseCapture,
+        crossterm::cursor::Show
+    );
+}
+
+/// Restore the terminal before a panic message is printed, so it is
+/// readable. Panics in background tasks are caught by tokio and the UI keeps
+/// running, so those are only logged.
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        tracing::error!("{}", info);
+        if std::thread::current().name() == Some("main") {
+            restore();
+            previous(info);
+        }
+    }));
+}
+
+/// Stop the process as the shell's Ctrl-Z would. Call with the terminal
+/// suspended; returns once the job is brought back with `fg`.
+#[cfg(unix)]
+pub fn stop_process() -> Result<()> {
+    // SAFETY: raise only sends a signal to this process
+    if unsafe { libc::raise(libc::SIGTSTP) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn stop_process() -> Result<()> {
+    anyhow::bail!("Suspending is not supported on this platform")
+}
+
+/// Termination requests the main loop should exit cleanly on
+pub struct ExitSignals {
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    hangup: tokio::signal::unix::Signal,
+}
+
+impl ExitSignals {
+    pub fn new() -> Result<Self> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{SignalKind, signal};
+            Ok(Self {
+                terminate: signal(SignalKind::terminate())?,
+                hangup: signal(SignalKind::hangup())?,
+            })
+        }
+        #[cfg(not(unix))]
+        Ok(Self {})
+    }
+
+    /// The name of the next signal received
+    pub async fn recv(&mut self) -> &'static str {
+        #[cfg(unix)]
+        tokio::select! {
+            _ = self.terminate.recv() => "SIGTERM",
+            _ = self.hangup.recv() => "SIGHUP",
+        }
+        #[cfg(not(unix))]
+        std::future::pending().await
+    }
+}
+
+/// Counts Gmail changes still being sent in the background, so exiting can
+/// wait for them instead of dropping them
+#[derive(Debug, Clone, Default)]
+pub struct PendingWork(Arc<AtomicUsize>);
+
+/// One unit of `PendingWork`, finished when dropped
+pub struct PendingGuard(Arc<AtomicUsize>);
+
+impl PendingWork {
+    /// Call before spawning the task and move the guard into it
+    pub fn start(&self) -> PendingGuard {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        PendingGuard(self.0.clone())
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Wait until nothing is pending or `timeout` passes; returns how many
+    /// are still pending
+    pub async fn wait(&self, timeout: Duration) -> usize {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.count() > 0 && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        self.count()
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pending_work_waits_for_tasks() {
+        let pending = PendingWork::default();
+        let guard = pending.start();
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(guard);
+        });
+        assert_eq!(pending.count(), 1);
+        assert_eq!(pending.wait(Duration::from_secs(5)).await, 0);
+        task.await.unwrap();
+
+        let _stuck = pending.start();
+        assert_eq!(pending.wait(Duration::from_millis(10)).await, 1);
+    }
+}
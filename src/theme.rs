@@ -0,0 +1,220 @@
+use crate::config::{ThemeConfig, ThemePreset};
+use ratatui::style::{Color, Modifier, Style};
+use std::str::FromStr;
+
+/// Resolved styles for every themed element of the UI
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub border: Style,
+    pub border_focused: Style,
+    pub selection: Style,
+    pub unread: Style,
+    pub read: Style,
+    pub status: Style,
+    /// Separators, placeholders and other de-emphasised text
+    pub dim: Style,
+    /// Links, inline code and other highlighted text
+    pub accent: Style,
+    pub compose_border: Style,
+    pub compose_focused: Style,
+    pub label_chip: Style,
+    pub error: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            border: Style::default().fg(Color::Gray),
+            border_focused: Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::BOLD),
+            selection: Style::default().fg(Color::Blue),
+            unread: Style::default().add_modifier(Modifier::BOLD),
+            read: Style::default(),
+            status: Style::default().fg(Color::Blue),
+            dim: Style::default().fg(Color::DarkGray),
+            accent: Style::default().fg(Color::Yellow),
+            compose_border: Style::default().fg(Color::Gray),
+            compose_focused: Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+            label_chip: Style::default().fg(Color::Cyan),
+            error: Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        }
+    }
+
+    /// Dark text for light backgrounds; avoids the pale cyan, yellow and
+    /// gray that wash out on white
+    pub fn light() -> Self {
+        Self {
+            border: Style::default().fg(Color::DarkGray),
+            border_focused: Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::BOLD),
+            selection: Style::default().fg(Color::Blue),
+            unread: Style::default()
+                .fg(Color::Black)
+                .add_modifier(Modifier::BOLD),
+            read: Style::default().fg(Color::DarkGray),
+            status: Style::default().fg(Color::Blue),
+            dim: Style::default().fg(Color::DarkGray),
+            accent: Style::default().fg(Color::Magenta),
+            compose_border: Style::default().fg(Color::DarkGray),
+            compose_focused: Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::BOLD),
+            label_chip: Style::default().fg(Color::Magenta),
+            error: Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            border: Style::default().fg(Color::White),
+            border_focused: Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+            selection: Style::default()
+                .fg(Color::Black)
+                .bg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+            unread: Style::default()
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+            read: Style::default().fg(Color::Gray),
+            status: Style::default().fg(Color::LightCyan),
+            dim: Style::default().fg(Color::Gray),
+            accent: Style::default().fg(Color::LightYellow),
+            compose_border: Style::default().fg(Color::White),
+            compose_focused: Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+            label_chip: Style::default().fg(Color::LightCyan),
+            error: Style::default()
+                .fg(Color::LightRed)
+                .add_modifier(Modifier::BOLD),
+        }
+    }
+
+    /// Build the theme from `[theme]`, along with the overrides that were
+    /// ignored because they aren't colors. `NO_COLOR` (https://no-color.org)
+    /// wins over both the preset and any overrides.
+    pub fn from_config(config: &ThemeConfig) -> (Self, Vec<String>) {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        Self::resolve(config, no_color)
+    }
+
+    fn resolve(config: &ThemeConfig, no_color: bool) -> (Self, Vec<String>) {
+        let mut theme = match config.preset {
+            ThemePreset::Dark => Self::dark(),
+            ThemePreset::Light => Self::light(),
+            ThemePreset::HighContrast => Self::high_contrast(),
+        };
+
+        let overrides = [
+            ("border", &config.border, &mut theme.border),
+            (
+                "border_focused",
+                &config.border_focused,
+                &mut theme.border_focused,
+            ),
+            ("selection", &config.selection, &mut theme.selection),
+            ("unread", &config.unread, &mut theme.unread),
+            ("read", &config.read, &mut theme.read),
+            ("status", &config.status, &mut theme.status),
+            ("dim", &config.dim, &mut theme.dim),
+            ("accent", &config.accent, &mut theme.accent),
+            (
+                "compose_border",
+                &config.compose_border,
+                &mut theme.compose_border,
+            ),
+            (
+                "compose_focused",
+                &config.compose_focused,
+                &mut theme.compose_focused,
+            ),
+            ("label_chip", &config.label_chip, &mut theme.label_chip),
+            ("error", &config.error, &mut theme.error),
+        ];
+        let mut invalid = Vec::new();
+        for (name, value, style) in overrides {
+            let Some(value) = value.as_deref() else {
+                continue;
+            };
+            // Unknown color names keep the preset's color
+            match Color::from_str(value) {
+                Ok(color) => *style = style.fg(color),
+                Err(_) => invalid.push(format!("{} = \"{}\"", name, value)),
+            }
+        }
+
+        if no_color {
+            return (theme.monochrome(), invalid);
+        }
+        (theme, invalid)
+    }
+
+    /// Drop every color, leaving only modifiers. Selection and focus are
+    /// carried by reverse video and bold instead.
+    fn monochrome(self) -> Self {
+        let strip = |style: Style| Style {
+            fg: None,
+            bg: None,
+            ..style
+        };
+        Self {
+            border: strip(self.border),
+            border_focused: strip(self.border_focused).add_modifier(Modifier::BOLD),
+            selection: strip(self.selection).add_modifier(Modifier::REVERSED),
+            unread: strip(self.unread),
+            read: strip(self.read),
+            status: strip(self.status),
+            dim: strip(self.dim),
+            accent: strip(self.accent).add_modifier(Modifier::UNDERLINED),
+            compose_border: strip(self.compose_border),
+            compose_focused: strip(self.compose_focused).add_modifier(Modifier::BOLD),
+            label_chip: strip(self.label_chip),
+            error: strip(self.error).add_modifier(Modifier::BOLD),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_replace_preset_color() {
+        let config = ThemeConfig {
+            preset: ThemePreset::Light,
+            selection: Some("#ff8800".to_string()),
+            unread: Some("not-a-color".to_string()),
+            ..Default::default()
+        };
+        let (theme, invalid) = Theme::resolve(&config, false);
+        assert_eq!(theme.selection.fg, Some(Color::Rgb(0xff, 0x88, 0x00)));
+        assert_eq!(theme.unread.fg, Theme::light().unread.fg);
+        assert_eq!(invalid, ["unread = \"not-a-color\""]);
+        assert!(theme.unread.add_modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn test_no_color_strips_colors() {
+        let config = ThemeConfig {
+            preset: ThemePreset::HighContrast,
+            border: Some("red".to_string()),
+            ..Default::default()
+        };
+        let (theme, _) = Theme::resolve(&config, true);
+        assert_eq!(theme.border.fg, None);
+        assert_eq!(theme.selection.bg, None);
+        assert!(theme.selection.add_modifier.contains(Modifier::REVERSED));
+    }
+}
@@ -1,29 +1,31 @@
+use crate::address::{completion_token, parse_address_list};
+use crate::commands::{self, Action, CommandSpec};
+use crate::config::Keybindings;
+use crate::contacts;
+use crate::editor::Draft;
+use crate::layout::{LayoutState, PanelAreas};
+use crate::logging::LogBuffer;
+use crate::markdown;
 use crate::models;
+use crate::notifications::{Level, Notifications};
+use crate::outgoing::{ComposeError, OutgoingMessage, replace_signature};
+use crate::row_template::{RowContext, RowTemplate};
 use crate::sync::SyncState;
+use crate::terminal::PendingWork;
+use crate::theme::Theme;
 use crate::undo::UndoableAction;
 use chrono::{DateTime, Local};
+use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::{
     Frame,
     layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier, Style},
-    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
 };
+use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
-use tui_textarea::TextArea;
-
-/// Write to debug log file if debug mode is enabled
-fn debug_log(enabled: bool, msg: &str) {
-    if enabled {
-        if let Ok(mut file) = std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open("gtui_debug.log")
-        {
-            use std::io::Write;
-            let _ = writeln!(file, "{}", msg);
-        }
-    }
-}
+use tui_textarea::{CursorMove, TextArea};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
 pub enum FocusedPanel {
@@ -57,35 +59,75 @@ pub struct ComposeState<'a> {
     pub body: TextArea<'a>,
     pub focused_field: ComposeField,
     pub show_cc_bcc: bool,
+    /// Address book matches for the address being typed in To/Cc/Bcc
+    pub completions: Vec<models::Contact>,
+    pub completion_index: usize,
+    /// Why the last send attempt was refused, shown inline until the next edit
+    pub error: Option<String>,
+    /// Verified send-as aliases and the one currently selected for From
+    pub send_as: Vec<models::SendAs>,
+    pub from_index: usize,
+    /// Signature from settings.toml, used when the alias has none
+    pub fallback_signature: Option<String>,
+    /// Send the body as Markdown with an HTML alternative
+    pub markdown: bool,
+    /// Show the rendered Markdown beside the body
+    pub show_preview: bool,
+    /// The outbox entry this message was reopened from, replaced on send
+    pub outbox_id: Option<i64>,
 }
 
 impl<'a> ComposeState<'a> {
     pub fn new(to: &str, cc: &str, bcc: &str, subject: &str, body: &str) -> Self {
-        let mut to_textarea = TextArea::from(to.lines());
-        let mut cc_textarea = TextArea::from(cc.lines());
-        let mut bcc_textarea = TextArea::from(bcc.lines());
-        let mut subject_textarea = TextArea::from(subject.lines());
-        let mut body_textarea = TextArea::from(body.lines());
-
-        // Disable cursor line highlighting for cleaner look
-        let no_highlight = Style::default();
-        to_textarea.set_cursor_line_style(no_highlight);
-        cc_textarea.set_cursor_line_style(no_highlight);
-        bcc_textarea.set_cursor_line_style(no_highlight);
-        subject_textarea.set_cursor_line_style(no_highlight);
-        body_textarea.set_cursor_line_style(no_highlight);
-
         Self {
-            to: to_textarea,
-            cc: cc_textarea,
-            bcc: bcc_textarea,
-            subject: subject_textarea,
-            body: body_textarea,
+            to: new_textarea(to.lines()),
+            cc: new_textarea(cc.lines()),
+            bcc: new_textarea(bcc.lines()),
+            subject: new_textarea(subject.lines()),
+            body: new_textarea(body.lines()),
             focused_field: ComposeField::To,
             show_cc_bcc: false,
+            completions: Vec::new(),
+            completion_index: 0,
+            error: None,
+            send_as: Vec::new(),
+            from_index: 0,
+            fallback_signature: None,
+            markdown: false,
+            show_preview: false,
+            outbox_id: None,
         }
     }
 
+    /// Reopen an unsent message from the outbox, selecting the alias it was
+    /// going to be sent from
+    pub fn from_outbox(
+        msg: &models::OutboxMessage,
+        send_as: Vec<models::SendAs>,
+        fallback_signature: Option<String>,
+    ) -> Self {
+        let from = msg
+            .from_address
+            .as_deref()
+            .and_then(|f| parse_address_list(f).into_iter().next());
+        let from_index = from
+            .and_then(|from| send_as.iter().position(|a| a.email == from.email))
+            .unwrap_or(0);
+        let mut state = Self::new(
+            &msg.to_address,
+            &msg.cc_address,
+            &msg.bcc_address,
+            &msg.subject,
+            &msg.body,
+        )
+        .with_send_as(send_as, from_index, fallback_signature);
+        state.show_cc_bcc = !msg.cc_address.is_empty() || !msg.bcc_address.is_empty();
+        state.markdown = msg.markdown;
+        state.outbox_id = Some(msg.id);
+        state.error = msg.last_error.clone();
+        state
+    }
+
     /// Get the current text content of a field
     pub fn get_to(&self) -> String {
         self.to.lines().join("\n")
@@ -107,6 +149,198 @@ impl<'a> ComposeState<'a> {
         self.body.lines().join("\n")
     }
 
+    /// Validate the fields into a message that can be sent
+    pub fn to_outgoing(&self) -> Result<OutgoingMessage, ComposeError> {
+        let mut outgoing = OutgoingMessage::new(
+            &self.get_to(),
+            &self.get_cc(),
+            &self.get_bcc(),
+            &self.get_subject(),
+            &self.get_body(),
+        )?;
+        outgoing.from = self.from().map(|a| a.mailbox());
+        outgoing.markdown = self.markdown;
+        Ok(outgoing)
+    }
+
+    /// The fields as an outbox entry, queued before the message is sent
+    pub fn to_outbox(&self) -> models::OutboxMessage {
+        models::OutboxMessage {
+            id: self.outbox_id.unwrap_or(0),
+            from_address: self.from().map(|a| a.mailbox().to_display_string()),
+            to_address: self.get_to(),
+            cc_address: self.get_cc(),
+            bcc_address: self.get_bcc(),
+            subject: self.get_subject(),
+            body: self.get_body(),
+            markdown: self.markdown,
+            last_error: None,
+            created_at: Local::now().timestamp_millis(),
+            draft: false,
+        }
+    }
+
+    /// Attach the send-as aliases, selecting `from_index`
+    pub fn with_send_as(
+        mut self,
+        send_as: Vec<models::SendAs>,
+        from_index: usize,
+        fallback_signature: Option<String>,
+    ) -> Self {
+        self.send_as = send_as;
+        self.from_index = from_index;
+        self.fallback_signature = fallback_signature;
+        self
+    }
+
+    pub fn toggle_markdown(&mut self) {
+        self.markdown = !self.markdown;
+        if !self.markdown {
+            self.show_preview = false;
+        }
+    }
+
+    /// The preview only makes sense for Markdown, so showing it turns it on
+    pub fn toggle_preview(&mut self) {
+        self.show_preview = !self.show_preview;
+        if self.show_preview {
+            self.markdown = true;
+        }
+    }
+
+    pub fn from(&self) -> Option<&models::SendAs> {
+        self.send_as.get(self.from_index)
+    }
+
+    /// The signature for the selected alias
+    pub fn signature(&self) -> Option<&str> {
+        self.from()
+            .and_then(|a| a.signature.as_deref())
+            .or(self.fallback_signature.as_deref())
+    }
+
+    /// Select the next alias and swap its signature into the body
+    pub fn cycle_from(&mut self) {
+        if self.send_as.len() < 2 {
+            return;
+        }
+        let old_signature = self.signature().map(str::to_string);
+        self.from_index = (self.from_index + 1) % self.send_as.len();
+
+        let body = replace_signature(&self.get_body(), old_signature.as_deref(), self.signature());
+        let (row, col) = self.body.cursor();
+        self.body = new_textarea(body.lines());
+        self.body
+            .move_cursor(CursorMove::Jump(row as u16, col as u16));
+    }
+
+    /// Snapshot the fields for editing outside the TUI
+    pub fn to_draft(&self) -> Draft {
+        Draft {
+            to: self.get_to(),
+            cc: self.get_cc(),
+            bcc: self.get_bcc(),
+            subject: self.get_subject(),
+            body: self.get_body(),
+        }
+    }
+
+    /// Replace the fields with a draft edited outside the TUI
+    pub fn apply_draft(&mut self, draft: &Draft) {
+        let edited = Self::new(
+            &draft.to,
+            &draft.cc,
+            &draft.bcc,
+            &draft.subject,
+            &draft.body,
+        );
+        self.to = edited.to;
+        self.cc = edited.cc;
+        self.bcc = edited.bcc;
+        self.subject = edited.subject;
+        self.body = edited.body;
+        self.error = None;
+        if !draft.cc.is_empty() || !draft.bcc.is_empty() {
+            self.show_cc_bcc = true;
+        }
+    }
+
+    /// Whether the focused field holds a list of addresses
+    pub fn is_address_field(&self) -> bool {
+        matches!(
+            self.focused_field,
+            ComposeField::To | ComposeField::Cc | ComposeField::Bcc
+        )
+    }
+
+    /// Recompute address book matches for the address under the cursor
+    pub fn update_completions(&mut self, contacts: &[models::Contact]) {
+        self.completion_index = 0;
+        if !self.is_address_field() {
+            self.completions.clear();
+            return;
+        }
+
+        let textarea = self.focused_textarea();
+        let (row, col) = textarea.cursor();
+        let line = textarea.lines().get(row).cloned().unwrap_or_default();
+        let entered = parse_address_list(&textarea.lines().join(","));
+        let before: String = line.chars().take(col).collect();
+        let (_, token) = completion_token(&before);
+        let now = chrono::Utc::now().timestamp_millis();
+
+        self.completions = contacts::complete(contacts, token, &entered, now)
+            .into_iter()
+            .cloned()
+            .collect();
+    }
+
+    pub fn select_next_completion(&mut self) {
+        if !self.completions.is_empty() {
+            self.completion_index = (self.completion_index + 1) % self.completions.len();
+        }
+    }
+
+    pub fn select_prev_completion(&mut self) {
+        if !self.completions.is_empty() {
+            self.completion_index =
+                (self.completion_index + self.completions.len() - 1) % self.completions.len();
+        }
+    }
+
+    /// Replace the address under the cursor with the selected completion,
+    /// followed by a separator ready for the next address
+    pub fn accept_completion(&mut self) {
+        let Some(contact) = self.completions.get(self.completion_index).cloned() else {
+            return;
+        };
+        self.completions.clear();
+        self.completion_index = 0;
+
+        let textarea = self.focused_textarea();
+        let (row, col) = textarea.cursor();
+        let mut lines = textarea.lines().to_vec();
+        let Some(line) = lines.get(row) else {
+            return;
+        };
+        let before: String = line.chars().take(col).collect();
+        let after: String = line.chars().skip(col).collect();
+        let (start, _) = completion_token(&before);
+
+        let mut new_before = before[..start].to_string();
+        if !new_before.is_empty() && !new_before.ends_with(' ') {
+            new_before.push(' ');
+        }
+        new_before.push_str(&contact.mailbox().to_display_string());
+        new_before.push_str(", ");
+        let new_col = new_before.chars().count();
+        lines[row] = format!("{}{}", new_before, after.trim_start_matches([',', ' ']));
+
+        let mut replaced = new_textarea(lines);
+        replaced.move_cursor(CursorMove::Jump(row as u16, new_col as u16));
+        *self.focused_textarea() = replaced;
+    }
+
     /// Get mutable reference to the currently focused textarea
     pub fn focused_textarea(&mut self) -> &mut TextArea<'a> {
         match self.focused_field {
@@ -119,6 +353,84 @@ impl<'a> ComposeState<'a> {
     }
 }
 
+/// The `:` command line and the fuzzy list of commands under it
+#[derive(Debug, Default)]
+pub struct PaletteState {
+    pub input: String,
+    pub selected: usize,
+    /// Why the last command line was rejected
+    pub error: Option<String>,
+}
+
+pub enum PaletteOutcome {
+    /// Still typing
+    Pending,
+    Close,
+    Run(Action),
+}
+
+impl PaletteState {
+    /// Commands matching the name typed so far
+    pub fn matches(&self) -> Vec<&'static CommandSpec> {
+        let name = self.input.split_whitespace().next().unwrap_or("");
+        commands::search(name)
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> PaletteOutcome {
+        match key.code {
+            KeyCode::Esc => return PaletteOutcome::Close,
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down if self.selected + 1 < self.matches().len() => self.selected += 1,
+            KeyCode::Tab => self.complete(),
+            KeyCode::Backspace => {
+                if self.input.pop().is_none() {
+                    return PaletteOutcome::Close;
+                }
+                self.selected = 0;
+            }
+            KeyCode::Enter => return self.submit(),
+            KeyCode::Char(c) => {
+                self.input.push(c);
+                self.selected = 0;
+            }
+            _ => {}
+        }
+        self.error = None;
+        PaletteOutcome::Pending
+    }
+
+    /// Replace the typed name with the highlighted command
+    fn complete(&mut self) {
+        if let Some(spec) = self.matches().get(self.selected) {
+            self.input = format!("{} ", spec.name);
+            self.selected = 0;
+        }
+    }
+
+    fn submit(&mut self) -> PaletteOutcome {
+        // Without arguments, Enter runs the highlighted entry; commands that
+        // need arguments get their name filled in instead
+        if !self.input.trim().contains(char::is_whitespace)
+            && let Some(spec) = self.matches().get(self.selected)
+        {
+            if spec.takes_args() && !self.input.ends_with(' ') {
+                self.complete();
+                return PaletteOutcome::Pending;
+            }
+            if !spec.takes_args() {
+                self.input = spec.name.to_string();
+            }
+        }
+        match commands::parse(&self.input) {
+            Ok(action) => PaletteOutcome::Run(action),
+            Err(e) => {
+                self.error = Some(e);
+                PaletteOutcome::Pending
+            }
+        }
+    }
+}
+
 pub struct UIState<'a> {
     pub labels: Vec<models::Label>,
     pub messages: Vec<models::Message>,
@@ -131,11 +443,47 @@ pub struct UIState<'a> {
     pub mode: UIMode,
     pub compose_state: Option<ComposeState<'a>>,
     pub auth_url: Option<String>,
-    pub remote_signature: Option<String>,
+    /// Verified send-as aliases, fetched once after authentication
+    pub send_as: Vec<models::SendAs>,
     pub sync_state: Arc<Mutex<SyncState>>,
     pub undo_stack: Vec<UndoableAction>,
-    pub status_message: Option<String>,
-    pub debug_logging: bool,
+    /// Messages saved locally that Gmail has not accepted yet
+    pub outbox: Vec<models::OutboxMessage>,
+    /// Outbox entries with a send in flight
+    pub sending: HashSet<i64>,
+    /// Gmail changes still being sent, waited for before exiting
+    pub pending: PendingWork,
+    /// Expiring messages about what just happened or went wrong
+    pub notifications: Notifications,
+    /// Recent log events, shown while the console is open
+    pub log: LogBuffer,
+    pub show_console: bool,
+    /// Address book, refreshed from the database after each sync
+    pub contacts: Vec<models::Contact>,
+    pub theme: Theme,
+    pub layout: LayoutState,
+    pub row_template: RowTemplate,
+    /// Where the panels were last drawn, for mouse hit-testing
+    pub panel_areas: PanelAreas,
+    /// Open while the command palette is showing
+    pub palette: Option<PaletteState>,
+    /// The cache has nothing past the last loaded conversation
+    pub end_of_cache: bool,
+    /// Set when scrolling reaches the end of the cache; the main loop then
+    /// fetches an older page from Gmail
+    pub want_older_page: bool,
+    /// Jump-to-date still paging back towards this timestamp (ms)
+    pub jump_target: Option<i64>,
+    /// A Gmail request was rate limited and will be retried at this time
+    pub retrying_until: Option<std::time::Instant>,
+    /// Count or partial key sequence typed so far, e.g. `5` or `g`
+    pub pending_keys: Option<String>,
+    /// Open while the `?` help overlay is showing
+    pub show_help: bool,
+    pub help_scroll: u16,
+    /// Set while the list shows search results instead of a label
+    pub search_query: Option<String>,
+    pub keybindings: Keybindings,
 }
 
 impl<'a> Default for UIState<'a> {
@@ -152,29 +500,132 @@ impl<'a> Default for UIState<'a> {
             mode: UIMode::Browsing,
             compose_state: None,
             auth_url: None,
-            remote_signature: None,
+            send_as: Vec::new(),
             sync_state: Arc::new(Mutex::new(SyncState::default())),
             undo_stack: Vec::new(),
-            status_message: None,
-            debug_logging: false,
+            outbox: Vec::new(),
+            sending: HashSet::new(),
+            pending: PendingWork::default(),
+            notifications: Notifications::default(),
+            log: LogBuffer::default(),
+            show_console: false,
+            theme: Theme::default(),
+            layout: LayoutState::default(),
+            row_template: RowTemplate::default(),
+            panel_areas: PanelAreas::default(),
+            contacts: Vec::new(),
+            palette: None,
+            end_of_cache: false,
+            retrying_until: None,
+            want_older_page: false,
+            jump_target: None,
+            pending_keys: None,
+            show_help: false,
+            help_scroll: 0,
+            search_query: None,
+            keybindings: Keybindings::default(),
         }
     }
 }
 
+impl UIState<'_> {
+    /// Show a notification and record it in the log, so it can still be
+    /// read in the console after it expires
+    pub fn notify(&mut self, level: Level, text: impl Into<String>) {
+        let text = text.into();
+        match level {
+            Level::Info => tracing::info!("{}", text),
+            Level::Warn => tracing::warn!("{}", text),
+            Level::Error => tracing::error!("{}", text),
+        }
+        self.notifications
+            .push(level, text, std::time::Instant::now());
+    }
+
+    /// The panel drawn at the given screen position
+    pub fn panel_at(&self, column: u16, row: u16) -> Option<FocusedPanel> {
+        let position = ratatui::layout::Position { x: column, y: row };
+        [
+            (self.panel_areas.labels, FocusedPanel::Labels),
+            (self.panel_areas.messages, FocusedPanel::Messages),
+            (self.panel_areas.details, FocusedPanel::Details),
+        ]
+        .into_iter()
+        .find(|(area, _)| area.contains(position))
+        .map(|(_, panel)| panel)
+    }
+
+    /// How many rows of `panel` fit on screen, for paging
+    pub fn page_size(&self, panel: FocusedPanel) -> usize {
+        let area = match panel {
+            FocusedPanel::Labels => self.panel_areas.labels,
+            FocusedPanel::Messages => self.panel_areas.messages,
+            FocusedPanel::Details => self.panel_areas.details,
+        };
+        let lines = area.height.saturating_sub(2) as usize;
+        let per_row = match panel {
+            FocusedPanel::Messages if self.row_template.height() > 1 => {
+                self.row_template.height() + 1
+            }
+            FocusedPanel::Messages => self.row_template.height(),
+            _ => 1,
+        };
+        (lines / per_row.max(1)).max(1)
+    }
+
+    /// The label drawn on screen row `row`, if any
+    pub fn label_index_at(&self, row: u16) -> Option<usize> {
+        let area = self.panel_areas.labels;
+        if row <= area.y || row + 1 >= area.bottom() {
+            return None;
+        }
+        let index = (row - area.y - 1) as usize;
+        (index < self.labels.len()).then_some(index)
+    }
+
+    /// The conversation drawn on screen row `row`, if any. Accounts for the
+    /// list's scroll offset, multi-line rows and separators.
+    pub fn message_index_at(&self, row: u16) -> Option<usize> {
+        let area = self.panel_areas.messages;
+        if row <= area.y || row + 1 >= area.bottom() {
+            return None;
+        }
+        let separated = self.row_template.height() > 1;
+        let item_count = if separated {
+            (self.messages.len() * 2).saturating_sub(1)
+        } else {
+            self.messages.len()
+        };
+
+        let mut top = area.y + 1;
+        for item in self.messages_list_state.offset()..item_count {
+            let is_separator = separated && item % 2 == 1;
+            let height = if is_separator {
+                1
+            } else {
+                self.row_template.height() as u16
+            };
+            if row < top + height {
+                return match (is_separator, separated) {
+                    (true, _) => None,
+                    (false, true) => Some(item / 2),
+                    (false, false) => Some(item),
+                };
+            }
+            top += height;
+        }
+        None
+    }
+}
+
 pub fn render(f: &mut Frame, state: &mut UIState<'_>) {
     if let UIMode::Authentication = state.mode {
         render_authentication(f, state);
         return;
     }
 
-    let chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage(10), // Folder structure
-            Constraint::Percentage(30), // List of mails
-            Constraint::Percentage(60), // Selected email details
-        ])
-        .split(f.area());
+    let areas = state.layout.panel_areas(f.area(), state.focused_panel);
+    state.panel_areas = areas;
 
     // Panel 1: Labels
     let items: Vec<ListItem> = state
@@ -183,11 +634,9 @@ pub fn render(f: &mut Frame, state: &mut UIState<'_>) {
         .enumerate()
         .map(|(i, l)| {
             let style = if i == state.selected_label_index {
-                Style::default()
-                    .fg(Color::Blue)
-                    .add_modifier(Modifier::BOLD)
+                state.theme.selection.add_modifier(Modifier::BOLD)
             } else {
-                Style::default()
+                state.theme.label_chip
             };
 
             ListItem::new(l.display_name.clone()).style(style)
@@ -198,92 +647,103 @@ pub fn render(f: &mut Frame, state: &mut UIState<'_>) {
         .borders(Borders::ALL)
         .title("Labels")
         .border_style(if state.focused_panel == FocusedPanel::Labels {
-            Style::default()
-                .fg(Color::Blue)
-                .add_modifier(Modifier::BOLD)
+            state.theme.border_focused
         } else {
-            Style::default().fg(Color::Gray)
+            state.theme.border
         });
 
     let labels_list = List::new(items)
         .block(labels_block)
         .highlight_style(Style::default().add_modifier(Modifier::BOLD));
-    f.render_widget(labels_list, chunks[0]);
+    f.render_widget(labels_list, areas.labels);
 
     // Panel 2: Message List
-    let list_width = chunks[1].width.saturating_sub(2) as usize; // Inset from sides
+    let list_width = areas.messages.width.saturating_sub(2) as usize; // Inset from sides
 
+    let current_label = state
+        .labels
+        .get(state.selected_label_index)
+        .map(|l| l.id.as_str());
+    let row_ctx = RowContext {
+        now: Local::now(),
+        labels: &state.labels,
+        current_label,
+        label_style: state.theme.label_chip,
+    };
+    // One column for the selection indicator
+    let row_width = list_width.saturating_sub(1);
     let msg_items: Vec<ListItem> = state
         .messages
         .iter()
         .enumerate()
         .map(|(i, m)| {
-            let sender = m.from_address.as_deref().unwrap_or("Unknown");
-            let subject = m.subject.as_deref().unwrap_or("(No Subject)");
-
-            let date = DateTime::from_timestamp_millis(m.internal_date)
-                .unwrap_or_default()
-                .with_timezone(&Local);
-            let time_str = date.format("%b %d %Y @ %-I:%M%p").to_string();
-
-            let mut style = if i == state.selected_message_index {
-                Style::default().fg(Color::Blue)
+            let mut style = if m.is_read {
+                state.theme.read
             } else {
-                Style::default()
+                state.theme.unread
             };
-
-            if !m.is_read {
-                style = style.add_modifier(Modifier::BOLD);
+            let is_selected = i == state.selected_message_index;
+            if is_selected {
+                style = style.patch(state.theme.selection);
             }
 
-            // Reply indicator emoji if the thread contains a sent message
-            let reply_indicator = if m.has_sent_reply { "↩ " } else { "" };
-
-            // Truncate to fit if necessary (crude)
-            let s_label = format!(" From: {}", sender);
-            let t_label = format!(" Time: {}", time_str);
-            let sub_label = format!(" {}Subj: {}", reply_indicator, subject);
-
-            let pad = |s: String, len: usize| {
-                let char_count = s.chars().count();
-                if char_count > len {
-                    let truncated: String = s.chars().take(len.saturating_sub(3)).collect();
-                    format!("{}...", truncated)
-                } else {
-                    format!("{:width$}", s, width = len)
-                }
-            };
-
-            let inner_len = list_width.saturating_sub(2);
-            let line1 = format!("{}", pad(s_label, inner_len));
-            let line2 = format!("{}", pad(t_label, inner_len));
-            let line3 = format!("{}", pad(sub_label, inner_len));
-
-            let is_selected = i == state.selected_message_index;
             let indicator = if is_selected { "█" } else { " " };
-            let item_text = format!(
-                "{}{}\n{}{}\n{}{}",
-                indicator, line1, indicator, line2, indicator, line3
-            );
-            ListItem::new(item_text).style(style)
+            let lines: Vec<Line> = state
+                .row_template
+                .render(m, &row_ctx, row_width)
+                .into_iter()
+                .map(|line| {
+                    let mut spans = vec![Span::raw(indicator)];
+                    spans.extend(line.spans);
+                    Line::from(spans)
+                })
+                .collect();
+            ListItem::new(lines).style(style)
         })
         .collect();
 
-    let messages_title = if let Some(ref status) = state.status_message {
-        format!("Conversations - {}", status)
-    } else {
-        "Conversations".to_string()
+    let list_name = match &state.search_query {
+        Some(query) => format!("Search: {}", query),
+        None => "Conversations".to_string(),
     };
+    let mut messages_title = list_name;
+    if let Some(keys) = &state.pending_keys {
+        messages_title.push_str(&format!(" [{}]", keys));
+    }
+    if !state.sending.is_empty() {
+        messages_title.push_str(" - Sending...");
+    } else if !state.outbox.is_empty() {
+        messages_title.push_str(&format!(" - {} unsent", state.outbox.len()));
+    }
+    if let Some(until) = state.retrying_until {
+        let secs = until
+            .saturating_duration_since(std::time::Instant::now())
+            .as_secs()
+            + 1;
+        messages_title.push_str(&format!(" - rate limited, retrying in {}s", secs));
+    }
+    if let Ok(sync) = state.sync_state.lock() {
+        if let Some(progress) = &sync.progress {
+            messages_title.push_str(&format!(
+                " - Syncing {} {}/{}",
+                progress.label, progress.fetched, progress.total
+            ));
+        }
+        if sync.failed_fetches > 0 {
+            messages_title.push_str(&format!(
+                " - {} message(s) failed to sync",
+                sync.failed_fetches
+            ));
+        }
+    }
 
     let messages_block = Block::default()
         .borders(Borders::ALL)
         .title(messages_title)
         .border_style(if state.focused_panel == FocusedPanel::Messages {
-            Style::default()
-                .fg(Color::Blue)
-                .add_modifier(Modifier::BOLD)
+            state.theme.border_focused
         } else {
-            Style::default().fg(Color::Gray)
+            state.theme.border
         });
 
     if state.messages.is_empty() {
@@ -315,48 +775,48 @@ pub fn render(f: &mut Frame, state: &mut UIState<'_>) {
         };
 
         let status_style = if is_synced {
-            Style::default().fg(Color::DarkGray)
+            state.theme.dim
         } else {
-            Style::default().fg(Color::Blue)
+            state.theme.status
         };
 
         let status_paragraph = Paragraph::new(status_text)
             .block(messages_block)
             .style(status_style)
             .wrap(ratatui::widgets::Wrap { trim: true });
-        f.render_widget(status_paragraph, chunks[1]);
+        f.render_widget(status_paragraph, areas.messages);
     } else {
-        // Insert separator items between conversations
+        // Multi-line rows get a separator after each conversation; one-line
+        // rows are easier to scan without
+        let separated = state.row_template.height() > 1;
         let separator_width = list_width.saturating_sub(2);
         let separator = "─".repeat(separator_width);
         let mut items_with_separators: Vec<ListItem> = Vec::new();
         for (i, item) in msg_items.into_iter().enumerate() {
             items_with_separators.push(item);
             // Add separator after each item except the last one
-            if i < state.messages.len().saturating_sub(1) {
-                items_with_separators.push(
-                    ListItem::new(separator.clone()).style(Style::default().fg(Color::DarkGray)),
-                );
+            if separated && i < state.messages.len().saturating_sub(1) {
+                items_with_separators.push(ListItem::new(separator.clone()).style(state.theme.dim));
             }
         }
 
         let list_widget = List::new(items_with_separators).block(messages_block);
-        // Adjust index to account for separators (each message is followed by a separator)
-        let display_index = state.selected_message_index * 2;
+        let display_index = if separated {
+            state.selected_message_index * 2
+        } else {
+            state.selected_message_index
+        };
         state.messages_list_state.select(Some(display_index));
-        f.render_stateful_widget(list_widget, chunks[1], &mut state.messages_list_state);
+        f.render_stateful_widget(list_widget, areas.messages, &mut state.messages_list_state);
     }
 
     // Panel 3: Thread Details
     // Debug logging for border corruption investigation
-    debug_log(
-        state.debug_logging,
-        &format!(
-            "[UI Render] threaded_msgs: {}, selected_idx: {}, panel: {:?}",
-            state.threaded_messages.len(),
-            state.selected_message_index,
-            state.focused_panel
-        ),
+    tracing::trace!(
+        "[UI Render] threaded_msgs: {}, selected_idx: {}, panel: {:?}",
+        state.threaded_messages.len(),
+        state.selected_message_index,
+        state.focused_panel
     );
     if let Some(first_msg) = state.threaded_messages.first() {
         let preview: String = first_msg
@@ -364,12 +824,10 @@ pub fn render(f: &mut Frame, state: &mut UIState<'_>) {
             .as_ref()
             .map(|s| s.chars().take(50).collect())
             .unwrap_or_else(|| "(no body)".to_string());
-        debug_log(
-            state.debug_logging,
-            &format!(
-                "[UI Render] First msg from: {:?}, body preview: {:?}",
-                first_msg.from_address, preview
-            ),
+        tracing::trace!(
+            "[UI Render] First msg from: {:?}, body preview: {:?}",
+            first_msg.from_address,
+            preview
         );
     }
 
@@ -377,53 +835,28 @@ pub fn render(f: &mut Frame, state: &mut UIState<'_>) {
         .borders(Borders::ALL)
         .title("Message Details")
         .border_style(if state.focused_panel == FocusedPanel::Details {
-            Style::default()
-                .fg(Color::Blue)
-                .add_modifier(Modifier::BOLD)
+            state.theme.border_focused
         } else {
-            Style::default().fg(Color::Gray)
+            state.theme.border
         });
 
-    let mut detail_content = String::new();
-    if state.threaded_messages.is_empty() {
-        detail_content = "No conversation selected".to_string();
+    let detail_content = if state.threaded_messages.is_empty() {
+        "No conversation selected".to_string()
     } else {
-        for msg in &state.threaded_messages {
-            let sender = msg.from_address.as_deref().unwrap_or("Unknown");
-            let date = DateTime::from_timestamp_millis(msg.internal_date)
-                .unwrap_or_default()
-                .with_timezone(&Local);
-            let time_str = date.format("%Y-%m-%d %H:%M").to_string();
-
-            detail_content.push_str(&format!(
-                "From: {}\nDate: {}\n\n{}\n",
-                sender,
-                time_str,
-                &clean_body(
-                    msg.body_plain
-                        .as_deref()
-                        .unwrap_or_else(|| msg.snippet.as_deref().unwrap_or(""))
-                )
-            ));
-            detail_content
-                .push_str("\n------------------------------------------------------------\n\n");
-        }
-    }
+        format_thread(&state.threaded_messages)
+    };
 
     // Clear the details area first to prevent rendering artifacts when scrolling fast
-    f.render_widget(Clear, chunks[2]);
+    f.render_widget(Clear, areas.details);
 
     // Debug: Log details panel dimensions and content stats
-    debug_log(
-        state.debug_logging,
-        &format!(
-            "[UI Render] Details panel - area: x={}, y={}, w={}, h={}, content_len={}",
-            chunks[2].x,
-            chunks[2].y,
-            chunks[2].width,
-            chunks[2].height,
-            detail_content.len()
-        ),
+    tracing::trace!(
+        "[UI Render] Details panel - area: x={}, y={}, w={}, h={}, content_len={}",
+        areas.details.x,
+        areas.details.y,
+        areas.details.width,
+        areas.details.height,
+        detail_content.len()
     );
     // Log any vertical bar characters in content that might be problematic
     let vertical_bars: Vec<(usize, char)> = detail_content
@@ -431,12 +864,9 @@ pub fn render(f: &mut Frame, state: &mut UIState<'_>) {
         .filter(|(_, c)| *c == '│' || *c == '|')
         .collect();
     if !vertical_bars.is_empty() {
-        debug_log(
-            state.debug_logging,
-            &format!(
-                "[UI Render] WARNING: Found {} vertical bar chars in content",
-                vertical_bars.len()
-            ),
+        tracing::trace!(
+            "[UI Render] WARNING: Found {} vertical bar chars in content",
+            vertical_bars.len()
         );
     }
 
@@ -444,157 +874,418 @@ pub fn render(f: &mut Frame, state: &mut UIState<'_>) {
         .block(details_block)
         .wrap(ratatui::widgets::Wrap { trim: true })
         .scroll((state.detail_scroll, 0));
-    f.render_widget(detail_paragraph, chunks[2]);
+    f.render_widget(detail_paragraph, areas.details);
+
+    if state.show_console {
+        render_console(f, state);
+    }
+    render_notifications(f, state);
+    if let Some(palette) = &state.palette {
+        render_palette(f, palette, &state.keybindings, &state.theme);
+    }
+    if state.show_help {
+        render_help(f, state);
+    }
 
     // Popup for composing
-    if let UIMode::Composing = state.mode {
-        if let Some(cs) = &mut state.compose_state {
-            let area = centered_rect(80, 80, f.area());
-            f.render_widget(Clear, area);
-
-            let mut constraints = vec![
-                Constraint::Length(3), // To
-            ];
-            if cs.show_cc_bcc {
-                constraints.push(Constraint::Length(3)); // Cc
-                constraints.push(Constraint::Length(3)); // Bcc
-            }
-            constraints.push(Constraint::Length(3)); // Subject
-            constraints.push(Constraint::Min(10)); // Body
+    if let UIMode::Composing = state.mode
+        && let Some(cs) = &mut state.compose_state
+    {
+        let area = centered_rect(80, 80, f.area());
+        f.render_widget(Clear, area);
+
+        let mut constraints = Vec::new();
+        if !cs.send_as.is_empty() {
+            constraints.push(Constraint::Length(3)); // From
+        }
+        constraints.push(Constraint::Length(3)); // To
+        if cs.show_cc_bcc {
+            constraints.push(Constraint::Length(3)); // Cc
+            constraints.push(Constraint::Length(3)); // Bcc
+        }
+        constraints.push(Constraint::Length(3)); // Subject
+        constraints.push(Constraint::Min(10)); // Body
+        if cs.error.is_some() {
+            constraints.push(Constraint::Length(1)); // Validation error
+        }
 
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints(constraints)
-                .split(area);
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(constraints)
+            .split(area);
 
-            let mut current_chunk = 0;
+        let mut current_chunk = 0;
 
-            // To field
-            let to_style = if cs.focused_field == ComposeField::To {
-                Style::default()
-                    .fg(Color::Cyan)
-                    .add_modifier(Modifier::BOLD)
+        // From field (send-as alias, cycled rather than typed)
+        if let Some(from) = cs.from() {
+            let title = if cs.send_as.len() > 1 {
+                format!(
+                    " From [{} to Change] ",
+                    commands::display_keys(&state.keybindings.cycle_from)
+                )
             } else {
-                Style::default().fg(Color::Gray)
+                " From ".to_string()
             };
-            cs.to.set_block(
+            let from_field = Paragraph::new(from.mailbox().to_display_string()).block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title(" To ")
-                    .border_style(to_style),
+                    .title(title)
+                    .border_style(state.theme.compose_border),
             );
-            f.render_widget(&cs.to, chunks[current_chunk]);
+            f.render_widget(from_field, chunks[current_chunk]);
             current_chunk += 1;
+        }
 
-            // Cc/Bcc fields (optional)
-            if cs.show_cc_bcc {
-                let cc_style = if cs.focused_field == ComposeField::Cc {
-                    Style::default()
-                        .fg(Color::Cyan)
-                        .add_modifier(Modifier::BOLD)
-                } else {
-                    Style::default().fg(Color::Gray)
-                };
-                cs.cc.set_block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .title(" Cc ")
-                        .border_style(cc_style),
-                );
-                f.render_widget(&cs.cc, chunks[current_chunk]);
-                current_chunk += 1;
-
-                let bcc_style = if cs.focused_field == ComposeField::Bcc {
-                    Style::default()
-                        .fg(Color::Cyan)
-                        .add_modifier(Modifier::BOLD)
-                } else {
-                    Style::default().fg(Color::Gray)
-                };
-                cs.bcc.set_block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .title(" Bcc ")
-                        .border_style(bcc_style),
-                );
-                f.render_widget(&cs.bcc, chunks[current_chunk]);
-                current_chunk += 1;
-            }
-
-            // Subject field
-            let sub_style = if cs.focused_field == ComposeField::Subject {
-                Style::default()
-                    .fg(Color::Cyan)
-                    .add_modifier(Modifier::BOLD)
+        // To field
+        let to_chunk_idx = current_chunk;
+        let to_style = if cs.focused_field == ComposeField::To {
+            state.theme.compose_focused
+        } else {
+            state.theme.compose_border
+        };
+        cs.to.set_block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" To ")
+                .border_style(to_style),
+        );
+        f.render_widget(&cs.to, chunks[current_chunk]);
+        current_chunk += 1;
+
+        // Cc/Bcc fields (optional)
+        let cc_chunk_idx = current_chunk;
+        let bcc_chunk_idx = current_chunk + 1;
+        if cs.show_cc_bcc {
+            let cc_style = if cs.focused_field == ComposeField::Cc {
+                state.theme.compose_focused
             } else {
-                Style::default().fg(Color::Gray)
+                state.theme.compose_border
             };
-            cs.subject.set_block(
+            cs.cc.set_block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title(" Subject ")
-                    .border_style(sub_style),
+                    .title(" Cc ")
+                    .border_style(cc_style),
             );
-            f.render_widget(&cs.subject, chunks[current_chunk]);
-            let sub_chunk_idx = current_chunk;
+            f.render_widget(&cs.cc, chunks[current_chunk]);
             current_chunk += 1;
 
-            // Body field
-            let body_title = if cs.show_cc_bcc {
-                " Body [Esc to Cancel, Ctrl-S to Send, Tab to Switch, Ctrl-B to Hide CC/BCC] "
-            } else {
-                " Body [Esc to Cancel, Ctrl-S to Send, Tab to Switch, Ctrl-B to Show CC/BCC] "
-            };
-            let body_style = if cs.focused_field == ComposeField::Body {
-                Style::default()
-                    .fg(Color::Cyan)
-                    .add_modifier(Modifier::BOLD)
+            let bcc_style = if cs.focused_field == ComposeField::Bcc {
+                state.theme.compose_focused
             } else {
-                Style::default().fg(Color::Gray)
+                state.theme.compose_border
             };
-            cs.body.set_block(
+            cs.bcc.set_block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title(body_title)
-                    .border_style(body_style),
+                    .title(" Bcc ")
+                    .border_style(bcc_style),
             );
-            f.render_widget(&cs.body, chunks[current_chunk]);
-            let body_chunk_idx = current_chunk;
-
-            // Set cursor position - TextArea handles this internally but we need to tell the frame
-            let (cursor_row, cursor_col) = match cs.focused_field {
-                ComposeField::To => {
-                    let (row, col) = cs.to.cursor();
-                    (chunks[0].y + 1 + row as u16, chunks[0].x + 1 + col as u16)
-                }
-                ComposeField::Cc => {
-                    let (row, col) = cs.cc.cursor();
-                    (chunks[1].y + 1 + row as u16, chunks[1].x + 1 + col as u16)
-                }
-                ComposeField::Bcc => {
-                    let (row, col) = cs.bcc.cursor();
-                    (chunks[2].y + 1 + row as u16, chunks[2].x + 1 + col as u16)
-                }
-                ComposeField::Subject => {
-                    let (row, col) = cs.subject.cursor();
-                    (
-                        chunks[sub_chunk_idx].y + 1 + row as u16,
-                        chunks[sub_chunk_idx].x + 1 + col as u16,
-                    )
-                }
-                ComposeField::Body => {
-                    let (row, col) = cs.body.cursor();
-                    (
-                        chunks[body_chunk_idx].y + 1 + row as u16,
-                        chunks[body_chunk_idx].x + 1 + col as u16,
-                    )
-                }
+            f.render_widget(&cs.bcc, chunks[current_chunk]);
+            current_chunk += 1;
+        }
+
+        // Subject field
+        let sub_style = if cs.focused_field == ComposeField::Subject {
+            state.theme.compose_focused
+        } else {
+            state.theme.compose_border
+        };
+        cs.subject.set_block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Subject ")
+                .border_style(sub_style),
+        );
+        f.render_widget(&cs.subject, chunks[current_chunk]);
+        let sub_chunk_idx = current_chunk;
+        current_chunk += 1;
+
+        // Body field
+        let mut body_title = format!(
+            " Body [Esc to Cancel, {} to Send, Tab to Switch, {} to {} CC/BCC] ",
+            commands::display_keys(&state.keybindings.send_message),
+            commands::display_keys(&state.keybindings.toggle_cc_bcc),
+            if cs.show_cc_bcc { "Hide" } else { "Show" },
+        );
+        if cs.markdown {
+            body_title.push_str("[Markdown] ");
+        }
+        let body_style = if cs.focused_field == ComposeField::Body {
+            state.theme.compose_focused
+        } else {
+            state.theme.compose_border
+        };
+        cs.body.set_block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(body_title)
+                .border_style(body_style),
+        );
+        let body_chunk_idx = current_chunk;
+        let body_area = if cs.show_preview {
+            let halves = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .split(chunks[body_chunk_idx]);
+            let preview = Paragraph::new(markdown::to_preview(&cs.get_body(), &state.theme))
+                .wrap(Wrap { trim: false })
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(" Preview ")
+                        .border_style(state.theme.compose_border),
+                );
+            f.render_widget(preview, halves[1]);
+            halves[0]
+        } else {
+            chunks[body_chunk_idx]
+        };
+        f.render_widget(&cs.body, body_area);
+
+        if let Some(error) = &cs.error {
+            let error_line = Paragraph::new(format!(" ⚠ {}", error)).style(state.theme.error);
+            f.render_widget(error_line, chunks[body_chunk_idx + 1]);
+        }
+
+        // Set cursor position - TextArea handles this internally but we need to tell the frame
+        let (cursor_row, cursor_col) = match cs.focused_field {
+            ComposeField::To => {
+                let (row, col) = cs.to.cursor();
+                (
+                    chunks[to_chunk_idx].y + 1 + row as u16,
+                    chunks[to_chunk_idx].x + 1 + col as u16,
+                )
+            }
+            ComposeField::Cc => {
+                let (row, col) = cs.cc.cursor();
+                (
+                    chunks[cc_chunk_idx].y + 1 + row as u16,
+                    chunks[cc_chunk_idx].x + 1 + col as u16,
+                )
+            }
+            ComposeField::Bcc => {
+                let (row, col) = cs.bcc.cursor();
+                (
+                    chunks[bcc_chunk_idx].y + 1 + row as u16,
+                    chunks[bcc_chunk_idx].x + 1 + col as u16,
+                )
+            }
+            ComposeField::Subject => {
+                let (row, col) = cs.subject.cursor();
+                (
+                    chunks[sub_chunk_idx].y + 1 + row as u16,
+                    chunks[sub_chunk_idx].x + 1 + col as u16,
+                )
+            }
+            ComposeField::Body => {
+                let (row, col) = cs.body.cursor();
+                (body_area.y + 1 + row as u16, body_area.x + 1 + col as u16)
+            }
+        };
+        f.set_cursor_position((cursor_col, cursor_row));
+
+        // Address book completions, just below the field being typed in
+        if !cs.completions.is_empty() {
+            let field_area = match cs.focused_field {
+                ComposeField::To => chunks[to_chunk_idx],
+                ComposeField::Cc => chunks[cc_chunk_idx],
+                ComposeField::Bcc => chunks[bcc_chunk_idx],
+                _ => body_area,
+            };
+            let height = (cs.completions.len() as u16 + 2)
+                .min(area.bottom().saturating_sub(field_area.bottom()));
+            let popup = Rect {
+                x: field_area.x + 1,
+                y: field_area.bottom(),
+                width: field_area.width.saturating_sub(2),
+                height,
+            };
+            let items: Vec<ListItem> = cs
+                .completions
+                .iter()
+                .map(|c| ListItem::new(c.mailbox().to_display_string()))
+                .collect();
+            let mut list_state = ListState::default();
+            list_state.select(Some(cs.completion_index));
+            let list = List::new(items)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(state.theme.compose_focused),
+                )
+                .highlight_style(state.theme.compose_focused.add_modifier(Modifier::REVERSED));
+            f.render_widget(Clear, popup);
+            f.render_stateful_widget(list, popup, &mut list_state);
+        }
+    }
+}
+
+fn render_help(f: &mut Frame, state: &UIState<'_>) {
+    let area = centered_rect(70, 80, f.area());
+    f.render_widget(Clear, area);
+
+    let sections = commands::help_sections(&state.keybindings);
+    let key_width = sections
+        .iter()
+        .flat_map(|(_, rows)| rows.iter().map(|(keys, _)| keys.len()))
+        .max()
+        .unwrap_or(0);
+    let mut lines = Vec::new();
+    for (heading, rows) in sections {
+        if !lines.is_empty() {
+            lines.push(Line::default());
+        }
+        lines.push(Line::styled(
+            heading,
+            state.theme.accent.add_modifier(Modifier::BOLD),
+        ));
+        for (keys, description) in rows {
+            lines.push(Line::from(vec![
+                Span::styled(
+                    format!("  {:width$}  ", keys, width = key_width),
+                    state.theme.unread,
+                ),
+                Span::raw(description),
+            ]));
+        }
+    }
+
+    let help = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title(format!(
+                    " Help [{}/{} to Scroll, any other key to Close] ",
+                    commands::display_keys(&state.keybindings.move_down),
+                    commands::display_keys(&state.keybindings.move_up),
+                ))
+                .borders(Borders::ALL)
+                .border_style(state.theme.compose_focused),
+        )
+        .scroll((state.help_scroll, 0));
+    f.render_widget(help, area);
+}
+
+/// Recent log events along the bottom of the screen, newest last
+fn render_console(f: &mut Frame, state: &UIState<'_>) {
+    let full = f.area();
+    let height = (full.height / 3).max(5).min(full.height);
+    let area = Rect::new(full.x, full.bottom() - height, full.width, height);
+    f.render_widget(Clear, area);
+
+    let lines: Vec<Line> = state
+        .log
+        .tail(height.saturating_sub(2) as usize)
+        .into_iter()
+        .map(|line| {
+            let style = match line.level {
+                tracing::Level::ERROR => state.theme.error,
+                tracing::Level::WARN => state.theme.accent,
+                _ => state.theme.read,
             };
-            f.set_cursor_position((cursor_col, cursor_row));
+            Line::from(vec![
+                Span::styled(format!("{} {:5} ", line.time, line.level), state.theme.dim),
+                Span::styled(line.text, style),
+            ])
+        })
+        .collect();
+    let console = Paragraph::new(lines).block(
+        Block::default()
+            .title(format!(
+                " Log [{} to Close] ",
+                commands::display_keys(&state.keybindings.toggle_console)
+            ))
+            .borders(Borders::ALL)
+            .border_style(state.theme.border),
+    );
+    f.render_widget(console, area);
+}
+
+/// Unexpired notifications stacked above the bottom-right corner, newest
+/// lowest
+fn render_notifications(f: &mut Frame, state: &UIState<'_>) {
+    let full = f.area();
+    let width = (full.width / 2).clamp(20.min(full.width), 60.min(full.width));
+    let mut bottom = full.bottom();
+    let now = std::time::Instant::now();
+    let visible: Vec<_> = state.notifications.visible(now).collect();
+    for notification in visible.into_iter().rev() {
+        let inner_width = width.saturating_sub(2).max(1) as usize;
+        let text_width = unicode_width::UnicodeWidthStr::width(notification.text.as_str());
+        let height = (text_width.div_ceil(inner_width).clamp(1, 3) + 2) as u16;
+        if bottom < full.y + height {
+            break;
         }
+        bottom -= height;
+        let area = Rect::new(full.right() - width, bottom, width, height);
+        let style = match notification.level {
+            Level::Info => state.theme.status,
+            Level::Warn => state.theme.accent,
+            Level::Error => state.theme.error,
+        };
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(notification.text.as_str())
+                .style(style)
+                .wrap(Wrap { trim: true })
+                .block(Block::default().borders(Borders::ALL).border_style(style)),
+            area,
+        );
     }
 }
 
+fn render_palette(f: &mut Frame, palette: &PaletteState, keybindings: &Keybindings, theme: &Theme) {
+    let area = centered_rect(60, 50, f.area());
+    f.render_widget(Clear, area);
+
+    let block = Block::default()
+        .title(" Command Palette [Esc to Close] ")
+        .borders(Borders::ALL)
+        .border_style(theme.compose_focused);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(2), Constraint::Min(0)])
+        .split(inner);
+
+    let prompt = match &palette.error {
+        Some(error) => Line::from(vec![
+            Span::raw(format!(":{} ", palette.input)),
+            Span::styled(error.clone(), theme.error),
+        ]),
+        None => Line::from(format!(":{}", palette.input)),
+    };
+    f.render_widget(Paragraph::new(prompt), chunks[0]);
+    f.set_cursor_position((
+        chunks[0].x + 1 + unicode_width::UnicodeWidthStr::width(palette.input.as_str()) as u16,
+        chunks[0].y,
+    ));
+
+    let usage_width = commands::COMMANDS
+        .iter()
+        .map(|c| c.usage.len())
+        .max()
+        .unwrap_or(0);
+    let items: Vec<ListItem> = palette
+        .matches()
+        .into_iter()
+        .map(|spec| {
+            let keys = commands::display_keys((spec.keys)(keybindings));
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{:width$}  ", spec.usage, width = usage_width)),
+                Span::raw(spec.description),
+                Span::styled(format!("  {}", keys), theme.dim),
+            ]))
+        })
+        .collect();
+    let mut list_state = ListState::default();
+    list_state.select(Some(palette.selected));
+    let list = List::new(items).highlight_style(theme.selection.add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(list, chunks[1], &mut list_state);
+}
+
 fn render_authentication(f: &mut Frame, state: &mut UIState<'_>) {
     let area = centered_rect(60, 40, f.area());
     f.render_widget(Clear, area);
@@ -602,7 +1293,7 @@ fn render_authentication(f: &mut Frame, state: &mut UIState<'_>) {
     let block = Block::default()
         .title(" Authentication Required ")
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Cyan));
+        .border_style(state.theme.compose_focused);
 
     let inner = block.inner(area);
     f.render_widget(block, area);
@@ -624,22 +1315,55 @@ fn render_authentication(f: &mut Frame, state: &mut UIState<'_>) {
 
     if let Some(url) = &state.auth_url {
         let url_p = Paragraph::new(url.as_str())
-            .style(
-                Style::default()
-                    .fg(Color::Blue)
-                    .add_modifier(Modifier::UNDERLINED),
-            )
+            .style(state.theme.accent.add_modifier(Modifier::UNDERLINED))
             .block(Block::default().borders(Borders::ALL).title(" URL "))
             .wrap(ratatui::widgets::Wrap { trim: false }); // Wrap the URL!
         f.render_widget(url_p, chunks[1]);
     }
 
     let footer = Paragraph::new("Your default browser should have opened automatically. If not, please copy the URL above (Tip: Hold Shift to select in most terminals).\n\nThe application will proceed automatically once complete.")
-        .style(Style::default().fg(Color::Gray))
+        .style(state.theme.dim)
         .wrap(ratatui::widgets::Wrap { trim: true });
     f.render_widget(footer, chunks[2]);
 }
 
+fn new_textarea<'a, I>(lines: I) -> TextArea<'a>
+where
+    I: IntoIterator,
+    I::Item: Into<String>,
+{
+    let mut textarea = TextArea::from(lines);
+    // Disable cursor line highlighting for cleaner look
+    textarea.set_cursor_line_style(Style::default());
+    textarea
+}
+
+/// Render a thread as plain text, one message after another, for the Details
+/// panel and the external pager
+pub fn format_thread(messages: &[models::Message]) -> String {
+    let mut content = String::new();
+    for msg in messages {
+        let sender = msg.from_address.as_deref().unwrap_or("Unknown");
+        let date = DateTime::from_timestamp_millis(msg.internal_date)
+            .unwrap_or_default()
+            .with_timezone(&Local);
+        let time_str = date.format("%Y-%m-%d %H:%M").to_string();
+
+        content.push_str(&format!(
+            "From: {}\nDate: {}\n\n{}\n",
+            sender,
+            time_str,
+            &clean_body(
+                msg.body_plain
+                    .as_deref()
+                    .unwrap_or_else(|| msg.snippet.as_deref().unwrap_or(""))
+            )
+        ));
+        content.push_str("\n------------------------------------------------------------\n\n");
+    }
+    content
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
@@ -707,6 +1431,47 @@ fn clean_body(body: &str) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_message_index_at_skips_separators() {
+        let mut state = UIState {
+            messages: vec![models::Message::default(); 3],
+            panel_areas: PanelAreas {
+                messages: Rect::new(0, 0, 40, 20),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // Three-line rows: row 1-3 is the first conversation, 4 a separator
+        assert_eq!(state.message_index_at(0), None);
+        assert_eq!(state.message_index_at(3), Some(0));
+        assert_eq!(state.message_index_at(4), None);
+        assert_eq!(state.message_index_at(5), Some(1));
+        assert_eq!(state.message_index_at(13), None);
+
+        state.row_template = RowTemplate::parse("{subject}", true).unwrap();
+        *state.messages_list_state.offset_mut() = 1;
+        assert_eq!(state.message_index_at(1), Some(1));
+        assert_eq!(state.message_index_at(3), None);
+    }
+
+    #[test]
+    fn test_page_size_counts_whole_rows() {
+        let mut state = UIState {
+            panel_areas: PanelAreas {
+                messages: Rect::new(0, 0, 40, 22),
+                details: Rect::new(40, 0, 40, 22),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // 20 inner lines of three-line rows plus separators
+        assert_eq!(state.page_size(FocusedPanel::Messages), 5);
+        assert_eq!(state.page_size(FocusedPanel::Details), 20);
+        state.row_template = RowTemplate::parse("{subject}", true).unwrap();
+        assert_eq!(state.page_size(FocusedPanel::Messages), 20);
+        assert_eq!(state.page_size(FocusedPanel::Labels), 1);
+    }
+
     #[test]
     fn test_clean_body_removes_extra_newlines() {
         let input = "Line 1\n\n\nLine 2\n\n\n\nLine 3";
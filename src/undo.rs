@@ -25,4 +25,23 @@ impl UndoableAction {
             UndoableAction::Archive { .. } => "archive",
         }
     }
+
+    /// The messages of the affected thread
+    pub fn messages(&self) -> &[Message] {
+        match self {
+            UndoableAction::Delete { messages, .. } | UndoableAction::Archive { messages, .. } => {
+                messages
+            }
+        }
+    }
+
+    /// Whether `other` records the same change to the same messages
+    pub fn same_as(&self, other: &UndoableAction) -> bool {
+        self.description() == other.description()
+            && self
+                .messages()
+                .iter()
+                .map(|m| &m.id)
+                .eq(other.messages().iter().map(|m| &m.id))
+    }
 }